@@ -9,6 +9,21 @@ pub enum Command<'a> {
 const GET: &[u8] = b"GET";
 const SET: &[u8] = b"SET";
 const DELETE: &[u8] = b"DELETE";
+
+impl Command<'_> {
+    /// Names of every command, as sent on the wire
+    pub const NAMES: [&'static str; 3] = ["GET", "SET", "DELETE"];
+
+    /// Name of the command, as sent on the wire
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Get { .. } => "GET",
+            Command::Set { .. } => "SET",
+            Command::Delete { .. } => "DELETE",
+        }
+    }
+}
+
 impl<'a> From<Command<'a>> for Vec<u8> {
     fn from(value: Command) -> Self {
         match value {
@@ -126,5 +141,21 @@ mod tests {
             let cmd = Command::Delete { key: b"test" };
             assert_eq!(Vec::<u8>::from(cmd), b"DELETE test".to_vec());
         }
+
+        #[test]
+        fn test_command_names_match_the_wire_format() {
+            for cmd in [
+                Command::Get { key: b"test" },
+                Command::Set {
+                    key: b"test",
+                    value: b"value",
+                },
+                Command::Delete { key: b"test" },
+            ] {
+                let name = cmd.name();
+                assert!(Command::NAMES.contains(&name));
+                assert!(Vec::<u8>::from(cmd).starts_with(name.as_bytes()));
+            }
+        }
     }
 }
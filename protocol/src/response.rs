@@ -1,3 +1,5 @@
+use std::io::Write;
+
 #[derive(Debug)]
 pub enum Response {
     Ok(Option<Vec<u8>>),
@@ -5,6 +7,21 @@ pub enum Response {
     Success,
 }
 
+impl Response {
+    /// Writes the same bytes as `Response::Ok` straight to `writer`, so a borrowed value
+    /// does not have to be copied into a response first
+    pub fn write_ok<W: Write>(writer: &mut W, value: Option<&[u8]>) -> std::io::Result<()> {
+        match value {
+            Some(value) => {
+                writer.write_all(b"OK: ")?;
+                writer.write_all(value)?;
+                writer.write_all(b"\n")
+            }
+            None => writer.write_all(b"OK:\n"),
+        }
+    }
+}
+
 impl From<Response> for Vec<u8> {
     fn from(value: Response) -> Self {
         match value {
@@ -47,3 +64,17 @@ impl<'a> TryFrom<&'a [u8]> for Response {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_ok_matches_serialized_response() {
+        for value in [Some(b"value".to_vec()), None] {
+            let mut written = Vec::new();
+            Response::write_ok(&mut written, value.as_deref()).unwrap();
+            assert_eq!(written, Vec::<u8>::from(Response::Ok(value)));
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Prints the contents of a segment file and its side files without starting the server
+//!
+//! Usage: sst-dump [--hex] [--get <key>] <segment file>
+
+use std::process::ExitCode;
+
+use server::database::{RecordValue, SegmentInspector, SegmentRecord};
+
+const HEX_FLAG: &str = "--hex";
+const GET_FLAG: &str = "--get";
+const USAGE: &str = "Usage: sst-dump [--hex] [--get <key>] <segment file>";
+
+struct Arguments {
+    path: String,
+    hex: bool,
+    key: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let Some(arguments) = parse_arguments(std::env::args().skip(1)) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    match dump(&arguments) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("sst-dump: {}: {}", arguments.path, error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_arguments(mut args: impl Iterator<Item = String>) -> Option<Arguments> {
+    let mut path = None;
+    let mut hex = false;
+    let mut key = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            HEX_FLAG => hex = true,
+            GET_FLAG => key = Some(args.next()?),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return None,
+        }
+    }
+
+    Some(Arguments {
+        path: path?,
+        hex,
+        key,
+    })
+}
+
+fn dump(arguments: &Arguments) -> std::io::Result<()> {
+    let inspector = SegmentInspector::open(&arguments.path)?;
+    let format = |bytes: &[u8]| format_bytes(bytes, arguments.hex);
+
+    if let Some(key) = &arguments.key {
+        match inspector.get(key.as_bytes())? {
+            Some(record) => println!("{}", format_record(&record, &format)),
+            None => println!("{} not found", format(key.as_bytes())),
+        }
+        return Ok(());
+    }
+
+    println!("Files:");
+    for (path, size) in inspector.file_sizes()? {
+        println!("  {} {} bytes", path.display(), size);
+    }
+
+    println!("Properties:");
+    match inspector.properties()? {
+        Some(properties) => {
+            println!(
+                "  key range: {} .. {}",
+                format(&properties.min_key),
+                format(&properties.max_key)
+            );
+            println!(
+                "  entries: {} ({} tombstones)",
+                properties.num_entries, properties.num_tombstones
+            );
+            println!(
+                "  raw key bytes: {}, raw value bytes: {}",
+                properties.raw_key_bytes, properties.raw_value_bytes
+            );
+            println!(
+                "  sequences: {} .. {}",
+                properties.min_sequence, properties.max_sequence
+            );
+            println!("  created at: {}", properties.created_at);
+        }
+        None => println!("  none"),
+    }
+
+    println!("Filter:");
+    match inspector.filter() {
+        Ok(Some(filter)) => {
+            println!("  kind: {}, {} bytes", filter.kind, filter.file_size);
+            if let Some(prefix_extractor) = filter.prefix_extractor {
+                println!("  prefix extractor: {}", prefix_extractor);
+            }
+            if let (Some(num_bits), Some(num_hashes), Some(fill_ratio)) =
+                (filter.num_bits, filter.num_hashes, filter.fill_ratio)
+            {
+                println!(
+                    "  bits: {}, hash functions: {}, fill ratio: {:.4}",
+                    num_bits, num_hashes, fill_ratio
+                );
+            }
+        }
+        Ok(None) => println!("  none"),
+        Err(error) => println!("  unreadable: {}", error),
+    }
+
+    println!("Index:");
+    match inspector.index_entries() {
+        Ok(Some(entries)) => {
+            for entry in entries {
+                println!("  {} @ {}", format(entry.key()), entry.offset());
+            }
+        }
+        Ok(None) => println!("  none"),
+        Err(error) => println!("  unreadable: {}", error),
+    }
+
+    println!("Records:");
+    let records = inspector.records()?;
+    for record in &records {
+        println!("  {}", format_record(record, &format));
+    }
+    println!("  {} records", records.len());
+    Ok(())
+}
+
+fn format_record(record: &SegmentRecord, format: &impl Fn(&[u8]) -> String) -> String {
+    let value = match &record.value {
+        RecordValue::Inline(value) => format(value),
+        RecordValue::Pointer {
+            file_number,
+            offset,
+            len,
+        } => format!(
+            "-> value_{}.vlog offset {} len {}",
+            file_number, offset, len
+        ),
+        RecordValue::Tombstone => "<deleted>".to_string(),
+    };
+    format!("@{} {} {}", record.offset, format(&record.key), value)
+}
+
+/// Hex digits, or the bytes with everything but printable ASCII escaped
+fn format_bytes(bytes: &[u8], hex: bool) -> String {
+    if hex {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    } else {
+        bytes.escape_ascii().to_string()
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    ffi::OsString,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::database::env::{Env, WritableFile};
+
+pub const TEMP_FILE_EXTENSION: &str = "tmp";
+
+/// Writes a file under a temporary name, syncs it and only then renames it into place,
+/// so readers never observe a partially written file at `path`
+pub fn write_atomically<F>(env: &dyn Env, path: &Path, write: F) -> std::io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<Box<dyn WritableFile>>) -> std::io::Result<()>,
+{
+    let temp_path = temp_path(path);
+    let mut writer = BufWriter::new(env.create_writable(&temp_path)?);
+
+    let result = write(&mut writer)
+        .and_then(|_| writer.flush())
+        .and_then(|_| writer.get_mut().sync());
+    drop(writer);
+    if let Err(error) = result {
+        let _ = env.remove_file(&temp_path);
+        return Err(error);
+    }
+
+    env.rename(&temp_path, path)?;
+    match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => env.sync_dir(directory),
+        _ => Ok(()),
+    }
+}
+
+/// Removes temporary files left behind by writes that were interrupted by a crash
+pub fn remove_temporary_files(env: &dyn Env, directory: &Path) -> std::io::Result<()> {
+    for path in env.list_files(directory)? {
+        let is_temporary = path
+            .extension()
+            .map(|ext| ext == TEMP_FILE_EXTENSION)
+            .unwrap_or(false);
+
+        if is_temporary {
+            tracing::warn!("Removing orphaned temporary file {}", path.display());
+            env.remove_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(TEMP_FILE_EXTENSION);
+    path.with_file_name(file_name)
+}
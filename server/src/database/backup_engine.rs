@@ -0,0 +1,443 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::database::{
+    Database,
+    atomic_file::write_atomically,
+    bloom_filter_registry::BLOOM_FILTER_FILE_EXTENSION,
+    env::{DiskEnv, Env},
+    hash::hash64,
+    index_file::INDEX_FILE_EXTENSION,
+    manifest::MANIFEST_FILE_NAME,
+    segment_file::SEGMENT_FILE_EXTENSION,
+    segment_properties::PROPERTIES_FILE_EXTENSION,
+    value_log::VALUE_LOG_FILE_EXTENSION,
+    verification::{FileReport, VerificationReport},
+};
+
+/// Files shared between backups, named after the original file and its checksum
+const SHARED_DIRECTORY: &str = "shared";
+/// One directory per backup holding the files that change after they are written, i.e.
+/// the WAL files and the manifest
+const PRIVATE_DIRECTORY: &str = "private";
+/// One metadata file per backup, named after its ID
+const META_DIRECTORY: &str = "meta";
+/// Files that never change once written, which backups store only once
+const SHARED_FILE_EXTENSIONS: [&str; 5] = [
+    SEGMENT_FILE_EXTENSION,
+    INDEX_FILE_EXTENSION,
+    BLOOM_FILTER_FILE_EXTENSION,
+    PROPERTIES_FILE_EXTENSION,
+    VALUE_LOG_FILE_EXTENSION,
+];
+
+const TIMESTAMP: &str = "timestamp";
+const FILE: &str = "file";
+
+/// Summary of a stored backup
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupInfo {
+    pub id: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Bytes of every file the backup restores, including ones shared with other backups
+    pub size: u64,
+    pub num_files: usize,
+}
+
+/// A file of a backup, under the name it is restored as
+#[derive(Clone, Debug, PartialEq)]
+struct BackupFile {
+    name: String,
+    size: u64,
+    checksum: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct BackupMeta {
+    timestamp: u64,
+    files: Vec<BackupFile>,
+}
+
+/// Incremental backups of a database into a backup directory. Segment and value log files
+/// a previous backup already holds are not stored again, and the rest are hard linked
+/// where possible and copied otherwise
+pub struct BackupEngine {
+    directory: PathBuf,
+    backups: BTreeMap<u64, BackupMeta>,
+    env: Arc<dyn Env>,
+}
+
+impl BackupEngine {
+    pub fn open<P: AsRef<Path>>(directory: P) -> std::io::Result<Self> {
+        Self::open_with_env(Arc::new(DiskEnv::new()), directory)
+    }
+
+    /// Opens the backup directory through `env`, which must also be the env of the
+    /// databases backed up
+    pub fn open_with_env<P: AsRef<Path>>(env: Arc<dyn Env>, directory: P) -> std::io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        for subdirectory in [SHARED_DIRECTORY, PRIVATE_DIRECTORY, META_DIRECTORY] {
+            env.create_dir_all(&directory.join(subdirectory))?;
+        }
+
+        let mut backups = BTreeMap::new();
+        for path in env.list_files(&directory.join(META_DIRECTORY))? {
+            let Some(id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            let meta = BackupMeta::try_from(env.read(&path)?.as_slice())?;
+            backups.insert(id, meta);
+        }
+
+        Ok(Self {
+            directory,
+            backups,
+            env,
+        })
+    }
+
+    /// Backs the database up and returns the new backup's ID
+    pub fn create_backup<P: AsRef<Path> + Clone>(
+        &mut self,
+        database: &mut Database<P>,
+    ) -> std::io::Result<u64> {
+        let id = self.backups.keys().last().map_or(1, |id| id + 1);
+        let private_directory = self.private_directory(id);
+        // Left behind by an earlier attempt that failed before its metadata was written
+        self.remove_private_directory(id)?;
+        self.env.create_dir_all(&private_directory)?;
+
+        // Immutable files are checksummed where they are, and only the ones no earlier
+        // backup holds are linked or copied into the shared directory
+        let mut files = Vec::new();
+        let mut new_shared_files = 0;
+        for path in database.file_directory.immutable_files()? {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let data = match self.env.read(&path) {
+                Ok(data) => data,
+                // Side files are optional
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            };
+            let file = BackupFile {
+                name: name.to_string(),
+                size: data.len() as u64,
+                checksum: hash64(&data),
+            };
+
+            let shared_path = self.shared_path(&file);
+            match self.env.file_size(&shared_path) {
+                Ok(_) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                    database.file_directory.link_or_copy(&path, &shared_path)?;
+                    new_shared_files += 1;
+                }
+                Err(error) => return Err(error),
+            }
+            files.push(file);
+        }
+
+        // The WAL files and the manifest change, so every backup keeps its own copy
+        database
+            .file_directory
+            .checkpoint_mutable_files(&private_directory)?;
+        for path in self.env.list_files(&private_directory)? {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let data = self.env.read(&path)?;
+            files.push(BackupFile {
+                name: name.to_string(),
+                size: data.len() as u64,
+                checksum: hash64(&data),
+            });
+        }
+        self.env.sync_dir(&self.directory.join(SHARED_DIRECTORY))?;
+        self.env.sync_dir(&private_directory)?;
+
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        let meta = BackupMeta {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            files,
+        };
+        write_atomically(self.env.as_ref(), &self.meta_path(id), |file| {
+            file.write_all(&Vec::<u8>::from(&meta))
+        })?;
+        tracing::info!(
+            "Created backup {} with {} files, {} of them new",
+            id,
+            meta.files.len(),
+            new_shared_files
+        );
+
+        self.backups.insert(id, meta);
+        Ok(id)
+    }
+
+    /// Every stored backup, oldest first
+    pub fn backup_infos(&self) -> Vec<BackupInfo> {
+        self.backups
+            .iter()
+            .map(|(id, meta)| BackupInfo {
+                id: *id,
+                timestamp: meta.timestamp,
+                size: meta.files.iter().map(|file| file.size).sum(),
+                num_files: meta.files.len(),
+            })
+            .collect()
+    }
+
+    /// Checks that every file of the backup is present with the size and checksum it was
+    /// backed up with
+    pub fn verify_backup(&self, id: u64) -> std::io::Result<VerificationReport> {
+        let meta = self.meta(id)?;
+        let mut report = VerificationReport::default();
+        for file in &meta.files {
+            let path = self.stored_path(id, file);
+            let mut file_report = FileReport::new(path.clone());
+            match self.env.read(&path) {
+                Ok(data) if data.len() as u64 != file.size => file_report.problems.push(format!(
+                    "holds {} bytes instead of {}",
+                    data.len(),
+                    file.size
+                )),
+                Ok(data) if hash64(&data) != file.checksum => {
+                    file_report.problems.push("checksum mismatch".to_string())
+                }
+                Ok(_) => {}
+                Err(error) => file_report
+                    .problems
+                    .push(format!("is unreadable: {}", error)),
+            }
+            report.files.push(file_report);
+        }
+        Ok(report)
+    }
+
+    /// Copies the backup into `target_directory`, which must not hold any files, leaving a
+    /// database directory that can be opened. The manifest is restored last
+    pub fn restore<P: AsRef<Path>>(&self, id: u64, target_directory: P) -> std::io::Result<()> {
+        let meta = self.meta(id)?;
+        let target_directory = target_directory.as_ref();
+        if self
+            .env
+            .list_files(target_directory)
+            .is_ok_and(|files| !files.is_empty())
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "Restore directory {} is not empty",
+                    target_directory.display()
+                ),
+            ));
+        }
+        self.env.create_dir_all(target_directory)?;
+
+        let (manifests, files): (Vec<_>, Vec<_>) = meta
+            .files
+            .iter()
+            .partition(|file| file.name == MANIFEST_FILE_NAME);
+        for file in files.into_iter().chain(manifests) {
+            let data = self.env.read(&self.stored_path(id, file))?;
+            if hash64(&data) != file.checksum {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Backup {} file {} is corrupted", id, file.name),
+                ));
+            }
+            write_atomically(
+                self.env.as_ref(),
+                &target_directory.join(&file.name),
+                |writer| writer.write_all(&data),
+            )?;
+        }
+        tracing::info!("Restored backup {} into {}", id, target_directory.display());
+        Ok(())
+    }
+
+    /// Deletes the backup along with the shared files no other backup needs
+    pub fn delete_backup(&mut self, id: u64) -> std::io::Result<()> {
+        self.meta(id)?;
+        // Without its metadata the backup is gone, even if deleting its files fails
+        self.env.remove_file(&self.meta_path(id))?;
+        self.backups.remove(&id);
+
+        self.remove_private_directory(id)?;
+        self.remove_unreferenced_shared_files()
+    }
+
+    /// Deletes all but the newest `num_backups_to_keep` backups
+    pub fn purge_old_backups(&mut self, num_backups_to_keep: usize) -> std::io::Result<()> {
+        let excess = self.backups.len().saturating_sub(num_backups_to_keep);
+        let old: Vec<u64> = self.backups.keys().take(excess).copied().collect();
+        for id in old {
+            tracing::info!("Purging backup {}", id);
+            self.delete_backup(id)?;
+        }
+        Ok(())
+    }
+
+    fn remove_unreferenced_shared_files(&self) -> std::io::Result<()> {
+        let referenced: HashSet<PathBuf> = self
+            .backups
+            .values()
+            .flat_map(|meta| &meta.files)
+            .filter(|file| is_shared(&file.name))
+            .map(|file| self.shared_path(file))
+            .collect();
+
+        for path in self
+            .env
+            .list_files(&self.directory.join(SHARED_DIRECTORY))?
+        {
+            if !referenced.contains(&path) {
+                self.env.remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_private_directory(&self, id: u64) -> std::io::Result<()> {
+        let private_directory = self.private_directory(id);
+        let Ok(files) = self.env.list_files(&private_directory) else {
+            return Ok(());
+        };
+        for path in files {
+            self.env.remove_file(&path)?;
+        }
+        match self.env.remove_dir(&private_directory) {
+            Err(error) if error.kind() != std::io::ErrorKind::Unsupported => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    fn meta(&self, id: u64) -> std::io::Result<&BackupMeta> {
+        self.backups.get(&id).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No backup with ID {}", id),
+            )
+        })
+    }
+
+    fn stored_path(&self, id: u64, file: &BackupFile) -> PathBuf {
+        if is_shared(&file.name) {
+            self.shared_path(file)
+        } else {
+            self.private_directory(id).join(&file.name)
+        }
+    }
+
+    /// Path of a shared file, e.g. `shared/segment_3_1234.sst`. The checksum keeps apart
+    /// files that share a name, such as segments of different databases
+    fn shared_path(&self, file: &BackupFile) -> PathBuf {
+        let path = Path::new(&file.name);
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("");
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        self.directory
+            .join(SHARED_DIRECTORY)
+            .join(format!("{}_{}.{}", stem, file.checksum, extension))
+    }
+
+    fn private_directory(&self, id: u64) -> PathBuf {
+        self.directory.join(PRIVATE_DIRECTORY).join(id.to_string())
+    }
+
+    fn meta_path(&self, id: u64) -> PathBuf {
+        self.directory.join(META_DIRECTORY).join(id.to_string())
+    }
+}
+
+fn is_shared(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .is_some_and(|ext| SHARED_FILE_EXTENSIONS.iter().any(|shared| ext == *shared))
+}
+
+impl From<&BackupMeta> for Vec<u8> {
+    fn from(value: &BackupMeta) -> Self {
+        let mut data = format!("{} {}\n", TIMESTAMP, value.timestamp);
+        for file in &value.files {
+            data.push_str(&format!(
+                "{} {} {} {}\n",
+                FILE, file.name, file.size, file.checksum
+            ));
+        }
+        data.into_bytes()
+    }
+}
+
+impl TryFrom<&[u8]> for BackupMeta {
+    type Error = std::io::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid backup");
+        let text = std::str::from_utf8(value).map_err(|_| invalid())?;
+
+        let mut timestamp = None;
+        let mut files = Vec::new();
+        for line in text.lines() {
+            match line.split(' ').collect::<Vec<_>>().as_slice() {
+                [TIMESTAMP, value] => timestamp = Some(value.parse().map_err(|_| invalid())?),
+                [FILE, name, size, checksum] => files.push(BackupFile {
+                    name: name.to_string(),
+                    size: size.parse().map_err(|_| invalid())?,
+                    checksum: checksum.parse().map_err(|_| invalid())?,
+                }),
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(Self {
+            timestamp: timestamp.ok_or_else(invalid)?,
+            files,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_meta_round_trip() {
+        let meta = BackupMeta {
+            timestamp: 1_700_000_000,
+            files: vec![
+                BackupFile {
+                    name: "MANIFEST".to_string(),
+                    size: 42,
+                    checksum: 7,
+                },
+                BackupFile {
+                    name: "segment_3.sst".to_string(),
+                    size: 4096,
+                    checksum: u64::MAX,
+                },
+            ],
+        };
+
+        let data = Vec::<u8>::from(&meta);
+        assert_eq!(BackupMeta::try_from(data.as_slice()).unwrap(), meta);
+        assert!(BackupMeta::try_from(b"file x 1".as_slice()).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::database::lru_cache::LruCache;
+
+pub const DEFAULT_BLOCK_CACHE_CAPACITY: usize = 8 * 1024 * 1024;
+const MAX_NUM_SHARDS: usize = 16;
+/// Small caches use fewer shards so a single block still fits in a shard
+const MIN_SHARD_CAPACITY: usize = 512 * 1024;
+
+/// Identifies a block by the cache id of the database it was read by, the number of the
+/// segment it belongs to and its offset in that segment
+pub type BlockKey = (u64, u64, u64);
+
+static NEXT_CACHE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Capacity bounded LRU cache of segment blocks shared by every reader of a database.
+/// Entries are spread over independently locked shards so concurrent readers rarely contend
+#[derive(Debug)]
+pub struct BlockCache {
+    shards: Vec<Mutex<LruCache<BlockKey, Arc<Vec<u8>>>>>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BlockCache {
+    /// Create a cache holding at most `capacity` bytes of block data
+    pub fn new(capacity: usize) -> Self {
+        let num_shards = (capacity / MIN_SHARD_CAPACITY).clamp(1, MAX_NUM_SHARDS);
+        let shard_capacity = capacity / num_shards;
+        Self {
+            shards: (0..num_shards)
+                .map(|_| Mutex::new(LruCache::new(shard_capacity)))
+                .collect(),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Look up a block, marking it as the most recently used one
+    pub fn get(&self, key: BlockKey) -> Option<Arc<Vec<u8>>> {
+        let block = self
+            .shard(key)
+            .lock()
+            .ok()
+            .and_then(|mut shard| shard.get(&key));
+
+        match block {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        block
+    }
+
+    /// Add a block, evicting the least recently used blocks of its shard to make room.
+    /// Blocks bigger than a whole shard are not cached
+    pub fn insert(&self, key: BlockKey, block: Arc<Vec<u8>>) {
+        if let Ok(mut shard) = self.shard(key).lock() {
+            let charge = block.len();
+            shard.insert(key, block, charge);
+        }
+    }
+
+    /// Returns an id no other caller gets, which a database adds to its block keys. Segment
+    /// numbers start over in every database, so databases sharing a cache would otherwise
+    /// read each other's blocks
+    pub fn new_cache_id() -> u64 {
+        NEXT_CACHE_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of bytes of block data currently cached
+    pub fn usage(&self) -> usize {
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.lock().ok().map(|shard| shard.usage()))
+            .sum()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn shard(&self, key: BlockKey) -> &Mutex<LruCache<BlockKey, Arc<Vec<u8>>>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_BLOCK_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_cache_counts_hits_and_misses() {
+        let cache = BlockCache::new(1024);
+
+        assert!(cache.get((0, 1, 0)).is_none());
+        cache.insert((0, 1, 0), Arc::new(b"block".to_vec()));
+        assert_eq!(cache.get((0, 1, 0)).as_deref(), Some(&b"block".to_vec()));
+        assert!(cache.get((1, 1, 0)).is_none());
+
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.misses(), 2);
+        assert_eq!(cache.usage(), 5);
+    }
+}
@@ -0,0 +1,182 @@
+use std::io;
+
+use crate::database::{
+    bloom_filter::BloomFilterSize,
+    filter_policy::Filter,
+    hash::{hash64, read_u64},
+};
+
+const FORMAT_MAGIC: &[u8; 7] = b"KVBLKBF";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = FORMAT_MAGIC.len() + 1 + 16;
+const WORDS_PER_BLOCK: usize = 8;
+const BITS_PER_BLOCK: u32 = 512;
+const MAX_NUM_PROBES: usize = 30;
+
+/// One cache line worth of filter bits
+#[derive(Clone, Copy, Default)]
+#[repr(C, align(64))]
+struct CacheLine([u64; WORDS_PER_BLOCK]);
+
+/// Bloom filter split into cache line sized blocks. A key picks one block and sets all of
+/// its probes inside it, so checking a key touches a single cache line
+pub struct BlockedBloomFilter {
+    blocks: Vec<CacheLine>,
+    num_probes: usize,
+}
+
+impl BlockedBloomFilter {
+    /// Create a filter for `expected_keys` keys
+    pub fn with_size(expected_keys: usize, size: BloomFilterSize) -> Self {
+        let bits_per_key = size.bits_per_key().max(1.0);
+        let num_bits = (expected_keys as f64 * bits_per_key).ceil() as usize;
+        let num_blocks = num_bits.div_ceil(BITS_PER_BLOCK as usize).max(1);
+        let num_probes = ((bits_per_key * 2f64.ln()).round() as usize).clamp(1, MAX_NUM_PROBES);
+
+        Self {
+            blocks: vec![CacheLine::default(); num_blocks],
+            num_probes,
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        let (block, probes) = self.probes(key);
+        let block = &mut self.blocks[block];
+        for bit in probes {
+            block.0[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Whether `data` was written by `serialize`
+    pub fn is_blocked_bloom_filter(data: &[u8]) -> bool {
+        data.starts_with(FORMAT_MAGIC)
+    }
+
+    /// Format: [magic (7 bytes), version (1 byte), num_blocks (8 bytes), num_probes (8 bytes),
+    /// blocks (64 bytes each)]
+    pub fn deserialize(data: &[u8]) -> io::Result<Self> {
+        if data.len() < HEADER_LEN || data[FORMAT_MAGIC.len()] != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid blocked bloom filter header",
+            ));
+        }
+
+        // The header comes straight from disk, so a damaged one must not overflow
+        let num_blocks = usize::try_from(read_u64(&data[8..16])).unwrap_or(usize::MAX);
+        let num_probes = read_u64(&data[16..24]);
+        if num_probes == 0 || num_probes > MAX_NUM_PROBES as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Blocked bloom filter probe count {} is out of range",
+                    num_probes
+                ),
+            ));
+        }
+        let num_probes = num_probes as usize;
+        let body = &data[HEADER_LEN..];
+        if num_blocks == 0 || num_blocks.checked_mul(WORDS_PER_BLOCK * 8) != Some(body.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Blocked bloom filter size does not match its header",
+            ));
+        }
+
+        let blocks = body
+            .chunks_exact(WORDS_PER_BLOCK * 8)
+            .map(|block| {
+                let mut line = CacheLine::default();
+                for (word, bytes) in line.0.iter_mut().zip(block.chunks_exact(8)) {
+                    *word = read_u64(bytes);
+                }
+                line
+            })
+            .collect();
+
+        Ok(Self { blocks, num_probes })
+    }
+
+    /// The block a key lives in and the bit positions inside that block
+    fn probes(&self, key: &[u8]) -> (usize, impl Iterator<Item = usize> + use<>) {
+        let hash = hash64(key);
+        let block = ((hash >> 32) % self.blocks.len() as u64) as usize;
+        let first = hash as u32;
+        let delta = (hash.rotate_left(21) as u32) | 1;
+
+        let probes = (0..self.num_probes as u32)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(delta)) % BITS_PER_BLOCK) as usize);
+        (block, probes)
+    }
+}
+
+impl Filter for BlockedBloomFilter {
+    fn might_contain(&self, key: &[u8]) -> bool {
+        let (block, mut probes) = self.probes(key);
+        let block = &self.blocks[block];
+        probes.all(|bit| block.0[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(HEADER_LEN + self.blocks.len() * 64);
+        result.extend_from_slice(FORMAT_MAGIC);
+        result.push(FORMAT_VERSION);
+        result.extend_from_slice(&(self.blocks.len() as u64).to_le_bytes());
+        result.extend_from_slice(&(self.num_probes as u64).to_le_bytes());
+        for block in &self.blocks {
+            for word in block.0 {
+                result.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked_bloom_filter_round_trips_and_rejects_most_absent_keys() {
+        let mut filter = BlockedBloomFilter::with_size(10_000, BloomFilterSize::BitsPerKey(10.0));
+        for i in 0..10_000 {
+            filter.insert(format!("key_{}", i).as_bytes());
+        }
+        let filter = BlockedBloomFilter::deserialize(&filter.serialize()).unwrap();
+
+        assert!((0..10_000).all(|i| filter.might_contain(format!("key_{}", i).as_bytes())));
+        let false_positives = (0..100_000)
+            .filter(|i| filter.might_contain(format!("other_{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 3_000);
+    }
+
+    #[test]
+    fn test_blocked_bloom_filter_rejects_a_huge_block_count() {
+        let mut data =
+            BlockedBloomFilter::with_size(10, BloomFilterSize::BitsPerKey(10.0)).serialize();
+        data[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            BlockedBloomFilter::deserialize(&data)
+                .err()
+                .map(|error| error.kind()),
+            Some(io::ErrorKind::InvalidData)
+        );
+    }
+
+    #[test]
+    fn test_blocked_bloom_filter_rejects_an_out_of_range_probe_count() {
+        let serialized =
+            BlockedBloomFilter::with_size(10, BloomFilterSize::BitsPerKey(10.0)).serialize();
+        for num_probes in [0, MAX_NUM_PROBES as u64 + 1, u32::MAX as u64] {
+            let mut data = serialized.clone();
+            data[16..24].copy_from_slice(&num_probes.to_le_bytes());
+            assert_eq!(
+                BlockedBloomFilter::deserialize(&data)
+                    .err()
+                    .map(|error| error.kind()),
+                Some(io::ErrorKind::InvalidData)
+            );
+        }
+    }
+}
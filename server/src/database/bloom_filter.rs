@@ -1,44 +1,89 @@
 use std::io;
 
-// Default: ~10 bits per key, 3 hash functions
-const DEFAULT_BITS_PER_KEY: usize = 10;
-const DEFAULT_NUM_HASHES: usize = 3;
+use crate::database::{
+    filter_policy::Filter,
+    hash::{hash64, read_u64},
+};
+
+/// Written at the start of versioned filter files. Read as the `num_bits` field of a legacy
+/// filter it would describe an impossibly large filter, so the two formats cannot be confused
+const FORMAT_MAGIC: &[u8; 7] = b"KVBLOOM";
+const FORMAT_VERSION: u8 = 2;
+const HEADER_LEN: usize = FORMAT_MAGIC.len() + 1;
+/// Filters never get fewer bits than this, so tiny or empty segments still get a usable filter
+const MIN_NUM_BITS: usize = 64;
+const MAX_NUM_HASHES: usize = 30;
+
+/// How large the bloom filter of each segment is made
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BloomFilterSize {
+    /// Number of bits spent on every key
+    BitsPerKey(f64),
+    /// Fraction of lookups for absent keys that may wrongly pass the filter, e.g. `0.01`
+    FalsePositiveRate(f64),
+}
+
+impl BloomFilterSize {
+    pub fn bits_per_key(self) -> f64 {
+        match self {
+            BloomFilterSize::BitsPerKey(bits_per_key) => bits_per_key,
+            BloomFilterSize::FalsePositiveRate(rate) => {
+                -rate.clamp(f64::MIN_POSITIVE, 1.0).ln() / (2f64.ln() * 2f64.ln())
+            }
+        }
+    }
+}
+
+impl Default for BloomFilterSize {
+    /// About a 1% false positive rate
+    fn default() -> Self {
+        BloomFilterSize::BitsPerKey(10.0)
+    }
+}
+
+/// How the probe positions of a key are derived
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Hashing {
+    /// Filters written before the format was versioned: one FNV-1a pass per probe, seeded
+    /// by XOR-ing the probe number into the offset basis
+    LegacyFnv,
+    /// One strong 64-bit hash split into the probe positions by double hashing
+    DoubleHash,
+}
 
 pub struct BloomFilter {
     bits: Vec<u8>,
     num_bits: usize,
     num_hashes: usize,
+    hashing: Hashing,
 }
 
 impl BloomFilter {
     /// Create a new bloom filter with a given size in bits and number of hash functions
     pub fn new(num_bits: usize, num_hashes: usize) -> Self {
-        let num_bytes = (num_bits + 7) / 8; // Round up to nearest byte
+        let num_bits = num_bits.max(MIN_NUM_BITS);
+        let num_bytes = num_bits.div_ceil(8); // Round up to nearest byte
         Self {
             bits: vec![0; num_bytes],
             num_bits,
-            num_hashes,
+            num_hashes: num_hashes.clamp(1, MAX_NUM_HASHES),
+            hashing: Hashing::DoubleHash,
         }
     }
 
-    /// Create a bloom filter sized for approximately `bits_per_key` bits per key
-    /// with `num_hashes` hash functions
-    pub fn with_capacity(expected_keys: usize, bits_per_key: usize, num_hashes: usize) -> Self {
-        let num_bits = expected_keys * bits_per_key;
+    /// Create a bloom filter for `expected_keys` keys, using the number of hash functions
+    /// that gives the lowest false positive rate for its size
+    pub fn with_size(expected_keys: usize, size: BloomFilterSize) -> Self {
+        let bits_per_key = size.bits_per_key().max(1.0);
+        let num_bits = (expected_keys as f64 * bits_per_key).ceil() as usize;
+        let num_hashes = (bits_per_key * 2f64.ln()).round() as usize;
         Self::new(num_bits, num_hashes)
     }
 
-    /// Create a bloom filter with default parameters
-    pub fn default_for_keys(expected_keys: usize) -> Self {
-        Self::with_capacity(expected_keys, DEFAULT_BITS_PER_KEY, DEFAULT_NUM_HASHES)
-    }
-
     /// Add a key to the bloom filter
     pub fn insert(&mut self, key: &[u8]) {
-        for i in 0..self.num_hashes {
-            let hash = self.hash(key, i as u64);
-            let bit_index = hash % self.num_bits as u64;
-            self.set_bit(bit_index as usize);
+        for bit_index in self.probes(key) {
+            self.set_bit(bit_index);
         }
     }
 
@@ -46,26 +91,43 @@ impl BloomFilter {
     /// Returns false if the key is definitely not present
     /// Returns true if the key might be present (could be a false positive)
     pub fn might_contain(&self, key: &[u8]) -> bool {
-        for i in 0..self.num_hashes {
-            let hash = self.hash(key, i as u64);
-            let bit_index = hash % self.num_bits as u64;
-            if !self.get_bit(bit_index as usize) {
-                return false;
-            }
-        }
-        true
+        self.probes(key).all(|bit_index| self.get_bit(bit_index))
     }
 
-    /// Hash function: simple hash using FNV-1a style with seed variation
-    fn hash(&self, key: &[u8], seed: u64) -> u64 {
-        let mut hash = 14695981039346656037u64; // FNV offset basis
-        hash ^= seed; // Mix in seed for different hash functions
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
 
-        for &byte in key {
-            hash ^= byte as u64;
-            hash = hash.wrapping_mul(1099511628211u64); // FNV prime
-        }
-        hash
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    /// Fraction of bits that are set. The false positive rate is about this raised to the
+    /// number of hash functions
+    pub fn fill_ratio(&self) -> f64 {
+        let set_bits: u32 = self.bits.iter().map(|byte| byte.count_ones()).sum();
+        set_bits as f64 / self.num_bits as f64
+    }
+
+    /// Bit positions checked for a key
+    fn probes<'k>(&self, key: &'k [u8]) -> impl Iterator<Item = usize> + use<'k> {
+        let num_bits = self.num_bits as u64;
+        let num_hashes = self.num_hashes as u64;
+        let hashing = self.hashing;
+        let hash = match hashing {
+            Hashing::LegacyFnv => 0,
+            Hashing::DoubleHash => hash64(key),
+        };
+        // Kirsch-Mitzenmacher: probe i is h1 + i * h2, with h2 odd so it never degenerates
+        let delta = hash.rotate_left(32) | 1;
+
+        (0..num_hashes).map(move |i| {
+            let hash = match hashing {
+                Hashing::LegacyFnv => legacy_hash(key, i),
+                Hashing::DoubleHash => hash.wrapping_add(i.wrapping_mul(delta)),
+            };
+            (hash % num_bits) as usize
+        })
     }
 
     /// Set a bit at the given index
@@ -83,52 +145,92 @@ impl BloomFilter {
     }
 
     /// Serialize the bloom filter to bytes for disk storage
-    /// Format: [num_bits (8 bytes), num_hashes (8 bytes), bits (variable)]
+    /// Format: [magic (7 bytes), version (1 byte), num_bits (8 bytes), num_hashes (8 bytes),
+    /// bits (variable)]
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result = Vec::new();
-
-        // Write num_bits (8 bytes)
-        result.extend_from_slice(&self.num_bits.to_le_bytes());
-
-        // Write num_hashes (8 bytes)
-        result.extend_from_slice(&self.num_hashes.to_le_bytes());
-
-        // Write bit array
+        let mut result = Vec::with_capacity(HEADER_LEN + 16 + self.bits.len());
+        result.extend_from_slice(FORMAT_MAGIC);
+        result.push(FORMAT_VERSION);
+        result.extend_from_slice(&(self.num_bits as u64).to_le_bytes());
+        result.extend_from_slice(&(self.num_hashes as u64).to_le_bytes());
         result.extend_from_slice(&self.bits);
-
         result
     }
 
-    /// Deserialize a bloom filter from bytes
+    /// Whether `data` was serialized in the versioned format
+    pub fn is_versioned(data: &[u8]) -> bool {
+        data.starts_with(FORMAT_MAGIC)
+    }
+
+    /// Deserialize a bloom filter from bytes, accepting both the versioned format and the
+    /// unversioned one written by older releases
     pub fn deserialize(data: &[u8]) -> io::Result<Self> {
-        if data.len() < 16 {
+        let (hashing, body) = match data.strip_prefix(FORMAT_MAGIC.as_slice()) {
+            Some([FORMAT_VERSION, body @ ..]) => (Hashing::DoubleHash, body),
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unsupported bloom filter format version",
+                ));
+            }
+            None => (Hashing::LegacyFnv, data),
+        };
+
+        if body.len() < 16 {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Bloom filter data too short",
             ));
         }
 
-        // Read num_bits (8 bytes)
-        let num_bits = u64::from_le_bytes([
-            data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
-        ]) as usize;
-
-        // Read num_hashes (8 bytes)
-        let num_hashes = u64::from_le_bytes([
-            data[8], data[9], data[10], data[11], data[12], data[13], data[14], data[15],
-        ]) as usize;
-
-        // Read bit array
-        let bits = data[16..].to_vec();
+        let num_bits = read_u64(&body[0..8]) as usize;
+        let num_hashes = read_u64(&body[8..16]);
+        if num_hashes == 0 || num_hashes > MAX_NUM_HASHES as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Bloom filter hash count {} is out of range", num_hashes),
+            ));
+        }
+        let num_hashes = num_hashes as usize;
+        let bits = body[16..].to_vec();
+        if num_bits == 0 || bits.len() < num_bits.div_ceil(8) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Bloom filter bit array does not match its size",
+            ));
+        }
 
         Ok(Self {
             bits,
             num_bits,
             num_hashes,
+            hashing,
         })
     }
 }
 
+impl Filter for BloomFilter {
+    fn might_contain(&self, key: &[u8]) -> bool {
+        BloomFilter::might_contain(self, key)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        BloomFilter::serialize(self)
+    }
+}
+
+/// Hash used by unversioned filters: FNV-1a with the seed XOR-ed into the offset basis
+fn legacy_hash(key: &[u8], seed: u64) -> u64 {
+    let mut hash = 14695981039346656037u64; // FNV offset basis
+    hash ^= seed;
+
+    for &byte in key {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(1099511628211u64); // FNV prime
+    }
+    hash
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,6 +247,15 @@ mod tests {
         assert!(!filter.might_contain(b"key3")); // Should not be present
     }
 
+    #[test]
+    fn test_bloom_filter_fill_ratio() {
+        let mut filter = BloomFilter::new(1024, 4);
+        assert_eq!(filter.fill_ratio(), 0.0);
+
+        filter.insert(b"key1");
+        assert!(filter.fill_ratio() > 0.0 && filter.fill_ratio() <= 4.0 / 1024.0);
+    }
+
     #[test]
     fn test_bloom_filter_false_positive() {
         let mut filter = BloomFilter::new(10, 3); // Very small filter
@@ -156,6 +267,40 @@ mod tests {
         assert!(filter.might_contain(b"key1"));
     }
 
+    #[test]
+    fn test_bloom_filter_false_positive_rate_matches_its_size() {
+        for (size, max_rate) in [
+            (BloomFilterSize::BitsPerKey(10.0), 0.02),
+            (BloomFilterSize::FalsePositiveRate(0.001), 0.003),
+        ] {
+            let mut filter = BloomFilter::with_size(10_000, size);
+            for i in 0..10_000 {
+                filter.insert(format!("key_{}", i).as_bytes());
+            }
+
+            let false_positives = (0..100_000)
+                .filter(|i| filter.might_contain(format!("other_{}", i).as_bytes()))
+                .count();
+            assert!((false_positives as f64 / 100_000.0) < max_rate);
+        }
+    }
+
+    #[test]
+    fn test_bloom_filter_loads_legacy_format() {
+        let mut legacy = BloomFilter::new(1000, 3);
+        legacy.hashing = Hashing::LegacyFnv;
+        legacy.insert(b"old_key");
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&(legacy.num_bits as u64).to_le_bytes());
+        data.extend_from_slice(&(legacy.num_hashes as u64).to_le_bytes());
+        data.extend_from_slice(&legacy.bits);
+
+        let loaded = BloomFilter::deserialize(&data).unwrap();
+        assert_eq!(loaded.hashing, Hashing::LegacyFnv);
+        assert!(loaded.might_contain(b"old_key"));
+    }
+
     #[test]
     fn test_bloom_filter_serialize_deserialize() {
         let mut filter = BloomFilter::new(100, 3);
@@ -168,4 +313,22 @@ mod tests {
         assert_eq!(deserialized.num_bits, filter.num_bits);
         assert_eq!(deserialized.num_hashes, filter.num_hashes);
     }
+
+    #[test]
+    fn test_bloom_filter_rejects_an_out_of_range_hash_count() {
+        let mut filter = BloomFilter::new(100, 3);
+        filter.insert(b"test_key");
+        let serialized = filter.serialize();
+
+        for num_hashes in [0, MAX_NUM_HASHES as u64 + 1, u32::MAX as u64] {
+            let mut corrupted = serialized.clone();
+            corrupted[HEADER_LEN + 8..HEADER_LEN + 16].copy_from_slice(&num_hashes.to_le_bytes());
+            assert_eq!(
+                BloomFilter::deserialize(&corrupted)
+                    .err()
+                    .map(|error| error.kind()),
+                Some(io::ErrorKind::InvalidData)
+            );
+        }
+    }
 }
@@ -1,64 +1,103 @@
-use crate::database::mem_table::MemTable;
+use crate::database::{
+    atomic_file::write_atomically,
+    bloom_filter::BloomFilter,
+    env::Env,
+    filter_policy::{Filter, FilterPolicy, builtin_policies},
+    hash::read_u64,
+    metadata_cache::MetadataCache,
+    prefix_extractor::PrefixExtractor,
+};
 
-use super::bloom_filter::BloomFilter;
 use std::{
-    collections::HashMap,
-    fs,
+    collections::{HashMap, HashSet},
+    io::Write,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-const BLOOM_FILTER_FILE_EXTENSION: &str = "bf";
+pub const BLOOM_FILTER_FILE_EXTENSION: &str = "bf";
+/// Starts filter files whose filter also holds key prefixes. It is followed by the length
+/// of the prefix extractor's name (8 bytes), the name and then the filter itself
+const PREFIX_TAG: &[u8; 8] = b"KVPREFIX";
+
+pub struct SegmentFilter {
+    filter: Arc<dyn Filter>,
+    /// Name of the prefix extractor whose prefixes were added to the filter, if any
+    prefix_extractor: Option<String>,
+}
+
+/// Filters are read from their files on first use and kept in the metadata cache, except
+/// pinned ones which stay in memory until their segment goes away
 pub struct BloomFilterRegistry {
-    /// Bloom filters keyed by base file name (without extension), e.g., "segment_0"
-    filters: HashMap<String, BloomFilter>,
     directory: PathBuf,
+    /// Base file names (without extension), e.g., "segment_0", of the segments with a filter
+    filter_files: HashSet<String>,
+    /// Filters kept outside the metadata cache, keyed by base file name
+    pinned: HashMap<String, Arc<SegmentFilter>>,
+    metadata_cache: Arc<MetadataCache>,
+    /// Policies that may have written the filter files
+    known_policies: Vec<Arc<dyn FilterPolicy>>,
+    /// Policy used for new segments of each level, the last one covering deeper levels
+    policies: Vec<Arc<dyn FilterPolicy>>,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    env: Arc<dyn Env>,
 }
 
 impl BloomFilterRegistry {
-    pub fn new<P>(directory: P) -> std::io::Result<Self>
+    pub fn new<P>(
+        env: Arc<dyn Env>,
+        directory: P,
+        policies: Vec<Arc<dyn FilterPolicy>>,
+        prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+        metadata_cache: Arc<MetadataCache>,
+    ) -> std::io::Result<Self>
     where
         P: AsRef<Path>,
     {
-        let bloom_filter_paths = Self::find_bloom_filter_files(&directory)?;
-        let filters = bloom_filter_paths
-            .into_iter()
-            .filter_map(|path| Self::load_bloom_filter(&path))
+        assert!(!policies.is_empty());
+        // Files written under earlier configurations may use any of the builtin policies
+        let known_policies: Vec<_> = policies.iter().cloned().chain(builtin_policies()).collect();
+
+        let filter_files = Self::find_bloom_filter_files(env.as_ref(), directory.as_ref())?
+            .iter()
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
             .collect();
 
         Ok(Self {
-            filters,
             directory: directory.as_ref().to_path_buf(),
+            filter_files,
+            pinned: HashMap::new(),
+            metadata_cache,
+            known_policies,
+            policies,
+            prefix_extractor,
+            env,
         })
     }
 
     /// Find all bloom filter files in the given directory
-    fn find_bloom_filter_files<P: AsRef<Path>>(directory: P) -> std::io::Result<Vec<PathBuf>> {
-        Ok(std::fs::read_dir(&directory)?
-            .filter_map(Result::ok)
-            .filter_map(|entry| {
-                entry.path().extension().and_then(|ext| {
-                    if ext == BLOOM_FILTER_FILE_EXTENSION {
-                        Some(entry.path())
-                    } else {
-                        None
-                    }
-                })
+    fn find_bloom_filter_files(env: &dyn Env, directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+        Ok(env
+            .list_files(directory)?
+            .into_iter()
+            .filter(|path| {
+                path.extension()
+                    .map(|ext| ext == BLOOM_FILTER_FILE_EXTENSION)
+                    .unwrap_or(false)
             })
             .collect())
     }
 
-    /// Load a bloom filter from a file path, returning None on any error
-    /// Returns (base_name, filter) where base_name is the file name without extension
-    fn load_bloom_filter(path: &PathBuf) -> Option<(String, BloomFilter)> {
-        // Extract base name (file stem without extension) as owned String
-        // This avoids lifetime issues since String is owned
-        let base_name = path
-            .file_stem()
-            .and_then(|stem| stem.to_str())
-            .map(|s| s.to_string())?;
-
+    /// Load a filter from a file path with the first policy that recognizes it, returning
+    /// None on any error. Files no policy recognizes are unversioned bloom filters.
+    /// Returns the filter along with the size of its file
+    fn load_filter(
+        env: &dyn Env,
+        policies: &[Arc<dyn FilterPolicy>],
+        path: &Path,
+    ) -> Option<(SegmentFilter, usize)> {
         // Read file contents
-        let data = match fs::read(path) {
+        let data = match env.read(path) {
             Ok(data) => data,
             Err(error) => {
                 tracing::error!(
@@ -70,9 +109,24 @@ impl BloomFilterRegistry {
             }
         };
 
-        // Deserialize bloom filter
-        match BloomFilter::deserialize(&data) {
-            Ok(filter) => Some((base_name, filter)),
+        let (prefix_extractor, data) = match Self::split_prefix_tag(&data) {
+            Some((name, data)) => (Some(name), data),
+            None => (None, data.as_slice()),
+        };
+        let filter = policies
+            .iter()
+            .find_map(|policy| policy.load(data))
+            .unwrap_or_else(|| {
+                BloomFilter::deserialize(data).map(|filter| Box::new(filter) as Box<dyn Filter>)
+            });
+        match filter {
+            Ok(filter) => Some((
+                SegmentFilter {
+                    filter: Arc::from(filter),
+                    prefix_extractor,
+                },
+                data.len(),
+            )),
             Err(error) => {
                 tracing::error!(
                     "Failed to deserialize bloom filter file {}: {}",
@@ -84,34 +138,188 @@ impl BloomFilterRegistry {
         }
     }
 
-    /// Get a bloom filter by path. Extracts the base name from the path
+    /// Get a filter by path. Extracts the base name from the path
     /// and looks it up in the registry.
-    pub fn get(&self, path: &PathBuf) -> Option<&BloomFilter> {
-        // Extract base name from path (file stem without extension)
-        let base_name = path.file_stem()?.to_str()?;
-        self.filters.get(base_name)
+    pub fn get(&self, path: &Path) -> Option<Arc<dyn Filter>> {
+        self.get_segment_filter(path)
+            .map(|segment_filter| Arc::clone(&segment_filter.filter))
     }
 
-    pub fn store(&mut self, path: &PathBuf, mem_table: &MemTable) -> std::io::Result<()> {
-        let mut bloom_filter_path = path.clone();
-        bloom_filter_path.set_extension(BLOOM_FILTER_FILE_EXTENSION);
+    /// Whether the segment may hold keys starting with `prefix`. Only segments whose filter
+    /// was built with the configured prefix extractor can be ruled out
+    pub fn prefix_may_match(&self, path: &Path, prefix: &[u8]) -> bool {
+        let Some(prefix_extractor) = &self.prefix_extractor else {
+            return true;
+        };
+        let Some(filter_prefix) = prefix_extractor.prefix(prefix) else {
+            return true;
+        };
+
+        match self.get_segment_filter(path) {
+            Some(segment_filter)
+                if segment_filter.prefix_extractor.as_deref()
+                    == Some(prefix_extractor.name().as_str()) =>
+            {
+                segment_filter.filter.might_contain(filter_prefix)
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns the segment's filter, reading it from its file when it is neither pinned nor
+    /// cached
+    fn get_segment_filter(&self, path: &Path) -> Option<Arc<SegmentFilter>> {
+        let base_name = path.file_stem()?.to_str()?;
+        if let Some(segment_filter) = self.pinned.get(base_name) {
+            return Some(Arc::clone(segment_filter));
+        }
+        if !self.filter_files.contains(base_name) {
+            return None;
+        }
+        if let Some(segment_filter) = self.metadata_cache.get_filter(base_name) {
+            return Some(segment_filter);
+        }
 
-        let mut bloom_filter = BloomFilter::default_for_keys(mem_table.len());
-        for (key, _) in mem_table.iter() {
-            bloom_filter.insert(key);
+        let (segment_filter, charge) = Self::load_filter(
+            self.env.as_ref(),
+            &self.known_policies,
+            &self.filter_path(base_name),
+        )?;
+        let segment_filter = Arc::new(segment_filter);
+        self.metadata_cache
+            .insert_filter(base_name, Arc::clone(&segment_filter), charge);
+        Some(segment_filter)
+    }
+
+    /// Keeps the segment's filter in memory outside the metadata cache until it is removed
+    pub fn pin(&mut self, path: &Path) {
+        let Some(base_name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            return;
+        };
+        if !self.filter_files.contains(base_name) {
+            return;
         }
+        let loaded = Self::load_filter(
+            self.env.as_ref(),
+            &self.known_policies,
+            &self.filter_path(base_name),
+        );
+        if let Some((segment_filter, _)) = loaded {
+            self.pinned
+                .insert(base_name.to_string(), Arc::new(segment_filter));
+        }
+    }
 
-        let bloom_filter_base_name = bloom_filter_path
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
+    fn filter_path(&self, base_name: &str) -> PathBuf {
+        self.directory
+            .join(base_name)
+            .with_extension(BLOOM_FILTER_FILE_EXTENSION)
+    }
 
-        let data = bloom_filter.serialize();
-        fs::write(bloom_filter_path, data)?;
+    /// Builds and stores the filter of a new segment with the policy of its level. With a
+    /// prefix extractor configured the prefixes of the keys are added as well. The filter
+    /// is kept in memory when `pin` is set and otherwise loaded again on first use
+    pub fn store(
+        &mut self,
+        path: &Path,
+        level: usize,
+        keys: &[&[u8]],
+        pin: bool,
+    ) -> std::io::Result<()> {
+        let policy = &self.policies[level.min(self.policies.len() - 1)];
+        let filter = Self::write_filter(
+            self.env.as_ref(),
+            path,
+            policy.as_ref(),
+            self.prefix_extractor.as_deref(),
+            keys,
+        )?;
 
-        self.filters.insert(bloom_filter_base_name, bloom_filter);
+        let bloom_filter_base_name = path.file_stem().unwrap().to_str().unwrap().to_string();
+        if pin {
+            self.pinned.insert(
+                bloom_filter_base_name.clone(),
+                Arc::new(SegmentFilter {
+                    filter: Arc::from(filter),
+                    prefix_extractor: self
+                        .prefix_extractor
+                        .as_ref()
+                        .map(|extractor| extractor.name()),
+                }),
+            );
+        }
+        self.filter_files.insert(bloom_filter_base_name);
         Ok(())
     }
+
+    /// Builds the filter of a segment's keys and writes it next to the segment, adding the
+    /// prefixes of the keys when a prefix extractor is given
+    pub(crate) fn write_filter(
+        env: &dyn Env,
+        path: &Path,
+        policy: &dyn FilterPolicy,
+        prefix_extractor: Option<&dyn PrefixExtractor>,
+        keys: &[&[u8]],
+    ) -> std::io::Result<Box<dyn Filter>> {
+        let filter = match prefix_extractor {
+            Some(extractor) => {
+                let mut prefixes: Vec<&[u8]> = keys
+                    .iter()
+                    .filter_map(|key| extractor.prefix(key))
+                    .collect();
+                // Keys are sorted, so equal prefixes are next to each other
+                prefixes.dedup();
+                policy.build(&[keys, prefixes.as_slice()].concat())
+            }
+            None => policy.build(keys),
+        };
+
+        let data = filter.serialize();
+        let name = prefix_extractor.map(|extractor| extractor.name());
+        write_atomically(
+            env,
+            &path.with_extension(BLOOM_FILTER_FILE_EXTENSION),
+            |file| {
+                if let Some(name) = &name {
+                    file.write_all(PREFIX_TAG)?;
+                    file.write_all(&(name.len() as u64).to_le_bytes())?;
+                    file.write_all(name.as_bytes())?;
+                }
+                file.write_all(&data)
+            },
+        )?;
+        Ok(filter)
+    }
+
+    /// Starts serving the filter file of a segment that was linked into the directory
+    pub fn insert(&mut self, path: &Path) {
+        if let Some(base_name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            self.filter_files.insert(base_name.to_string());
+        }
+    }
+
+    /// Splits a filter file written with a prefix extractor into the extractor's name and
+    /// the filter data
+    pub(crate) fn split_prefix_tag(data: &[u8]) -> Option<(String, &[u8])> {
+        let rest = data.strip_prefix(PREFIX_TAG.as_slice())?;
+        let name_len = usize::try_from(read_u64(rest.get(..8)?)).ok()?;
+        let name = rest.get(8..8usize.checked_add(name_len)?)?;
+        Some((
+            String::from_utf8_lossy(name).into_owned(),
+            &rest[8 + name_len..],
+        ))
+    }
+
+    /// Deletes the filter of a segment that is no longer live
+    pub fn remove(&mut self, path: &Path) -> std::io::Result<()> {
+        let mut bloom_filter_path = path.to_path_buf();
+        bloom_filter_path.set_extension(BLOOM_FILTER_FILE_EXTENSION);
+
+        if let Some(base_name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            self.filter_files.remove(base_name);
+            self.pinned.remove(base_name);
+            self.metadata_cache.remove(base_name);
+        }
+        self.env.remove_file_if_exists(&bloom_filter_path)
+    }
 }
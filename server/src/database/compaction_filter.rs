@@ -0,0 +1,142 @@
+use std::fmt::Debug;
+
+use crate::database::{
+    entry::Entry,
+    value_log::{ValueLogReader, ValuePointer},
+};
+
+/// What happens to an entry passed to a `CompactionFilter`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompactionDecision {
+    Keep,
+    /// Drops the key as if it had been deleted
+    Remove,
+    /// Keeps the key with a new value, which is stored in the output segment even when a
+    /// value log is configured. It must not contain a newline or start with a zero byte
+    ChangeValue(Vec<u8>),
+}
+
+/// Decides what compaction does with each key, e.g. to purge the keys of a deleted tenant
+/// or expire records by a timestamp in their values without deleting them one by one.
+/// Only the newest version of a key that is still live is passed, with values that were
+/// moved to the value log read back first. Keys are only seen once a compaction picks up
+/// their segment, so writes still in the in-memory table or waiting in level 0 are not
+/// filtered yet
+pub trait CompactionFilter: Send + Sync + Debug {
+    fn filter(&self, key: &[u8], value: &[u8]) -> CompactionDecision;
+}
+
+/// Runs an entry through the filter, returning None when it is removed. Tombstones are
+/// passed through untouched
+pub(crate) fn apply(
+    filter: &dyn CompactionFilter,
+    value_log: &ValueLogReader,
+    entry: Entry,
+) -> std::io::Result<Option<Entry>> {
+    let Entry::KeyValue { key, value } = entry else {
+        return Ok(Some(entry));
+    };
+    let decision = match ValuePointer::decode(&value) {
+        Some(pointer) => filter.filter(&key, &value_log.read(pointer?)?),
+        None => filter.filter(&key, &value),
+    };
+
+    match decision {
+        CompactionDecision::Keep => Ok(Some(Entry::KeyValue { key, value })),
+        CompactionDecision::Remove => Ok(None),
+        CompactionDecision::ChangeValue(value)
+            if value.contains(&b'\n') || ValuePointer::decode(&value).is_some() =>
+        {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Compaction filter returned a value for {} that cannot be stored in a segment",
+                    String::from_utf8_lossy(&key)
+                ),
+            ))
+        }
+        CompactionDecision::ChangeValue(value) => Ok(Some(Entry::KeyValue { key, value })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::database::{
+        env::{Env, MemEnv},
+        value_log::ValueLog,
+    };
+
+    /// Removes keys starting with "tenant1/", upper-cases values starting with "old" and
+    /// turns "bad" into a value segments cannot hold
+    #[derive(Debug)]
+    struct TestFilter;
+
+    impl CompactionFilter for TestFilter {
+        fn filter(&self, key: &[u8], value: &[u8]) -> CompactionDecision {
+            if key.starts_with(b"tenant1/") {
+                CompactionDecision::Remove
+            } else if value.starts_with(b"old") {
+                CompactionDecision::ChangeValue(value.to_ascii_uppercase())
+            } else if value == b"bad" {
+                CompactionDecision::ChangeValue(b"two\nlines".to_vec())
+            } else {
+                CompactionDecision::Keep
+            }
+        }
+    }
+
+    fn key_value(key: &[u8], value: &[u8]) -> Entry {
+        Entry::KeyValue {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        }
+    }
+
+    fn value(entry: Option<Entry>) -> Option<Vec<u8>> {
+        match entry? {
+            Entry::KeyValue { value, .. } => Some(value),
+            Entry::Tombstone { .. } => None,
+        }
+    }
+
+    #[test]
+    fn test_apply_keeps_removes_and_changes_entries() {
+        let env: Arc<dyn Env> = Arc::new(MemEnv::new());
+        env.create_dir_all("/db".as_ref()).unwrap();
+        let mut value_log = ValueLog::new(Arc::clone(&env), "/db", Some(1), None).unwrap();
+        value_log.create_file(1).unwrap();
+        let pointer: Vec<u8> = value_log.append(b"old separated").unwrap().into();
+        value_log.sync().unwrap();
+        let reader = value_log.reader();
+
+        let apply = |entry| apply(&TestFilter, &reader, entry);
+        assert_eq!(
+            value(apply(key_value(b"tenant2/a", b"new")).unwrap()),
+            Some(b"new".to_vec())
+        );
+        assert!(apply(key_value(b"tenant1/a", b"new")).unwrap().is_none());
+        assert_eq!(
+            value(apply(key_value(b"tenant2/b", b"old inline")).unwrap()),
+            Some(b"OLD INLINE".to_vec())
+        );
+        // The filter sees the separated value, and its replacement is stored inline
+        assert_eq!(
+            value(apply(key_value(b"tenant2/c", &pointer)).unwrap()),
+            Some(b"OLD SEPARATED".to_vec())
+        );
+        assert!(matches!(
+            apply(Entry::Tombstone {
+                key: b"tenant1/d".to_vec()
+            })
+            .unwrap(),
+            Some(Entry::Tombstone { .. })
+        ));
+        assert_eq!(
+            apply(key_value(b"tenant2/e", b"bad")).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -4,6 +4,14 @@ pub enum Entry {
     Tombstone { key: Vec<u8> },
 }
 
+impl Entry {
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Entry::KeyValue { key, .. } | Entry::Tombstone { key } => key,
+        }
+    }
+}
+
 impl From<Vec<u8>> for Entry {
     fn from(value: Vec<u8>) -> Self {
         match value.iter().position(|&b| b == b' ') {
@@ -0,0 +1,97 @@
+use std::{
+    fs::{self, DirBuilder, File, OpenOptions},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use memmap2::Mmap;
+
+use super::{Env, MappedFile, ReadableFile, WritableFile};
+
+/// Env backed by the local filesystem through `std::fs`
+#[derive(Debug, Default)]
+pub struct DiskEnv;
+
+impl DiskEnv {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Env for DiskEnv {
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        DirBuilder::new().recursive(true).create(path)
+    }
+
+    fn list_files(&self, directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+        Ok(fs::read_dir(directory)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                entry
+                    .file_type()
+                    .ok()
+                    .and_then(|ft| ft.is_file().then_some(entry.path()))
+            })
+            .collect())
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn open_readable(&self, path: &Path) -> std::io::Result<Box<dyn ReadableFile>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn file_size(&self, path: &Path) -> std::io::Result<u64> {
+        Ok(fs::metadata(path)?.len())
+    }
+
+    fn map_readable(&self, path: &Path) -> std::io::Result<Option<Arc<dyn MappedFile>>> {
+        let file = File::open(path)?;
+        // SAFETY: only files that are never written again once installed are mapped. Removing
+        // a mapped file unlinks it but keeps its pages alive until the mapping is dropped
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Some(Arc::new(mmap)))
+    }
+
+    fn create_writable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>> {
+        Ok(Box::new(File::create(path)?))
+    }
+
+    fn open_appendable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>> {
+        Ok(Box::new(OpenOptions::new().append(true).open(path)?))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn hard_link(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        fs::hard_link(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> std::io::Result<()> {
+        File::open(path)?.sync_all()
+    }
+}
+
+impl WritableFile for File {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.sync_all()
+    }
+}
+
+impl MappedFile for Mmap {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+}
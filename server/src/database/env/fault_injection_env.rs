@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::{Env, ReadableFile, WritableFile};
+
+/// Env wrapper used to test crash recovery. It remembers which writes have not been made
+/// durable yet so `crash` can throw them away, and can fail writes or stop working
+/// altogether at a chosen operation as if the process had died there
+#[derive(Debug)]
+pub struct FaultInjectionEnv {
+    inner: Arc<dyn Env>,
+    state: Arc<Mutex<FaultState>>,
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+    /// Files written through this env, whose latest contents may not survive a crash
+    unsynced: HashMap<PathBuf, UnsyncedFile>,
+    /// Number of mutating operations still allowed before the simulated crash
+    operations_until_crash: Option<usize>,
+    crashed: bool,
+    fail_writes: bool,
+}
+
+#[derive(Debug)]
+struct UnsyncedFile {
+    /// Length of the file when it was last synced
+    synced_len: u64,
+    len: u64,
+    /// Whether the file's directory entry is durable. Newly created files only become
+    /// durable once their directory is synced
+    linked: bool,
+}
+
+impl FaultInjectionEnv {
+    pub fn new(inner: Arc<dyn Env>) -> Self {
+        Self {
+            inner,
+            state: Arc::new(Mutex::new(FaultState::default())),
+        }
+    }
+
+    /// Lets the next `operations` mutating operations succeed and fails every one after that
+    pub fn crash_after(&self, operations: usize) -> std::io::Result<()> {
+        self.lock()?.operations_until_crash = Some(operations);
+        Ok(())
+    }
+
+    /// Makes writes to files fail until switched off again
+    pub fn fail_writes(&self, fail: bool) -> std::io::Result<()> {
+        self.lock()?.fail_writes = fail;
+        Ok(())
+    }
+
+    /// Simulates the machine going down: files created since their directory was last synced
+    /// disappear and every other file loses whatever was written after its last sync.
+    /// Afterwards the env works normally again so the database can be reopened
+    pub fn crash(&self) -> std::io::Result<()> {
+        let mut state = self.lock()?;
+
+        for (path, file) in state.unsynced.drain() {
+            if !file.linked {
+                match self.inner.remove_file(&path) {
+                    Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(error);
+                    }
+                    _ => {}
+                }
+            } else if file.len > file.synced_len {
+                let mut data = self.inner.read(&path)?;
+                data.truncate(file.synced_len as usize);
+                let mut writer = self.inner.create_writable(&path)?;
+                writer.write_all(&data)?;
+                writer.sync()?;
+            }
+        }
+
+        state.operations_until_crash = None;
+        state.crashed = false;
+        state.fail_writes = false;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::io::Result<MutexGuard<'_, FaultState>> {
+        lock_state(&self.state)
+    }
+
+    fn check_read(&self) -> std::io::Result<()> {
+        if self.lock()?.crashed {
+            return Err(simulated_crash());
+        }
+        Ok(())
+    }
+
+    fn check_operation(&self) -> std::io::Result<()> {
+        check_operation(&mut *self.lock()?)
+    }
+}
+
+impl Env for FaultInjectionEnv {
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        self.check_operation()?;
+        self.inner.create_dir_all(path)
+    }
+
+    fn list_files(&self, directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+        self.check_read()?;
+        self.inner.list_files(directory)
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        self.check_read()?;
+        self.inner.read(path)
+    }
+
+    fn open_readable(&self, path: &Path) -> std::io::Result<Box<dyn ReadableFile>> {
+        self.check_read()?;
+        self.inner.open_readable(path)
+    }
+
+    fn file_size(&self, path: &Path) -> std::io::Result<u64> {
+        self.check_read()?;
+        self.inner.file_size(path)
+    }
+
+    fn create_writable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>> {
+        self.check_operation()?;
+        let inner = self.inner.create_writable(path)?;
+        self.lock()?.unsynced.insert(
+            path.to_path_buf(),
+            UnsyncedFile {
+                synced_len: 0,
+                len: 0,
+                linked: false,
+            },
+        );
+
+        Ok(Box::new(FaultInjectionWritableFile {
+            inner,
+            path: path.to_path_buf(),
+            state: Arc::clone(&self.state),
+        }))
+    }
+
+    fn open_appendable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>> {
+        self.check_operation()?;
+        let len = self.inner.read(path)?.len() as u64;
+        let inner = self.inner.open_appendable(path)?;
+        self.lock()?
+            .unsynced
+            .entry(path.to_path_buf())
+            .or_insert(UnsyncedFile {
+                synced_len: len,
+                len,
+                linked: true,
+            });
+
+        Ok(Box::new(FaultInjectionWritableFile {
+            inner,
+            path: path.to_path_buf(),
+            state: Arc::clone(&self.state),
+        }))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        self.check_operation()?;
+        self.inner.rename(from, to)?;
+
+        let mut state = self.lock()?;
+        state.unsynced.remove(to);
+        if let Some(file) = state.unsynced.remove(from) {
+            state.unsynced.insert(to.to_path_buf(), file);
+        }
+        Ok(())
+    }
+
+    fn hard_link(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        self.check_operation()?;
+        self.inner.hard_link(from, to)?;
+
+        // The contents are as durable as the original's, but the new name needs a
+        // directory sync
+        let len = self.inner.read(to)?.len() as u64;
+        self.lock()?.unsynced.insert(
+            to.to_path_buf(),
+            UnsyncedFile {
+                synced_len: len,
+                len,
+                linked: false,
+            },
+        );
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        self.check_operation()?;
+        self.inner.remove_file(path)?;
+        self.lock()?.unsynced.remove(path);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        self.check_operation()?;
+        self.inner.remove_dir(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> std::io::Result<()> {
+        self.check_operation()?;
+        self.inner.sync_dir(path)?;
+
+        for (file_path, file) in self.lock()?.unsynced.iter_mut() {
+            if file_path.parent() == Some(path) {
+                file.linked = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct FaultInjectionWritableFile {
+    inner: Box<dyn WritableFile>,
+    path: PathBuf,
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl Write for FaultInjectionWritableFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = lock_state(&self.state)?;
+        check_operation(&mut state)?;
+        if state.fail_writes {
+            return Err(std::io::Error::other("Injected write failure"));
+        }
+
+        let written = self.inner.write(buf)?;
+        if let Some(file) = state.unsynced.get_mut(&self.path) {
+            file.len += written as u64;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl WritableFile for FaultInjectionWritableFile {
+    fn sync(&mut self) -> std::io::Result<()> {
+        let mut state = lock_state(&self.state)?;
+        check_operation(&mut state)?;
+        self.inner.sync()?;
+
+        if let Some(file) = state.unsynced.get_mut(&self.path) {
+            file.synced_len = file.len;
+        }
+        Ok(())
+    }
+}
+
+fn lock_state(state: &Mutex<FaultState>) -> std::io::Result<MutexGuard<'_, FaultState>> {
+    state
+        .lock()
+        .map_err(|error| std::io::Error::other(error.to_string()))
+}
+
+fn check_operation(state: &mut FaultState) -> std::io::Result<()> {
+    if state.crashed {
+        return Err(simulated_crash());
+    }
+
+    match state.operations_until_crash {
+        Some(0) => {
+            state.crashed = true;
+            Err(simulated_crash())
+        }
+        Some(remaining) => {
+            state.operations_until_crash = Some(remaining - 1);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+fn simulated_crash() -> std::io::Error {
+    std::io::Error::other("Simulated crash")
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::{Env, MappedFile, ReadableFile, WritableFile};
+
+/// Contents of a file, shared between every path it is reachable from and every open writer.
+/// The inner `Arc` lets readers hold on to a snapshot while writers copy on write
+type FileData = Arc<Mutex<Arc<Vec<u8>>>>;
+
+#[derive(Debug, Default)]
+struct MemFileSystem {
+    files: HashMap<PathBuf, FileData>,
+    directories: HashSet<PathBuf>,
+}
+
+/// Env that keeps every file in memory. Nothing survives once the env is dropped
+#[derive(Debug, Default)]
+pub struct MemEnv {
+    file_system: Mutex<MemFileSystem>,
+}
+
+impl MemEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::io::Result<MutexGuard<'_, MemFileSystem>> {
+        self.file_system
+            .lock()
+            .map_err(|error| std::io::Error::other(error.to_string()))
+    }
+
+    /// Current contents of the file, unaffected by later writes
+    fn snapshot(&self, path: &Path) -> std::io::Result<SharedBytes> {
+        let data = self.lock()?.files.get(path).cloned();
+        let data = data.ok_or_else(|| Self::not_found(path))?;
+        data.lock()
+            .map(|contents| SharedBytes(Arc::clone(&contents)))
+            .map_err(|error| std::io::Error::other(error.to_string()))
+    }
+
+    fn not_found(path: &Path) -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No such file: {}", path.display()),
+        )
+    }
+}
+
+impl MemFileSystem {
+    fn has_parent_directory(&self, path: &Path) -> bool {
+        path.parent()
+            .map(|parent| parent.as_os_str().is_empty() || self.directories.contains(parent))
+            .unwrap_or(true)
+    }
+}
+
+impl Env for MemEnv {
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        let mut file_system = self.lock()?;
+        for ancestor in path.ancestors() {
+            if !ancestor.as_os_str().is_empty() {
+                file_system.directories.insert(ancestor.to_path_buf());
+            }
+        }
+        Ok(())
+    }
+
+    fn list_files(&self, directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let file_system = self.lock()?;
+        if !file_system.directories.contains(directory) {
+            return Err(Self::not_found(directory));
+        }
+
+        Ok(file_system
+            .files
+            .keys()
+            .filter(|path| path.parent() == Some(directory))
+            .cloned()
+            .collect())
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        let data = self.lock()?.files.get(path).cloned();
+        let data = data.ok_or_else(|| Self::not_found(path))?;
+        let contents = data
+            .lock()
+            .map_err(|error| std::io::Error::other(error.to_string()))?;
+        Ok(contents.to_vec())
+    }
+
+    fn open_readable(&self, path: &Path) -> std::io::Result<Box<dyn ReadableFile>> {
+        Ok(Box::new(Cursor::new(self.snapshot(path)?)))
+    }
+
+    fn file_size(&self, path: &Path) -> std::io::Result<u64> {
+        Ok(self.snapshot(path)?.bytes().len() as u64)
+    }
+
+    /// Files already live in memory, so mapping one hands out a snapshot of its contents
+    fn map_readable(&self, path: &Path) -> std::io::Result<Option<Arc<dyn MappedFile>>> {
+        Ok(Some(Arc::new(self.snapshot(path)?)))
+    }
+
+    fn create_writable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>> {
+        let mut file_system = self.lock()?;
+        if !file_system.has_parent_directory(path) {
+            return Err(Self::not_found(path));
+        }
+
+        let data = FileData::default();
+        file_system
+            .files
+            .insert(path.to_path_buf(), Arc::clone(&data));
+        Ok(Box::new(MemWritableFile { data }))
+    }
+
+    fn open_appendable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>> {
+        let data = self.lock()?.files.get(path).cloned();
+        let data = data.ok_or_else(|| Self::not_found(path))?;
+        Ok(Box::new(MemWritableFile { data }))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut file_system = self.lock()?;
+        if !file_system.has_parent_directory(to) {
+            return Err(Self::not_found(to));
+        }
+
+        let data = file_system
+            .files
+            .remove(from)
+            .ok_or_else(|| Self::not_found(from))?;
+        file_system.files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    /// Both paths share the same contents, so writes through either are seen by both
+    fn hard_link(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut file_system = self.lock()?;
+        if !file_system.has_parent_directory(to) {
+            return Err(Self::not_found(to));
+        }
+        if file_system.files.contains_key(to) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("File exists: {}", to.display()),
+            ));
+        }
+
+        let data = file_system
+            .files
+            .get(from)
+            .cloned()
+            .ok_or_else(|| Self::not_found(from))?;
+        file_system.files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        self.lock()?
+            .files
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| Self::not_found(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        let mut file_system = self.lock()?;
+        if file_system
+            .files
+            .keys()
+            .chain(&file_system.directories)
+            .any(|entry| entry.parent() == Some(path))
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::DirectoryNotEmpty,
+                format!("Directory not empty: {}", path.display()),
+            ));
+        }
+        if !file_system.directories.remove(path) {
+            return Err(Self::not_found(path));
+        }
+        Ok(())
+    }
+
+    fn sync_dir(&self, path: &Path) -> std::io::Result<()> {
+        if self.lock()?.directories.contains(path) {
+            Ok(())
+        } else {
+            Err(Self::not_found(path))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SharedBytes(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+impl MappedFile for SharedBytes {
+    fn bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+struct MemWritableFile {
+    data: FileData,
+}
+
+impl Write for MemWritableFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut contents = self
+            .data
+            .lock()
+            .map_err(|error| std::io::Error::other(error.to_string()))?;
+        Arc::make_mut(&mut contents).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WritableFile for MemWritableFile {
+    fn sync(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
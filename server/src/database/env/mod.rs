@@ -0,0 +1,92 @@
+use std::{
+    fmt::Debug,
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+mod disk_env;
+mod fault_injection_env;
+mod mem_env;
+
+pub use disk_env::DiskEnv;
+pub use fault_injection_env::FaultInjectionEnv;
+pub use mem_env::MemEnv;
+
+/// Filesystem operations used by the database, so the same engine can run against the real
+/// disk, purely in memory, or through a wrapper that injects failures
+pub trait Env: Send + Sync + Debug {
+    /// Creates the directory and any missing parents
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Returns the paths of the regular files directly inside the directory
+    fn list_files(&self, directory: &Path) -> std::io::Result<Vec<PathBuf>>;
+
+    /// Reads the whole file into memory
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open_readable(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn open_readable(&self, path: &Path) -> std::io::Result<Box<dyn ReadableFile>>;
+
+    /// Size of the file in bytes
+    fn file_size(&self, path: &Path) -> std::io::Result<u64> {
+        let mut file = self.open_readable(path)?;
+        file.seek(std::io::SeekFrom::End(0))
+    }
+
+    /// Maps the whole file into memory for reading. Returns None when the env cannot map
+    /// files, in which case callers fall back to `open_readable`
+    fn map_readable(&self, _path: &Path) -> std::io::Result<Option<Arc<dyn MappedFile>>> {
+        Ok(None)
+    }
+
+    /// Creates the file, truncating it if it already exists
+    fn create_writable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>>;
+
+    /// Opens an existing file so writes are added after its current contents
+    fn open_appendable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>>;
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+
+    /// Makes the file reachable at a second path without copying it. Fails when `to` exists.
+    /// Envs that cannot link files return `Unsupported` and callers copy the file instead
+    fn hard_link(&self, _from: &Path, _to: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Removes the file, treating one that is already gone as removed
+    fn remove_file_if_exists(&self, path: &Path) -> std::io::Result<()> {
+        match self.remove_file(path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes an empty directory. Envs without real directories return `Unsupported`
+    fn remove_dir(&self, _path: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+    }
+
+    /// Persists renames, creations and deletions made inside the directory
+    fn sync_dir(&self, path: &Path) -> std::io::Result<()>;
+}
+
+pub trait ReadableFile: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadableFile for T {}
+
+/// Read-only view of a whole file, such as a memory map. The contents stay readable after
+/// the file is removed, for as long as the view is alive
+pub trait MappedFile: Send + Sync + Debug {
+    fn bytes(&self) -> &[u8];
+}
+
+pub trait WritableFile: Write + Send {
+    /// Makes everything written so far durable
+    fn sync(&mut self) -> std::io::Result<()>;
+}
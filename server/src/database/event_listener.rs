@@ -0,0 +1,109 @@
+use std::{fmt::Debug, path::PathBuf, time::Duration};
+
+/// Receives notifications about work the engine does internally, e.g. to log it, alert on
+/// it or export metrics. Every method does nothing by default. Listeners are called on
+/// the thread doing the work while the database is busy with it, so they should return
+/// quickly and must not call back into the database
+pub trait EventListener: Send + Sync + Debug {
+    /// The in-memory table is about to be written out as a level 0 segment
+    fn on_flush_begin(&self, _info: &FlushBeginInfo) {}
+
+    fn on_flush_completed(&self, _info: &FlushJobInfo) {}
+
+    fn on_compaction_begin(&self, _info: &CompactionBeginInfo) {}
+
+    fn on_compaction_completed(&self, _info: &CompactionJobInfo) {}
+
+    /// A segment was added to the database. It is durable once the manifest is stored,
+    /// which happens before the flush, compaction or ingestion that created it completes
+    fn on_segment_created(&self, _info: &SegmentCreationInfo) {}
+
+    /// A segment that is no longer live had its files deleted
+    fn on_segment_deleted(&self, _info: &SegmentDeletionInfo) {}
+
+    /// Writes moved over to a new WAL file
+    fn on_wal_rotated(&self, _info: &WalRotationInfo) {}
+
+    /// A write was held up while the in-memory table it filled was flushed, along with any
+    /// compaction the flush triggered
+    fn on_write_stall(&self, _info: &WriteStallInfo) {}
+
+    /// Work the engine does on its own behalf failed. Flush and compaction errors are also
+    /// returned by the write that triggered them
+    fn on_background_error(&self, _reason: BackgroundErrorReason, _error: &std::io::Error) {}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlushBeginInfo {
+    /// Entries in the in-memory table, including tombstones
+    pub num_entries: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlushJobInfo {
+    pub segment_number: u64,
+    pub num_entries: u64,
+    /// Bytes of the segment's files and of the values moved to the value log
+    pub bytes_written: u64,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactionBeginInfo {
+    pub input_segments: Vec<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactionJobInfo {
+    pub input_segments: Vec<u64>,
+    /// Empty when every input entry was a tombstone or shadowed
+    pub output_segments: Vec<u64>,
+    /// Bytes of the input segment files
+    pub bytes_read: u64,
+    /// Bytes of the output segment's files
+    pub bytes_written: u64,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentCreationReason {
+    Flush,
+    Compaction,
+    Ingestion,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentCreationInfo {
+    pub path: PathBuf,
+    pub segment_number: u64,
+    pub level: usize,
+    pub reason: SegmentCreationReason,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentDeletionInfo {
+    pub path: PathBuf,
+    pub segment_number: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalRotationInfo {
+    pub previous_number: u64,
+    pub new_number: u64,
+    /// WAL files that backed the flushed table, which are deleted or archived next
+    pub retired: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteStallInfo {
+    pub duration: Duration,
+}
+
+/// Work that failed, as passed to `EventListener::on_background_error`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackgroundErrorReason {
+    Flush,
+    Compaction,
+    /// The scrubber found damaged files
+    Scrub,
+}
@@ -1,87 +1,1212 @@
-use std::fs::DirBuilder;
-use std::path::Path;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
-use crate::database::bloom_filter::BloomFilter;
-use crate::database::bloom_filter_registry::BloomFilterRegistry;
+use crate::database::atomic_file::{remove_temporary_files, write_atomically};
+use crate::database::block_cache::BlockCache;
+use crate::database::bloom_filter_registry::{BLOOM_FILTER_FILE_EXTENSION, BloomFilterRegistry};
+use crate::database::compaction_filter::{self, CompactionFilter};
 use crate::database::entry::Entry;
-use crate::database::index_entry::IndexEntry;
-use crate::database::index_file::IndexFile;
+use crate::database::env::Env;
+use crate::database::event_listener::{
+    BackgroundErrorReason, CompactionBeginInfo, CompactionJobInfo, EventListener, FlushBeginInfo,
+    FlushJobInfo, SegmentCreationInfo, SegmentCreationReason, SegmentDeletionInfo, WalRotationInfo,
+};
+use crate::database::filter_policy::Filter;
+use crate::database::hash::hash64;
+use crate::database::index_file::INDEX_FILE_EXTENSION;
 use crate::database::index_file_registry::IndexFileRegistry;
+use crate::database::manifest::Manifest;
 use crate::database::mem_table::MemTable;
-use crate::database::segment_file::SegmentFile;
+use crate::database::merging_iterator::{EntryIterator, MergingIterator};
+use crate::database::metadata_cache::{DEFAULT_METADATA_CACHE_CAPACITY, MetadataCache};
+use crate::database::options::DatabaseOptions;
+use crate::database::segment_file::{SEGMENT_FILE_EXTENSION, SegmentFile};
 use crate::database::segment_file_registry::SegmentFileRegistry;
+use crate::database::segment_properties::{PROPERTIES_FILE_EXTENSION, SegmentProperties};
+use crate::database::segment_properties_registry::SegmentPropertiesRegistry;
+use crate::database::statistics::{DatabaseStats, Statistics};
+use crate::database::table_cache::{
+    Block, BlockHandle, DEFAULT_TABLE_CACHE_CAPACITY, Table, TableCache,
+};
+use crate::database::value_log::{ValueLog, ValuePointer};
+use crate::database::verification::{self, FileReport};
 use crate::database::wal::Wal;
+use crate::database::wal_registry::WalRegistry;
 
 pub struct FileDirectory<P: AsRef<Path>> {
     directory: P,
+    manifest: Manifest,
     segment_file_registry: SegmentFileRegistry,
-    wal: Wal,
+    wal_registry: WalRegistry,
     bloom_filter_registry: BloomFilterRegistry,
     index_file_registry: IndexFileRegistry,
+    segment_properties_registry: SegmentPropertiesRegistry,
+    value_log: ValueLog,
+    block_cache: Arc<BlockCache>,
+    /// Sets this database's blocks apart from those of other databases sharing the cache
+    block_cache_id: u64,
+    table_cache: TableCache,
+    metadata_cache: Arc<MetadataCache>,
+    statistics: Statistics,
+    listeners: Vec<Arc<dyn EventListener>>,
+    compaction_filter: Option<Arc<dyn CompactionFilter>>,
+    /// Keep the filters and indexes of level 0 segments out of the metadata cache
+    pin_level0_metadata: bool,
+    level0_compaction_trigger: usize,
+    target_segment_size: usize,
+    env: Arc<dyn Env>,
 }
 
+/// A segment checked for ingestion
+struct ExternalFile<'a> {
+    path: &'a Path,
+    /// Properties without sequence numbers
+    properties: SegmentProperties,
+    /// Offset and key of every record
+    records: Vec<(u64, Vec<u8>)>,
+}
+
+/// Number of level 0 segments that triggers a compaction when not configured
+pub const DEFAULT_LEVEL0_COMPACTION_TRIGGER: usize = 4;
+/// Size a compaction output grows to before the next one is started, when not configured
+pub const DEFAULT_TARGET_SEGMENT_SIZE: usize = 2 * 1024 * 1024;
+/// Segments are compacted out of level 0 into this level, which is also the bottom one
+const BOTTOM_LEVEL: usize = 1;
+
 impl<P: AsRef<Path> + Clone> FileDirectory<P> {
-    pub fn new(directory: P) -> std::io::Result<Self> {
-        DirBuilder::new()
-            .recursive(true)
-            .create(directory.clone())?;
+    pub fn new(directory: P, options: &DatabaseOptions) -> std::io::Result<Self> {
+        let env = options.env();
+        env.create_dir_all(directory.as_ref())?;
+        remove_temporary_files(env.as_ref(), directory.as_ref())?;
 
-        let segment_file_registry = SegmentFileRegistry::new(directory.clone())?;
-        let wal = Wal::new(directory.clone())?;
-        let bloom_filter_registry = BloomFilterRegistry::new(&directory)?;
-        let index_file_registry = IndexFileRegistry::new(&directory)?;
+        // Directories written before the manifest existed only have their file names to go on
+        let stored_manifest = Manifest::load(env.as_ref(), directory.as_ref())?.unwrap_or_default();
+        if let Some(live_segments) = stored_manifest.segments() {
+            Self::remove_orphaned_segments(env.as_ref(), directory.as_ref(), live_segments)?;
+            Self::remove_orphaned_value_logs(
+                env.as_ref(),
+                directory.as_ref(),
+                stored_manifest.next_file_number(),
+            )?;
+        }
+
+        let segment_file_registry = SegmentFileRegistry::new(
+            Arc::clone(&env),
+            directory.clone(),
+            stored_manifest.segments(),
+        )?;
+        let metadata_cache = Arc::new(MetadataCache::new(
+            options
+                .metadata_cache_capacity
+                .unwrap_or(DEFAULT_METADATA_CACHE_CAPACITY),
+        ));
+        let mut bloom_filter_registry = BloomFilterRegistry::new(
+            Arc::clone(&env),
+            &directory,
+            options.filter_policies(),
+            options.prefix_extractor.clone(),
+            Arc::clone(&metadata_cache),
+        )?;
+        let mut index_file_registry = IndexFileRegistry::new(
+            Arc::clone(&env),
+            &directory,
+            Arc::clone(&metadata_cache),
+            options.use_mmap,
+        )?;
+        let mut segment_properties_registry =
+            SegmentPropertiesRegistry::new(Arc::clone(&env), &directory)?;
+        Self::upgrade_index_files(
+            env.as_ref(),
+            &segment_file_registry,
+            &mut index_file_registry,
+            &mut segment_properties_registry,
+        )?;
+        if options.pin_level0_metadata {
+            for segment_file in segment_file_registry.level_files(0) {
+                bloom_filter_registry.pin(segment_file.path());
+                index_file_registry.pin(segment_file)?;
+            }
+        }
+        let value_log = ValueLog::new(
+            Arc::clone(&env),
+            &directory,
+            options.value_log_threshold,
+            options.value_log_file_size,
+        )?;
+        let mut next_file_number = segment_file_registry
+            .max_number()
+            .max(value_log.max_number())
+            .map(|number| number + 1)
+            .unwrap_or_default()
+            .max(stored_manifest.next_file_number());
+
+        let wal_registry = WalRegistry::new(
+            Arc::clone(&env),
+            &directory,
+            stored_manifest.log_number(),
+            options.wal_archive_limit,
+            || {
+                next_file_number += 1;
+                next_file_number - 1
+            },
+        )?;
+        next_file_number = next_file_number.max(wal_registry.active_number() + 1);
 
         Ok(Self {
             directory: directory.clone(),
+            manifest: Manifest::new(next_file_number, stored_manifest.log_number())
+                .with_last_sequence(stored_manifest.last_sequence()),
             segment_file_registry,
             index_file_registry,
-            wal,
+            segment_properties_registry,
+            value_log,
+            wal_registry,
             bloom_filter_registry,
+            block_cache: options.block_cache.clone().unwrap_or_default(),
+            block_cache_id: BlockCache::new_cache_id(),
+            table_cache: TableCache::new(
+                options
+                    .table_cache_capacity
+                    .unwrap_or(DEFAULT_TABLE_CACHE_CAPACITY),
+                options.use_mmap,
+            ),
+            metadata_cache,
+            statistics: Statistics::default(),
+            listeners: options.listeners.clone(),
+            compaction_filter: options.compaction_filter.clone(),
+            pin_level0_metadata: options.pin_level0_metadata,
+            level0_compaction_trigger: options
+                .level0_compaction_trigger
+                .unwrap_or(DEFAULT_LEVEL0_COMPACTION_TRIGGER)
+                .max(1),
+            target_segment_size: options
+                .target_segment_size
+                .unwrap_or(DEFAULT_TARGET_SEGMENT_SIZE)
+                .max(1),
+            env,
         })
     }
 
+    /// Deletes the files of segments the manifest does not list. They were either compacted
+    /// away or written by a flush that never made it into the manifest, whose writes are
+    /// still in the WAL
+    fn remove_orphaned_segments(
+        env: &dyn Env,
+        directory: &Path,
+        live_segments: &BTreeMap<u64, usize>,
+    ) -> std::io::Result<()> {
+        for path in env.list_files(directory)? {
+            if let Some(number) = SegmentFile::number_from_path(&path)
+                && !live_segments.contains_key(&number)
+            {
+                tracing::warn!("Removing orphaned segment file {}", path.display());
+                env.remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes value log files numbered at or above the manifest's next file number. They
+    /// were started by a flush that never made it into the manifest, so no live segment
+    /// points into them and their values are still in the WAL
+    fn remove_orphaned_value_logs(
+        env: &dyn Env,
+        directory: &Path,
+        next_file_number: u64,
+    ) -> std::io::Result<()> {
+        for path in env.list_files(directory)? {
+            if ValueLog::file_number(&path).is_some_and(|number| number >= next_file_number) {
+                tracing::warn!("Removing orphaned value log file {}", path.display());
+                env.remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites index files written before the index format was versioned, once, so that
+    /// they are not rebuilt from their segment on every load. The new checksum is recorded in
+    /// the segment's properties so verification keeps passing. An index that cannot be
+    /// rewritten is left alone and rebuilt on load as any unreadable index is
+    fn upgrade_index_files(
+        env: &dyn Env,
+        segment_file_registry: &SegmentFileRegistry,
+        index_file_registry: &mut IndexFileRegistry,
+        segment_properties_registry: &mut SegmentPropertiesRegistry,
+    ) -> std::io::Result<()> {
+        for segment_file in segment_file_registry.files() {
+            match index_file_registry.upgrade(segment_file) {
+                Ok(false) => {}
+                Ok(true) => {
+                    let path = segment_file.path();
+                    if let Some(mut properties) = segment_properties_registry
+                        .get(path)
+                        .filter(|properties| properties.index_checksum.is_some())
+                        .cloned()
+                    {
+                        let index = env.read(&path.with_extension(INDEX_FILE_EXTENSION))?;
+                        properties.index_checksum = Some(hash64(&index));
+                        segment_properties_registry.store(path, properties)?;
+                    }
+                }
+                Err(error) => tracing::warn!(
+                    "Could not rewrite the index of {}: {}",
+                    segment_file.path().display(),
+                    error
+                ),
+            }
+        }
+        Ok(())
+    }
+
     pub fn wal(&mut self) -> &mut Wal {
-        &mut self.wal
+        self.wal_registry.active()
     }
 
-    pub fn get_bloom_filter(&self, path: &PathBuf) -> Option<&BloomFilter> {
+    pub fn wal_entries(&mut self) -> std::io::Result<impl Iterator<Item = std::io::Result<Entry>>> {
+        self.wal_registry.entries()
+    }
+
+    pub fn get_bloom_filter(&self, path: &Path) -> Option<Arc<dyn Filter>> {
         self.bloom_filter_registry.get(path)
     }
 
-    pub fn get_index_file(&self, path: &PathBuf) -> Option<&IndexFile> {
-        self.index_file_registry.get(path)
+    /// Whether the segment may hold keys starting with `prefix`, going by its key range and
+    /// its filter
+    pub fn prefix_may_match(&self, path: &Path, prefix: &[u8]) -> bool {
+        self.segment_properties(path)
+            .is_none_or(|properties| properties.may_contain_prefix(prefix))
+            && self.bloom_filter_registry.prefix_may_match(path, prefix)
+    }
+
+    /// Whether `key` falls inside the segment's key range. Segments without recorded
+    /// properties are never ruled out
+    pub fn may_contain_key(&self, path: &Path, key: &[u8]) -> bool {
+        self.segment_properties(path)
+            .is_none_or(|properties| properties.may_contain_key(key))
+    }
+
+    pub fn segment_properties(&self, path: &Path) -> Option<&SegmentProperties> {
+        self.segment_properties_registry.get(path)
+    }
+
+    /// Sequence number of the newest write stored in a segment
+    pub fn last_sequence(&self) -> u64 {
+        self.manifest.last_sequence()
     }
 
     pub fn segment_files(&self) -> impl Iterator<Item = &SegmentFile> {
         self.segment_file_registry.files()
     }
 
+    pub fn block_cache(&self) -> &BlockCache {
+        &self.block_cache
+    }
+
+    pub fn metadata_cache(&self) -> &MetadataCache {
+        &self.metadata_cache
+    }
+
+    /// Calls `event` with every listener, in the order they were configured
+    pub fn notify(&self, event: impl Fn(&dyn EventListener)) {
+        for listener in &self.listeners {
+            event(listener.as_ref());
+        }
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Statistics about the directory's files and the work done on them. The in-memory
+    /// table is left to the caller
+    pub fn stats(&self) -> std::io::Result<DatabaseStats> {
+        let mut stats = DatabaseStats {
+            segments_per_level: vec![0; BOTTOM_LEVEL + 1],
+            block_cache_hits: self.block_cache.hits(),
+            block_cache_misses: self.block_cache.misses(),
+            metadata_cache_hits: self.metadata_cache.hits(),
+            metadata_cache_misses: self.metadata_cache.misses(),
+            ..Default::default()
+        };
+        self.statistics.fill(&mut stats);
+        for level in self.segment_file_registry.levels().values() {
+            stats.segments_per_level[*level] += 1;
+        }
+
+        for path in self.env.list_files(self.directory.as_ref())? {
+            let file_type = match path.extension().or(path.file_name()) {
+                Some(file_type) => file_type.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let size = match self.env.file_size(&path) {
+                Ok(size) => size,
+                // Removed by a compaction while listing
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            };
+            *stats.disk_bytes.entry(file_type).or_default() += size;
+        }
+        Ok(stats)
+    }
+
+    /// Finds the block of the segment that would hold `key`, loading the segment's index
+    /// if it is neither pinned nor cached
+    pub fn find_block(
+        &self,
+        segment_file: &SegmentFile,
+        key: &[u8],
+    ) -> std::io::Result<Option<BlockHandle>> {
+        let index = self.index_file_registry.load(segment_file)?;
+        Ok(self.table(segment_file)?.find_block(&index, key))
+    }
+
+    /// Returns a block of the segment from the block cache, reading it from the file on a miss.
+    /// Blocks read from the file are only added to the cache when `fill_cache` is set.
+    /// Memory mapped segments are already served from the page cache and bypass the block cache
+    pub fn read_block(
+        &self,
+        segment_file: &SegmentFile,
+        block: BlockHandle,
+        fill_cache: bool,
+    ) -> std::io::Result<Block> {
+        let table = self.table(segment_file)?;
+        if table.is_mapped() {
+            return table.read_block(block);
+        }
+
+        let cache_key = segment_file
+            .number()
+            .map(|number| (self.block_cache_id, number, block.offset));
+        if let Some(data) = cache_key.and_then(|key| self.block_cache.get(key)) {
+            return Ok(Block::Read(data));
+        }
+
+        let data = table.read_block(block)?;
+        if fill_cache
+            && let Some(key) = cache_key
+            && let Block::Read(data) = &data
+        {
+            self.block_cache.insert(key, Arc::clone(data));
+        }
+        Ok(data)
+    }
+
+    fn table(&self, segment_file: &SegmentFile) -> std::io::Result<Arc<Table>> {
+        self.table_cache.get(segment_file)
+    }
+
+    /// Writes the table out as a new level 0 segment and moves writes over to a fresh WAL
+    /// file. The WAL files backing the table are only retired once the manifest records that
+    /// they have been flushed, so a crash part way through never loses writes and at worst
+    /// replays a table that already made it to disk
     pub fn store_segment(&mut self, map: MemTable) -> std::io::Result<()> {
-        let size = map.len();
-        let file_path = self.segment_file_registry.store_new(map.clone())?;
-        self.bloom_filter_registry.store(&file_path, &map)?;
-
-        let mut index_entries = Vec::with_capacity(size / 100);
-        if let Some(segment_file) = self.segment_file_registry.get(&file_path) {
-            for result in segment_file.entries(None)?.step_by(100) {
-                let (line_start_position, entry) = result?;
-                match entry {
-                    Entry::KeyValue { key, .. } => {
-                        index_entries.push(IndexEntry::new(key, line_start_position));
+        let result = self.flush_segment(map);
+        if let Err(error) = &result {
+            self.notify(|listener| {
+                listener.on_background_error(BackgroundErrorReason::Flush, error)
+            });
+        }
+        result
+    }
+
+    fn flush_segment(&mut self, map: MemTable) -> std::io::Result<()> {
+        let num_entries = map.len() as u64;
+        self.notify(|listener| listener.on_flush_begin(&FlushBeginInfo { num_entries }));
+        let start = Instant::now();
+        let last_sequence = self.last_sequence();
+        let (min_sequence, max_sequence) = map
+            .sequence_range()
+            .unwrap_or((last_sequence, last_sequence));
+        let (entries, separated_bytes) = self.separate_values(map)?;
+        let segment_number = self.allocate_file_number();
+        let segment_bytes = self.write_segment(
+            segment_number,
+            0,
+            SegmentProperties::new(min_sequence, max_sequence),
+            entries.into_iter().map(Ok),
+        )?;
+
+        let previous_wal_number = self.wal_registry.active_number();
+        let wal_number = self.allocate_file_number();
+        let retired_wal_files = self.wal_registry.rotate(wal_number)?;
+        self.store_manifest(wal_number, max_sequence)?;
+        self.notify_segment_created(segment_number, 0, SegmentCreationReason::Flush);
+        self.notify(|listener| {
+            listener.on_wal_rotated(&WalRotationInfo {
+                previous_number: previous_wal_number,
+                new_number: wal_number,
+                retired: retired_wal_files.clone(),
+            })
+        });
+        self.wal_registry.retire(retired_wal_files)?;
+
+        let duration = start.elapsed();
+        let bytes_written = segment_bytes + separated_bytes;
+        self.statistics.record_flush(duration, bytes_written);
+        self.notify(|listener| {
+            listener.on_flush_completed(&FlushJobInfo {
+                segment_number,
+                num_entries,
+                bytes_written,
+                duration,
+            })
+        });
+        Ok(())
+    }
+
+    /// Moves the values the value log takes out of the table's entries, leaving pointers to
+    /// them behind. The moved values are durable before any segment points at them.
+    /// Returns the entries along with the number of bytes moved
+    fn separate_values(&mut self, map: MemTable) -> std::io::Result<(Vec<Entry>, u64)> {
+        let mut entries = Vec::new();
+        let mut separated_bytes = 0;
+        for entry in map {
+            entries.push(match entry {
+                Entry::KeyValue { key, value } if self.value_log.should_separate(&value) => {
+                    if self.value_log.needs_new_file() {
+                        let number = self.allocate_file_number();
+                        self.value_log.create_file(number)?;
                     }
-                    Entry::Tombstone { key } => {
-                        index_entries.push(IndexEntry::new(key, line_start_position));
+                    let pointer = self.value_log.append(&value)?;
+                    separated_bytes += pointer.len;
+                    Entry::KeyValue {
+                        key,
+                        value: pointer.into(),
                     }
                 }
+                entry => entry,
+            });
+        }
+        self.value_log.sync()?;
+        Ok((entries, separated_bytes))
+    }
+
+    /// Returns the value a segment value points to in the value log, or None when the
+    /// segment holds the value itself
+    pub fn read_value_pointer(&self, value: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        ValuePointer::decode(value)
+            .map(|pointer| self.value_log.read(pointer?))
+            .transpose()
+    }
+
+    /// Oldest value log file that garbage collection can reclaim
+    pub fn oldest_value_log_file(&self) -> Option<u64> {
+        self.value_log.oldest_sealed_file()
+    }
+
+    /// Returns the keys whose newest version in the segments points into the value log
+    /// file, along with their values
+    pub fn live_values(&self, file_number: u64) -> std::io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        // Segments are listed newest first, which is the precedence the merge expects
+        let sources = self
+            .segment_file_registry
+            .files()
+            .map(|segment_file| {
+                let entries = segment_file.entries(None)?;
+                Ok(Box::new(entries.map(|entry| entry.map(|(_, entry)| entry))) as EntryIterator)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let mut live_values = Vec::new();
+        for entry in MergingIterator::new(sources) {
+            let Entry::KeyValue { key, value } = entry? else {
+                continue;
+            };
+            if let Some(pointer) = ValuePointer::decode(&value) {
+                let pointer = pointer?;
+                if pointer.file_number == file_number {
+                    live_values.push((key, self.value_log.read(pointer)?));
+                }
+            }
+        }
+        Ok(live_values)
+    }
+
+    /// Deletes a value log file whose live values have all been written elsewhere
+    pub fn remove_value_log_file(&mut self, file_number: u64) -> std::io::Result<()> {
+        self.value_log.remove_file(file_number)
+    }
+
+    /// Compacts level 0 once it holds `level0_compaction_trigger` segments
+    pub fn compact_if_needed(&mut self) -> std::io::Result<()> {
+        if self.segment_file_registry.level_files(0).count() >= self.level0_compaction_trigger {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Merges the level 0 segments with the bottom level segments whose key ranges overlap
+    /// theirs, keeping only the newest version of each key. Every older version of a key in
+    /// level 0 is among the inputs, so tombstones have nothing left to shadow and are
+    /// dropped. The remaining entries go through the compaction filter, if one is
+    /// configured, and are split into bottom level segments of about `target_segment_size`
+    /// bytes. The inputs are deleted once the manifest lists the outputs in their place
+    pub fn compact(&mut self) -> std::io::Result<()> {
+        let result = self.compact_segments();
+        if let Err(error) = &result {
+            self.notify(|listener| {
+                listener.on_background_error(BackgroundErrorReason::Compaction, error)
+            });
+        }
+        result
+    }
+
+    fn compact_segments(&mut self) -> std::io::Result<()> {
+        let input_files = self.compaction_inputs();
+        if input_files.is_empty() {
+            return Ok(());
+        }
+        let inputs: Vec<u64> = input_files
+            .iter()
+            .filter_map(|segment_file| segment_file.number())
+            .collect();
+        self.notify(|listener| {
+            listener.on_compaction_begin(&CompactionBeginInfo {
+                input_segments: inputs.clone(),
+            })
+        });
+        let start = Instant::now();
+        let mut bytes_read = 0;
+        for segment_file in &input_files {
+            bytes_read += self.env.file_size(segment_file.path())?;
+        }
+        let input_properties: Vec<_> = input_files
+            .iter()
+            .map(|segment_file| self.segment_properties(segment_file.path()))
+            .collect();
+        let (entry_count, tombstone_count) =
+            input_properties
+                .iter()
+                .flatten()
+                .fold((0, 0), |(entries, tombstones), properties| {
+                    (
+                        entries + properties.num_entries,
+                        tombstones + properties.num_tombstones,
+                    )
+                });
+        // Inputs without properties could hold any write up to the last flushed one
+        let min_sequence = input_properties
+            .iter()
+            .map(|properties| properties.map_or(0, |properties| properties.min_sequence))
+            .min()
+            .unwrap_or_default();
+        let max_sequence = input_properties
+            .iter()
+            .map(|properties| {
+                properties.map_or(self.last_sequence(), |properties| properties.max_sequence)
+            })
+            .max()
+            .unwrap_or_default();
+        tracing::info!(
+            "Compacting {} segments holding {} entries, {} of them tombstones",
+            inputs.len(),
+            entry_count,
+            tombstone_count
+        );
+
+        // Inputs are listed level 0 first and newest first within a level, which is the
+        // precedence the merge expects
+        let sources = input_files
+            .iter()
+            .map(|segment_file| {
+                let entries = segment_file.entries(None)?;
+                Ok(Box::new(entries.map(|entry| entry.map(|(_, entry)| entry))) as EntryIterator)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let compaction_filter = self.compaction_filter.clone();
+        let value_log = self.value_log.reader();
+        let mut merged = MergingIterator::new(sources)
+            .filter(|entry| !matches!(entry, Ok(Entry::Tombstone { .. })))
+            .filter_map(|entry| match &compaction_filter {
+                Some(filter) => entry
+                    .and_then(|entry| compaction_filter::apply(filter.as_ref(), &value_log, entry))
+                    .transpose(),
+                None => Some(entry),
+            })
+            .peekable();
+
+        let mut bytes_written = 0;
+        let mut outputs = Vec::new();
+        while merged.peek().is_some() {
+            let output_number = self.allocate_file_number();
+            let target_segment_size = self.target_segment_size;
+            let mut output_size = 0;
+            let output_entries = std::iter::from_fn(|| {
+                if output_size >= target_segment_size {
+                    return None;
+                }
+                let entry = merged.next()?;
+                output_size += match &entry {
+                    Ok(Entry::KeyValue { key, value }) => key.len() + value.len() + 2,
+                    Ok(Entry::Tombstone { key }) => key.len() + 1,
+                    Err(_) => 0,
+                };
+                Some(entry)
+            });
+            bytes_written += self.write_segment(
+                output_number,
+                BOTTOM_LEVEL,
+                SegmentProperties::new(min_sequence, max_sequence),
+                output_entries,
+            )?;
+            outputs.push(output_number);
+        }
+
+        let removed = self.segment_file_registry.remove(&inputs);
+        self.store_manifest(self.manifest.log_number(), self.last_sequence())?;
+        for &output_number in &outputs {
+            self.notify_segment_created(
+                output_number,
+                BOTTOM_LEVEL,
+                SegmentCreationReason::Compaction,
+            );
+        }
+
+        for segment_file in removed {
+            tracing::info!(
+                "Deleting compacted segment {}",
+                segment_file.path().display()
+            );
+            self.env.remove_file_if_exists(segment_file.path())?;
+            self.bloom_filter_registry.remove(segment_file.path())?;
+            self.index_file_registry.remove(segment_file.path())?;
+            self.segment_properties_registry
+                .remove(segment_file.path())?;
+            if let Some(segment_number) = segment_file.number() {
+                self.notify(|listener| {
+                    listener.on_segment_deleted(&SegmentDeletionInfo {
+                        path: segment_file.path().clone(),
+                        segment_number,
+                    })
+                });
             }
         }
+        for &number in &inputs {
+            self.table_cache.evict(number)?;
+        }
+        let duration = start.elapsed();
+        self.statistics
+            .record_compaction(duration, bytes_read, bytes_written);
+        self.notify(|listener| {
+            listener.on_compaction_completed(&CompactionJobInfo {
+                input_segments: inputs.clone(),
+                output_segments: outputs.clone(),
+                bytes_read,
+                bytes_written,
+                duration,
+            })
+        });
+        Ok(())
+    }
+
+    /// Segments the next compaction merges: all of level 0 and the bottom level segments
+    /// overlapping its key range. Segments without properties could hold any key, so they
+    /// are always included
+    fn compaction_inputs(&self) -> Vec<&SegmentFile> {
+        let level0: Vec<&SegmentFile> = self.segment_file_registry.level_files(0).collect();
+        if level0.is_empty() {
+            return Vec::new();
+        }
+        let level0_properties: Option<Vec<&SegmentProperties>> = level0
+            .iter()
+            .map(|segment_file| self.segment_properties(segment_file.path()))
+            .collect();
+        let key_range = level0_properties.map(|properties| {
+            (
+                properties
+                    .iter()
+                    .map(|properties| &properties.min_key)
+                    .min(),
+                properties
+                    .iter()
+                    .map(|properties| &properties.max_key)
+                    .max(),
+            )
+        });
+
+        let overlapping =
+            self.segment_file_registry
+                .level_files(BOTTOM_LEVEL)
+                .filter(|segment_file| {
+                    match (key_range, self.segment_properties(segment_file.path())) {
+                        (Some((Some(min_key), Some(max_key))), Some(properties)) => {
+                            &properties.min_key <= max_key && min_key <= &properties.max_key
+                        }
+                        _ => true,
+                    }
+                });
+        level0.iter().copied().chain(overlapping).collect()
+    }
+
+    fn notify_segment_created(
+        &self,
+        segment_number: u64,
+        level: usize,
+        reason: SegmentCreationReason,
+    ) {
+        let path = self.segment_file_registry.path(segment_number);
+        self.notify(|listener| {
+            listener.on_segment_created(&SegmentCreationInfo {
+                path: path.clone(),
+                segment_number,
+                level,
+                reason,
+            })
+        });
+    }
+
+    /// Writes a segment together with its filter, index and properties, which start out as
+    /// `properties` and account for every entry written. It only becomes durable once the
+    /// manifest is stored. Returns the number of bytes written across the files
+    fn write_segment(
+        &mut self,
+        segment_number: u64,
+        level: usize,
+        mut properties: SegmentProperties,
+        entries: impl IntoIterator<Item = std::io::Result<Entry>>,
+    ) -> std::io::Result<u64> {
+        let mut keys = Vec::new();
+        let entries = entries.into_iter().inspect(|entry| {
+            if let Ok(entry) = entry {
+                keys.push(entry.key().to_vec());
+                properties.add(entry);
+            }
+        });
+        let file_path = self
+            .segment_file_registry
+            .store_new(segment_number, level, entries)?;
+
+        let pin = self.pin_level0_metadata && level == 0;
+        let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+        self.bloom_filter_registry
+            .store(&file_path, level, &keys, pin)?;
+
+        let index_entries = match self.segment_file_registry.get(&file_path) {
+            Some(segment_file) => segment_file.build_index()?,
+            None => Vec::new(),
+        };
 
         if !index_entries.is_empty() {
             self.index_file_registry
                 .store_new(file_path.clone(), index_entries)?;
         }
+        if pin && let Some(segment_file) = self.segment_file_registry.get(&file_path) {
+            self.index_file_registry.pin(segment_file)?;
+        }
+
+        properties.segment_checksum = self.checksum(&file_path)?;
+        properties.index_checksum =
+            self.checksum(&file_path.with_extension(INDEX_FILE_EXTENSION))?;
+        properties.filter_checksum =
+            self.checksum(&file_path.with_extension(BLOOM_FILTER_FILE_EXTENSION))?;
+        self.segment_properties_registry
+            .store(&file_path, properties)?;
+
+        let mut bytes_written = 0;
+        for extension in [
+            SEGMENT_FILE_EXTENSION,
+            INDEX_FILE_EXTENSION,
+            BLOOM_FILTER_FILE_EXTENSION,
+            PROPERTIES_FILE_EXTENSION,
+        ] {
+            match self.env.file_size(&file_path.with_extension(extension)) {
+                Ok(size) => bytes_written += size,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(bytes_written)
+    }
+
+    /// `hash64` of the file's contents, or None when it does not exist
+    fn checksum(&self, path: &Path) -> std::io::Result<Option<u64>> {
+        match self.env.read(path) {
+            Ok(data) => Ok(Some(hash64(&data))),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Checks the files of the live segment with the given number, returning None when it
+    /// is no longer live. The segment must hold strictly sorted, readable records matching
+    /// its properties, and its index and filter must agree with those records. Every file
+    /// with recorded checksums must match them
+    pub fn verify_segment(&self, segment_number: u64) -> Option<Vec<FileReport>> {
+        let segment_file = self
+            .segment_file_registry
+            .files()
+            .find(|segment_file| segment_file.number() == Some(segment_number))?;
+        let path = segment_file.path();
+        let properties = self.segment_properties(path);
+        let mut reports = Vec::new();
+
+        let mut segment_report = FileReport::new(path.clone());
+        let records = match self.env.read(path) {
+            Ok(data) => verification::verify_segment(&data, properties, &mut segment_report),
+            Err(error) => {
+                segment_report
+                    .problems
+                    .push(format!("unreadable: {}", error));
+                Vec::new()
+            }
+        };
+        reports.push(segment_report);
+
+        let index_path = path.with_extension(INDEX_FILE_EXTENSION);
+        if let Some(data) = self.read_if_exists(&index_path, &mut reports) {
+            let mut report = FileReport::new(index_path);
+            match segment_file.build_index() {
+                Ok(expected) => verification::verify_index(
+                    &data,
+                    expected,
+                    &records,
+                    properties.and_then(|properties| properties.index_checksum),
+                    &mut report,
+                ),
+                Err(error) => report
+                    .problems
+                    .push(format!("index cannot be checked: {}", error)),
+            }
+            reports.push(report);
+        }
+
+        let filter_path = path.with_extension(BLOOM_FILTER_FILE_EXTENSION);
+        if let Some(data) = self.read_if_exists(&filter_path, &mut reports) {
+            let mut report = FileReport::new(filter_path);
+            verification::verify_filter(
+                &data,
+                self.get_bloom_filter(path).as_deref(),
+                &records,
+                properties.and_then(|properties| properties.filter_checksum),
+                &mut report,
+            );
+            reports.push(report);
+        }
+
+        Some(reports)
+    }
+
+    /// Reads a file that segments may go without, recording a report when it exists but
+    /// cannot be read
+    fn read_if_exists(&self, path: &Path, reports: &mut Vec<FileReport>) -> Option<Vec<u8>> {
+        match self.env.read(path) {
+            Ok(data) => Some(data),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => {
+                let mut report = FileReport::new(path.to_path_buf());
+                report.problems.push(format!("unreadable: {}", error));
+                reports.push(report);
+                None
+            }
+        }
+    }
+
+    /// Numbers of the live segments, in lookup order
+    pub fn segment_numbers(&self) -> Vec<u64> {
+        self.segment_files()
+            .filter_map(SegmentFile::number)
+            .collect()
+    }
+
+    /// Creates an openable copy of the directory in `target`, which must not exist yet.
+    /// Segments, their side files and sealed value log files never change once written, so
+    /// they are hard linked. The WAL files holding unflushed writes are copied, and the
+    /// manifest is written last, so a checkpoint without a manifest is incomplete
+    pub fn checkpoint(&mut self, target: &Path) -> std::io::Result<()> {
+        if self.env.list_files(target).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("Checkpoint directory {} already exists", target.display()),
+            ));
+        }
+        self.env.create_dir_all(target)?;
+        tracing::info!("Creating checkpoint in {}", target.display());
+
+        for path in self.immutable_files()? {
+            let Some(file_name) = path.file_name() else {
+                continue;
+            };
+            match self.link_or_copy(&path, &target.join(file_name)) {
+                Ok(()) => {}
+                // Side files are optional
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error),
+            }
+        }
+        self.checkpoint_mutable_files(target)
+    }
+
+    /// Paths of the live files that never change once written: the segments, their side
+    /// files, which may not exist, and the value log files, which are sealed first
+    pub(crate) fn immutable_files(&mut self) -> std::io::Result<Vec<PathBuf>> {
+        let mut immutable_files = Vec::new();
+        for segment_file in self.segment_file_registry.files() {
+            let path = segment_file.path();
+            immutable_files.push(path.clone());
+            for extension in [
+                INDEX_FILE_EXTENSION,
+                BLOOM_FILTER_FILE_EXTENSION,
+                PROPERTIES_FILE_EXTENSION,
+            ] {
+                immutable_files.push(path.with_extension(extension));
+            }
+        }
+        // Later values go to a new file, leaving every existing one immutable
+        self.value_log.seal()?;
+        immutable_files.extend(self.value_log.file_paths());
+        Ok(immutable_files)
+    }
 
+    /// Copies the WAL files holding unflushed writes into `target` and then writes the
+    /// manifest, completing a checkpoint whose immutable files are already in place
+    pub(crate) fn checkpoint_mutable_files(&mut self, target: &Path) -> std::io::Result<()> {
+        self.wal_registry.active().sync()?;
+        for path in self.wal_registry.live_paths() {
+            if let Some(file_name) = path.file_name() {
+                self.copy_file(&path, &target.join(file_name))?;
+            }
+        }
+
+        self.env.sync_dir(target)?;
+        Manifest::new(self.manifest.next_file_number(), self.manifest.log_number())
+            .with_last_sequence(self.manifest.last_sequence())
+            .with_segments(self.segment_file_registry.levels().clone())
+            .store(self.env.as_ref(), target)
+    }
+
+    /// Hard links `from` to `to`, copying it instead when the env or filesystem cannot
+    pub(crate) fn link_or_copy(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        match self.env.hard_link(from, to) {
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    std::io::ErrorKind::Unsupported | std::io::ErrorKind::CrossesDevices
+                ) =>
+            {
+                self.copy_file(from, to)
+            }
+            result => result,
+        }
+    }
+
+    fn copy_file(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let data = self.env.read(from)?;
+        write_atomically(self.env.as_ref(), to, |file| file.write_all(&data))
+    }
+
+    /// Adds segments written outside the database, e.g. by `SegmentWriter`, as holding the
+    /// writes numbered `sequence`, which must be newer than every write in the directory.
+    /// Every file is checked before any is added, and their key ranges must not overlap each
+    /// other. Segments that overlap no live segment go straight to the bottom level and the
+    /// rest to level 0. The segments are hard linked into the directory where possible, along
+    /// with their index and filter when those match the segment; otherwise they are rebuilt.
+    /// Nothing is visible after a crash until the manifest is stored at the end
+    pub fn ingest_external_files(&mut self, paths: &[&Path], sequence: u64) -> std::io::Result<()> {
+        let mut externals = paths
+            .iter()
+            .map(|path| self.check_external_file(path))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        externals.sort_by(|a, b| a.properties.min_key.cmp(&b.properties.min_key));
+        if externals
+            .windows(2)
+            .any(|pair| pair[0].properties.max_key >= pair[1].properties.min_key)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Key ranges of the ingested files overlap",
+            ));
+        }
+
+        let mut ingested = Vec::new();
+        for ExternalFile {
+            path,
+            properties,
+            records,
+        } in externals
+        {
+            let overlaps = self.segment_file_registry.files().any(|segment_file| {
+                self.segment_properties(segment_file.path())
+                    .is_none_or(|live| {
+                        live.min_key <= properties.max_key && properties.min_key <= live.max_key
+                    })
+            });
+            let level = if overlaps { 0 } else { BOTTOM_LEVEL };
+            let number = self.allocate_file_number();
+            let target = self.segment_file_registry.path(number);
+            tracing::info!(
+                "Ingesting {} as {} into level {}",
+                path.display(),
+                target.display(),
+                level
+            );
+
+            self.link_or_copy(path, &target)?;
+            for extension in [INDEX_FILE_EXTENSION, BLOOM_FILTER_FILE_EXTENSION] {
+                match self.link_or_copy(
+                    &path.with_extension(extension),
+                    &target.with_extension(extension),
+                ) {
+                    Ok(()) => {}
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(error) => return Err(error),
+                }
+            }
+            self.segment_file_registry.insert(number, level)?;
+            self.install_external_side_files(&target, level, &records)?;
+
+            let pin = self.pin_level0_metadata && level == 0;
+            if pin && let Some(segment_file) = self.segment_file_registry.get(&target) {
+                self.bloom_filter_registry.pin(&target);
+                self.index_file_registry.pin(segment_file)?;
+            }
+
+            let properties = SegmentProperties {
+                min_sequence: sequence,
+                max_sequence: sequence,
+                index_checksum: self.checksum(&target.with_extension(INDEX_FILE_EXTENSION))?,
+                filter_checksum: self
+                    .checksum(&target.with_extension(BLOOM_FILTER_FILE_EXTENSION))?,
+                ..properties
+            };
+            self.segment_properties_registry
+                .store(&target, properties)?;
+            ingested.push((number, level));
+        }
+
+        self.store_manifest(self.manifest.log_number(), sequence)?;
+        for (number, level) in ingested {
+            self.notify_segment_created(number, level, SegmentCreationReason::Ingestion);
+        }
+        Ok(())
+    }
+
+    /// Reads and checks a segment to be ingested
+    fn check_external_file<'a>(&self, path: &'a Path) -> std::io::Result<ExternalFile<'a>> {
+        let invalid = |problem: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Cannot ingest {}: {}", path.display(), problem),
+            )
+        };
+        if !SegmentFile::is_segment_file(path) {
+            return Err(invalid("not a segment file"));
+        }
+
+        let data = self.env.read(path)?;
+        let stored_properties = match self
+            .env
+            .read(&path.with_extension(PROPERTIES_FILE_EXTENSION))
+        {
+            Ok(data) => Some(SegmentProperties::try_from(data.as_slice())?),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error),
+        };
+        let mut report = FileReport::new(path.to_path_buf());
+        let records = verification::verify_segment(&data, stored_properties.as_ref(), &mut report);
+        if let Some(problem) = report.problems.first() {
+            return Err(invalid(problem));
+        }
+        if records.is_empty() {
+            return Err(invalid("it holds no records"));
+        }
+
+        let mut properties = SegmentProperties::new(0, 0);
+        for line in data.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+            let entry = Entry::from(line);
+            if let Entry::KeyValue { value, .. } = &entry
+                && ValuePointer::decode(value).is_some()
+            {
+                return Err(invalid("it points into a value log"));
+            }
+            properties.add(&entry);
+        }
+        properties.segment_checksum = Some(hash64(&data));
+        Ok(ExternalFile {
+            path,
+            properties,
+            records,
+        })
+    }
+
+    /// Serves the index and filter linked in with an ingested segment, rebuilding them when
+    /// they are missing or do not match the segment's records
+    fn install_external_side_files(
+        &mut self,
+        path: &Path,
+        level: usize,
+        records: &[(u64, Vec<u8>)],
+    ) -> std::io::Result<()> {
+        let build_index = |registry: &SegmentFileRegistry| match registry.get(path) {
+            Some(segment_file) => segment_file.build_index(),
+            None => Ok(Vec::new()),
+        };
+
+        let index_path = path.with_extension(INDEX_FILE_EXTENSION);
+        let mut report = FileReport::new(index_path.clone());
+        match self.env.read(&index_path) {
+            Ok(data) => verification::verify_index(
+                &data,
+                build_index(&self.segment_file_registry)?,
+                records,
+                None,
+                &mut report,
+            ),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                report.problems.push("missing".to_string())
+            }
+            Err(error) => return Err(error),
+        }
+        if report.is_healthy() {
+            self.index_file_registry.insert(path)?;
+        } else {
+            tracing::warn!("Rebuilding index of ingested segment {}", path.display());
+            self.env.remove_file_if_exists(&index_path)?;
+            let index_entries = build_index(&self.segment_file_registry)?;
+            self.index_file_registry
+                .store_new(path.to_path_buf(), index_entries)?;
+        }
+
+        let filter_path = path.with_extension(BLOOM_FILTER_FILE_EXTENSION);
+        self.bloom_filter_registry.insert(path);
+        let mut report = FileReport::new(filter_path.clone());
+        match self.env.read(&filter_path) {
+            Ok(data) => verification::verify_filter(
+                &data,
+                self.bloom_filter_registry.get(path).as_deref(),
+                records,
+                None,
+                &mut report,
+            ),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                report.problems.push("missing".to_string())
+            }
+            Err(error) => return Err(error),
+        }
+        if !report.is_healthy() {
+            tracing::warn!("Rebuilding filter of ingested segment {}", path.display());
+            self.bloom_filter_registry.remove(path)?;
+            let keys: Vec<&[u8]> = records.iter().map(|(_, key)| key.as_slice()).collect();
+            self.bloom_filter_registry
+                .store(path, level, &keys, false)?;
+        }
         Ok(())
     }
+
+    fn store_manifest(&mut self, log_number: u64, last_sequence: u64) -> std::io::Result<()> {
+        self.manifest = Manifest::new(self.manifest.next_file_number(), log_number)
+            .with_last_sequence(last_sequence)
+            .with_segments(self.segment_file_registry.levels().clone());
+        self.manifest
+            .store(self.env.as_ref(), self.directory.as_ref())
+    }
+
+    fn allocate_file_number(&mut self) -> u64 {
+        let number = self.manifest.next_file_number();
+        self.manifest = Manifest::new(number + 1, self.manifest.log_number())
+            .with_last_sequence(self.manifest.last_sequence());
+        number
+    }
 }
@@ -0,0 +1,111 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::database::{
+    blocked_bloom_filter::BlockedBloomFilter,
+    bloom_filter::{BloomFilter, BloomFilterSize},
+    xor_filter::XorFilter,
+};
+
+/// Filter over the keys of a single segment, letting lookups skip segments that
+/// definitely do not hold a key
+pub trait Filter: Send + Sync {
+    /// Returns false if the key is definitely not present and true if it might be
+    fn might_contain(&self, key: &[u8]) -> bool;
+
+    /// Bytes written to the segment's filter file
+    fn serialize(&self) -> Vec<u8>;
+}
+
+/// Decides which filter is built for new segments and how filter files are read back.
+/// Every policy tags its files, so a file is always read by the policy that wrote it even
+/// after the configured policies change
+pub trait FilterPolicy: Send + Sync + Debug {
+    /// Builds a filter over the keys of a new segment
+    fn build(&self, keys: &[&[u8]]) -> Box<dyn Filter>;
+
+    /// Reads back a filter written by this policy. Returns None when `data` was written by
+    /// a different policy
+    fn load(&self, data: &[u8]) -> Option<std::io::Result<Box<dyn Filter>>>;
+}
+
+/// Classic bloom filter, the default policy
+#[derive(Debug, Default)]
+pub struct BloomFilterPolicy {
+    size: BloomFilterSize,
+}
+
+impl BloomFilterPolicy {
+    pub fn new(size: BloomFilterSize) -> Self {
+        Self { size }
+    }
+}
+
+impl FilterPolicy for BloomFilterPolicy {
+    fn build(&self, keys: &[&[u8]]) -> Box<dyn Filter> {
+        let mut filter = BloomFilter::with_size(keys.len(), self.size);
+        for key in keys {
+            filter.insert(key);
+        }
+        Box::new(filter)
+    }
+
+    fn load(&self, data: &[u8]) -> Option<std::io::Result<Box<dyn Filter>>> {
+        BloomFilter::is_versioned(data)
+            .then(|| BloomFilter::deserialize(data).map(|filter| Box::new(filter) as _))
+    }
+}
+
+/// Bloom filter that keeps all probes of a key inside one cache line, so a lookup costs a
+/// single cache miss at the price of a slightly higher false positive rate
+#[derive(Debug, Default)]
+pub struct BlockedBloomFilterPolicy {
+    size: BloomFilterSize,
+}
+
+impl BlockedBloomFilterPolicy {
+    pub fn new(size: BloomFilterSize) -> Self {
+        Self { size }
+    }
+}
+
+impl FilterPolicy for BlockedBloomFilterPolicy {
+    fn build(&self, keys: &[&[u8]]) -> Box<dyn Filter> {
+        let mut filter = BlockedBloomFilter::with_size(keys.len(), self.size);
+        for key in keys {
+            filter.insert(key);
+        }
+        Box::new(filter)
+    }
+
+    fn load(&self, data: &[u8]) -> Option<std::io::Result<Box<dyn Filter>>> {
+        BlockedBloomFilter::is_blocked_bloom_filter(data)
+            .then(|| BlockedBloomFilter::deserialize(data).map(|filter| Box::new(filter) as _))
+    }
+}
+
+/// Xor filter with 8-bit fingerprints: about 9.8 bits per key for a 0.4% false positive
+/// rate. The whole key set has to be known up front, which suits the large, rarely
+/// rewritten segments of the bottom level
+#[derive(Debug, Default)]
+pub struct XorFilterPolicy;
+
+impl FilterPolicy for XorFilterPolicy {
+    fn build(&self, keys: &[&[u8]]) -> Box<dyn Filter> {
+        Box::new(XorFilter::build(keys))
+    }
+
+    fn load(&self, data: &[u8]) -> Option<std::io::Result<Box<dyn Filter>>> {
+        XorFilter::is_xor_filter(data)
+            .then(|| XorFilter::deserialize(data).map(|filter| Box::new(filter) as _))
+    }
+}
+
+/// Policies able to read the filter files written by this crate, whatever the
+/// configured policies are
+pub fn builtin_policies() -> Vec<Arc<dyn FilterPolicy>> {
+    vec![
+        Arc::new(BloomFilterPolicy::default()),
+        Arc::new(BlockedBloomFilterPolicy::default()),
+        Arc::new(XorFilterPolicy),
+    ]
+}
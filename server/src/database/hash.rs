@@ -0,0 +1,46 @@
+const M: u64 = 0xc6a4_a793_5bd1_e995;
+const R: u32 = 47;
+
+/// 64-bit hash in the style of MurmurHash64A: keys are consumed 8 bytes at a time and the
+/// result goes through a final avalanche so every input bit affects every output bit
+pub fn hash64(key: &[u8]) -> u64 {
+    const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+    let mut hash = SEED ^ (key.len() as u64).wrapping_mul(M);
+
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = read_u64(chunk);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        hash ^= k;
+        hash = hash.wrapping_mul(M);
+    }
+
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
+        let mut tail = [0; 8];
+        tail[..remainder.len()].copy_from_slice(remainder);
+        hash ^= u64::from_le_bytes(tail);
+        hash = hash.wrapping_mul(M);
+    }
+
+    mix64(hash)
+}
+
+/// Final avalanche of `hash64`, also used to derive independent hashes from a seeded one
+pub fn mix64(mut hash: u64) -> u64 {
+    hash ^= hash >> R;
+    hash = hash.wrapping_mul(M);
+    hash ^= hash >> R;
+    hash
+}
+
+/// Reads a little-endian u64 from the first 8 bytes of `bytes`
+pub fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buffer = [0; 8];
+    buffer.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(buffer)
+}
@@ -1,3 +1,10 @@
+/// Written at the start of versioned index files. Index files from older releases start
+/// straight with a key and are rewritten in this format when the database opens
+const FORMAT_MAGIC: &[u8; 7] = b"KVINDEX";
+const FORMAT_VERSION: u8 = 1;
+/// Length of the offset stored after each key
+const OFFSET_LEN: usize = std::mem::size_of::<u64>();
+
 pub struct IndexEntry {
     key: Vec<u8>,
     offset: u64,
@@ -17,31 +24,136 @@ impl IndexEntry {
     }
 }
 
-impl TryFrom<Vec<u8>> for IndexEntry {
-    type Error = std::io::Error;
+impl IndexEntry {
+    /// Encodes the contents of an index file: the header and then one record per entry
+    pub fn encode_all(entries: impl IntoIterator<Item = Self>) -> Vec<u8> {
+        let mut data = Vec::from(FORMAT_MAGIC.as_slice());
+        data.push(FORMAT_VERSION);
+        for entry in entries {
+            data.extend_from_slice(&Vec::<u8>::from(entry));
+        }
+        data
+    }
+
+    /// Whether `data` starts like an index file in the versioned format
+    pub fn is_versioned(data: &[u8]) -> bool {
+        data.starts_with(FORMAT_MAGIC)
+    }
 
-    fn try_from(mut value: Vec<u8>) -> Result<Self, Self::Error> {
-        let len = value.len();
-        if len < 8 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Vec too short to contain offset",
-            ));
+    /// Parses an index file. After the header come the records: the key, a space, the
+    /// offset as 8 little-endian bytes and a newline. Offset bytes can look like a newline,
+    /// so the data is not split into lines; the offset is taken to be the 8 bytes after the
+    /// key instead. Unversioned files are rejected since their records cannot be told apart
+    /// reliably
+    pub fn parse_all(data: &[u8]) -> std::io::Result<Vec<Self>> {
+        let invalid = |problem: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, problem);
+        let mut data = match data.strip_prefix(FORMAT_MAGIC.as_slice()) {
+            Some([FORMAT_VERSION, records @ ..]) => records,
+            Some(_) => return Err(invalid("Unsupported index format version")),
+            None => return Err(invalid("Index file predates the versioned format")),
+        };
+        let mut entries: Vec<Self> = Vec::new();
+        while !data.is_empty() {
+            let key_len = data
+                .iter()
+                .position(|&b| b == b' ' || b == b'\n')
+                .filter(|&at| data[at] == b' ')
+                .ok_or_else(|| invalid("Index record without a key separator"))?;
+            let record_len = key_len + 1 + OFFSET_LEN + 1;
+            if data.len() < record_len || data[record_len - 1] != b'\n' {
+                return Err(invalid("Truncated index record"));
+            }
+            let mut offset_bytes = [0; OFFSET_LEN];
+            offset_bytes.copy_from_slice(&data[key_len + 1..record_len - 1]);
+
+            let key = &data[..key_len];
+            if entries
+                .last()
+                .is_some_and(|last| last.key.as_slice() >= key)
+            {
+                return Err(invalid("Index keys are not in increasing order"));
+            }
+            entries.push(Self::new(key.to_vec(), u64::from_le_bytes(offset_bytes)));
+            data = &data[record_len..];
         }
-        let offset_bytes = value.split_off(len - 8);
-        let offset = u64::from_le_bytes(offset_bytes.try_into().map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid offset bytes")
-        })?);
-        Ok(Self::new(value, offset))
+        Ok(entries)
     }
 }
 
 impl From<IndexEntry> for Vec<u8> {
     fn from(value: IndexEntry) -> Self {
         let IndexEntry { mut key, offset } = value;
-        key.reserve(std::mem::size_of::<u64>() + 1);
+        key.reserve(OFFSET_LEN + 2);
+        key.push(b' ');
         key.extend_from_slice(&offset.to_le_bytes());
         key.push(b'\n');
         key
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(entries: Vec<IndexEntry>) -> Vec<u8> {
+        IndexEntry::encode_all(entries)
+    }
+
+    #[test]
+    fn test_parse_all_reads_offsets_holding_newline_bytes() {
+        // 10 is a newline byte, and 266 has one as its low byte
+        let offsets = [0, 10, 266, 0x0a0a_0a0a_0a0a_0a0a];
+        let data = encode(
+            offsets
+                .iter()
+                .enumerate()
+                .map(|(i, &offset)| IndexEntry::new(format!("key{}", i).into_bytes(), offset))
+                .collect(),
+        );
+        assert!(data.iter().filter(|&&b| b == b'\n').count() > offsets.len());
+
+        let entries = IndexEntry::parse_all(&data).unwrap();
+        assert_eq!(entries.len(), offsets.len());
+        for (i, (entry, offset)) in entries.iter().zip(offsets).enumerate() {
+            assert_eq!(entry.key(), format!("key{}", i).as_bytes());
+            assert_eq!(entry.offset(), offset);
+        }
+        assert!(IndexEntry::parse_all(&encode(vec![])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_all_rejects_malformed_records() {
+        let data = encode(vec![IndexEntry::new(b"key".to_vec(), 10)]);
+        for malformed in [
+            &data[..data.len() - 1],
+            b"key\n".as_slice(),
+            &[data.as_slice(), &data[FORMAT_MAGIC.len() + 1..]].concat(),
+        ] {
+            assert_eq!(
+                IndexEntry::parse_all(malformed)
+                    .err()
+                    .map(|error| error.kind()),
+                Some(std::io::ErrorKind::InvalidData)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_all_rejects_unversioned_and_unknown_versions() {
+        let data = encode(vec![IndexEntry::new(b"key".to_vec(), 10)]);
+        let legacy = &data[FORMAT_MAGIC.len() + 1..];
+        assert!(IndexEntry::is_versioned(&data));
+        assert!(!IndexEntry::is_versioned(legacy));
+
+        let mut future = data.clone();
+        future[FORMAT_MAGIC.len()] = FORMAT_VERSION + 1;
+        for unsupported in [legacy, future.as_slice()] {
+            assert_eq!(
+                IndexEntry::parse_all(unsupported)
+                    .err()
+                    .map(|error| error.kind()),
+                Some(std::io::ErrorKind::InvalidData)
+            );
+        }
+    }
+}
@@ -1,53 +1,68 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, Write},
-    path::PathBuf,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use crate::database::index_entry::IndexEntry;
+use crate::database::{atomic_file::write_atomically, env::Env, index_entry::IndexEntry};
 
 pub const INDEX_FILE_EXTENSION: &str = "idx";
 pub struct IndexFile {
     path: PathBuf,
+    env: Arc<dyn Env>,
 }
 
 impl IndexFile {
-    pub fn from_path(path: PathBuf) -> std::io::Result<Self> {
-        if Self::is_index_file(&path) {
+    pub fn from_path(env: Arc<dyn Env>, path: PathBuf) -> std::io::Result<Self> {
+        if !Self::is_index_file(&path) {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "Invalid index file extension",
             ));
         }
 
-        Ok(Self { path })
+        Ok(Self { path, env })
     }
 
-    pub fn create_and_store(path: PathBuf, entries: Vec<IndexEntry>) -> std::io::Result<Self> {
+    pub fn create_and_store(
+        env: Arc<dyn Env>,
+        path: PathBuf,
+        entries: Vec<IndexEntry>,
+    ) -> std::io::Result<Self> {
         assert!(Self::is_index_file(&path));
-        let mut file = File::create(&path)?;
-        for entry in entries {
-            file.write(&Vec::<u8>::from(entry))?;
-        }
-        file.flush()?;
-        Ok(Self { path })
+        write_atomically(env.as_ref(), &path, |file| {
+            file.write_all(&IndexEntry::encode_all(entries))
+        })?;
+        Ok(Self { path, env })
     }
 
     pub fn path(&self) -> &PathBuf {
         &self.path
     }
 
-    pub fn is_index_file(path: &PathBuf) -> bool {
+    pub fn is_index_file(path: &Path) -> bool {
         path.extension()
             .map(|ext| ext == INDEX_FILE_EXTENSION)
             .unwrap_or(false)
     }
 
-    pub fn entries(&self) -> std::io::Result<impl Iterator<Item = std::io::Result<IndexEntry>>> {
-        let file = File::open(&self.path)?;
-        let reader = BufReader::new(file);
-        Ok(reader.lines().map(|possible_line| {
-            possible_line.and_then(|line| IndexEntry::try_from(line.as_bytes().to_vec()))
-        }))
+    /// Whether the file was written in the versioned format. Older files are rewritten when
+    /// the database opens
+    pub fn is_versioned(&self) -> std::io::Result<bool> {
+        Ok(IndexEntry::is_versioned(&self.env.read(&self.path)?))
+    }
+
+    /// Reads every entry of the index, through a memory map when `use_mmap` is set and the
+    /// env supports it
+    pub fn load(&self, use_mmap: bool) -> std::io::Result<Vec<IndexEntry>> {
+        let mapped = if use_mmap {
+            self.env.map_readable(&self.path)?
+        } else {
+            None
+        };
+        match mapped {
+            Some(mapped) => IndexEntry::parse_all(mapped.bytes()),
+            None => IndexEntry::parse_all(&self.env.read(&self.path)?),
+        }
     }
 }
@@ -1,42 +1,115 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::database::{
+    env::Env,
     index_entry::IndexEntry,
     index_file::{INDEX_FILE_EXTENSION, IndexFile},
+    metadata_cache::MetadataCache,
+    segment_file::SegmentFile,
 };
 
+/// Indexes are parsed on first use and kept in the metadata cache, except pinned ones which
+/// stay in memory until their segment goes away
 pub struct IndexFileRegistry {
-    index_files: Vec<IndexFile>,
-    directory_path: PathBuf,
+    /// Index files keyed by the number of their segment
+    index_files: HashMap<u64, IndexFile>,
+    /// Parsed indexes kept outside the metadata cache, keyed by base file name
+    pinned: HashMap<String, Arc<Vec<IndexEntry>>>,
+    metadata_cache: Arc<MetadataCache>,
+    use_mmap: bool,
+    env: Arc<dyn Env>,
 }
 
 impl IndexFileRegistry {
-    pub fn new<P: AsRef<Path>>(directory_path: P) -> std::io::Result<Self> {
-        let index_files = Self::find_index_files(&directory_path)?;
+    pub fn new<P: AsRef<Path>>(
+        env: Arc<dyn Env>,
+        directory_path: P,
+        metadata_cache: Arc<MetadataCache>,
+        use_mmap: bool,
+    ) -> std::io::Result<Self> {
+        let index_files = Self::find_index_files(&env, directory_path.as_ref())?;
         Ok(Self {
             index_files,
-            directory_path: directory_path.as_ref().to_path_buf(),
+            pinned: HashMap::new(),
+            metadata_cache,
+            use_mmap,
+            env,
         })
     }
 
-    fn find_index_files<P: AsRef<Path>>(directory_path: P) -> std::io::Result<Vec<IndexFile>> {
-        Ok(std::fs::read_dir(directory_path.as_ref())?
-            .filter_map(Result::ok)
-            .filter_map(|entry| {
-                entry
-                    .file_type()
-                    .ok()
-                    .and_then(|ft| ft.is_file().then_some(entry.path()))
+    fn find_index_files(
+        env: &Arc<dyn Env>,
+        directory_path: &Path,
+    ) -> std::io::Result<HashMap<u64, IndexFile>> {
+        env.list_files(directory_path)?
+            .into_iter()
+            .filter(|path| IndexFile::is_index_file(path))
+            .filter_map(|path| {
+                let number = SegmentFile::number_from_path(&path)?;
+                Some(IndexFile::from_path(Arc::clone(env), path).map(|file| (number, file)))
             })
-            .filter(IndexFile::is_index_file)
-            .map(|path| IndexFile::from_path(path))
-            .collect::<Result<Vec<_>, _>>()?)
+            .collect()
     }
 
-    pub fn get(&self, file_path: &PathBuf) -> Option<&IndexFile> {
+    pub fn get(&self, file_path: &Path) -> Option<&IndexFile> {
         self.index_files
-            .iter()
-            .find(|file| file.path().file_stem() == file_path.file_stem())
+            .get(&SegmentFile::number_from_path(file_path)?)
+    }
+
+    /// Returns the parsed index of the segment, loading it on a miss. Segments whose index
+    /// file is missing or unreadable have it rebuilt from the segment itself
+    pub fn load(&self, segment_file: &SegmentFile) -> std::io::Result<Arc<Vec<IndexEntry>>> {
+        let Some(base_name) = Self::base_name(segment_file.path()) else {
+            return Ok(Arc::new(self.read(segment_file)?));
+        };
+        if let Some(index) = self.pinned.get(base_name) {
+            return Ok(Arc::clone(index));
+        }
+        if let Some(index) = self.metadata_cache.get_index(base_name) {
+            return Ok(index);
+        }
+
+        let index = Arc::new(self.read(segment_file)?);
+        self.metadata_cache
+            .insert_index(base_name, Arc::clone(&index));
+        Ok(index)
+    }
+
+    /// Keeps the segment's parsed index in memory outside the metadata cache until it is
+    /// removed
+    pub fn pin(&mut self, segment_file: &SegmentFile) -> std::io::Result<()> {
+        let index = Arc::new(self.read(segment_file)?);
+        if let Some(base_name) = Self::base_name(segment_file.path()) {
+            self.pinned.insert(base_name.to_string(), index);
+        }
+        Ok(())
+    }
+
+    fn read(&self, segment_file: &SegmentFile) -> std::io::Result<Vec<IndexEntry>> {
+        let stored_index = self
+            .get(segment_file.path())
+            .map(|index_file| index_file.load(self.use_mmap))
+            .transpose();
+        match stored_index {
+            Ok(Some(index)) => Ok(index),
+            Ok(None) => segment_file.build_index(),
+            Err(error) => {
+                tracing::warn!(
+                    "Rebuilding unreadable index for {}: {}",
+                    segment_file.path().display(),
+                    error
+                );
+                segment_file.build_index()
+            }
+        }
+    }
+
+    fn base_name(path: &Path) -> Option<&str> {
+        path.file_stem()?.to_str()
     }
 
     pub fn store_new(
@@ -45,8 +118,51 @@ impl IndexFileRegistry {
         entries: Vec<IndexEntry>,
     ) -> std::io::Result<PathBuf> {
         path.set_extension(INDEX_FILE_EXTENSION);
-        let index_file = IndexFile::create_and_store(path.clone(), entries)?;
-        self.index_files.push(index_file);
+        let index_file = IndexFile::create_and_store(Arc::clone(&self.env), path.clone(), entries)?;
+        if let Some(number) = SegmentFile::number_from_path(&path) {
+            self.index_files.insert(number, index_file);
+        }
         Ok(path)
     }
+
+    /// Rewrites the segment's index from the segment itself when it was written before the
+    /// index format was versioned, returning whether it was rewritten
+    pub fn upgrade(&mut self, segment_file: &SegmentFile) -> std::io::Result<bool> {
+        let Some(index_file) = self.get(segment_file.path()) else {
+            return Ok(false);
+        };
+        if index_file.is_versioned()? {
+            return Ok(false);
+        }
+
+        let path = index_file.path().clone();
+        tracing::info!("Rewriting index {} in the versioned format", path.display());
+        self.store_new(path, segment_file.build_index()?)?;
+        Ok(true)
+    }
+
+    /// Starts serving the index file of a segment that was linked into the directory
+    pub fn insert(&mut self, file_path: &Path) -> std::io::Result<()> {
+        let path = file_path.with_extension(INDEX_FILE_EXTENSION);
+        if let Some(number) = SegmentFile::number_from_path(&path) {
+            self.index_files
+                .insert(number, IndexFile::from_path(Arc::clone(&self.env), path)?);
+        }
+        Ok(())
+    }
+
+    /// Deletes the index of a segment that is no longer live
+    pub fn remove(&mut self, file_path: &Path) -> std::io::Result<()> {
+        let mut path = file_path.to_path_buf();
+        path.set_extension(INDEX_FILE_EXTENSION);
+
+        if let Some(number) = SegmentFile::number_from_path(file_path) {
+            self.index_files.remove(&number);
+        }
+        if let Some(base_name) = Self::base_name(file_path) {
+            self.pinned.remove(base_name);
+            self.metadata_cache.remove(base_name);
+        }
+        self.env.remove_file_if_exists(&path)
+    }
 }
@@ -0,0 +1,111 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// Least recently used cache bounded by the total charge of its entries.
+/// Not synchronized, callers wrap it in a lock
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    /// Cached values along with their charge and the tick of their last use
+    entries: HashMap<K, (V, usize, u64)>,
+    /// Keys ordered from least to most recently used
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    usage: usize,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            usage: 0,
+            capacity,
+        }
+    }
+
+    /// Look up a value, marking it as the most recently used one
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let tick = self.next_tick();
+        let (value, _, last_used) = self.entries.get_mut(key)?;
+        self.recency.remove(last_used);
+        self.recency.insert(tick, key.clone());
+        *last_used = tick;
+        Some(value.clone())
+    }
+
+    /// Add a value, evicting the least recently used entries to make room.
+    /// Values charged more than the whole capacity are not cached
+    pub fn insert(&mut self, key: K, value: V, charge: usize) {
+        if charge > self.capacity {
+            return;
+        }
+
+        self.remove(&key);
+        while self.usage + charge > self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some((_, evicted_charge, _)) = self.entries.remove(&oldest) {
+                self.usage -= evicted_charge;
+            }
+        }
+
+        let tick = self.next_tick();
+        self.usage += charge;
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(key, (value, charge, tick));
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some((_, charge, last_used)) = self.entries.remove(key) {
+            self.recency.remove(&last_used);
+            self.usage -= charge;
+        }
+    }
+
+    /// Total charge of the cached entries
+    pub fn usage(&self) -> usize {
+        self.usage
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_cache_evicts_least_recently_used() {
+        let mut cache = LruCache::new(10);
+
+        cache.insert(1, "a", 4);
+        cache.insert(2, "b", 4);
+        assert_eq!(cache.get(&1), Some("a"));
+
+        cache.insert(3, "c", 4);
+
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&2), None); // Least recently used, so evicted
+        assert_eq!(cache.get(&3), Some("c"));
+        assert_eq!(cache.usage(), 8);
+    }
+
+    #[test]
+    fn test_lru_cache_skips_values_larger_than_capacity() {
+        let mut cache = LruCache::new(10);
+
+        cache.insert(1, "a", 4);
+        cache.insert(2, "b", 11);
+
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&2), None);
+    }
+}
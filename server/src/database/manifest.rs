@@ -0,0 +1,189 @@
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use crate::database::{atomic_file::write_atomically, env::Env};
+
+pub const MANIFEST_FILE_NAME: &str = "MANIFEST";
+
+const NEXT_FILE_NUMBER: &[u8] = b"next_file_number";
+const LOG_NUMBER: &[u8] = b"log_number";
+const LAST_SEQUENCE: &[u8] = b"last_sequence";
+const SEGMENT_COUNT: &[u8] = b"segment_count";
+const SEGMENT: &[u8] = b"segment";
+
+/// Durable record of the directory state that can't be derived from file names alone
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    /// Next number to hand out to a WAL or segment file
+    next_file_number: u64,
+    /// WAL files numbered below this have been flushed to a segment and are obsolete
+    log_number: u64,
+    /// Sequence number of the newest write stored in a segment
+    last_sequence: u64,
+    /// Level of every live segment, keyed by segment number. None for manifests written
+    /// before segments were tracked, where every segment file on disk is live and in level 0
+    segments: Option<BTreeMap<u64, usize>>,
+}
+
+impl Manifest {
+    pub fn new(next_file_number: u64, log_number: u64) -> Self {
+        Self {
+            next_file_number,
+            log_number,
+            last_sequence: 0,
+            segments: None,
+        }
+    }
+
+    /// Records the sequence number of the newest write stored in a segment
+    pub fn with_last_sequence(mut self, last_sequence: u64) -> Self {
+        self.last_sequence = last_sequence;
+        self
+    }
+
+    /// Records the live segments and their levels
+    pub fn with_segments(mut self, segments: BTreeMap<u64, usize>) -> Self {
+        self.segments = Some(segments);
+        self
+    }
+
+    pub fn next_file_number(&self) -> u64 {
+        self.next_file_number
+    }
+
+    pub fn log_number(&self) -> u64 {
+        self.log_number
+    }
+
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    pub fn segments(&self) -> Option<&BTreeMap<u64, usize>> {
+        self.segments.as_ref()
+    }
+
+    /// Reads the manifest from the directory, returning None if it has never been written
+    pub fn load(env: &dyn Env, directory: &Path) -> std::io::Result<Option<Self>> {
+        match env.read(&directory.join(MANIFEST_FILE_NAME)) {
+            Ok(data) => Self::try_from(data.as_slice()).map(Some),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Atomically replaces the manifest in the directory
+    pub fn store(&self, env: &dyn Env, directory: &Path) -> std::io::Result<()> {
+        write_atomically(env, &directory.join(MANIFEST_FILE_NAME), |file| {
+            file.write_all(&Vec::<u8>::from(self))
+        })
+    }
+}
+
+impl From<&Manifest> for Vec<u8> {
+    fn from(value: &Manifest) -> Self {
+        let mut data = [
+            NEXT_FILE_NUMBER,
+            b" ",
+            value.next_file_number.to_string().as_bytes(),
+            b"\n",
+            LOG_NUMBER,
+            b" ",
+            value.log_number.to_string().as_bytes(),
+            b"\n",
+            LAST_SEQUENCE,
+            b" ",
+            value.last_sequence.to_string().as_bytes(),
+            b"\n",
+        ]
+        .concat();
+
+        if let Some(segments) = &value.segments {
+            data.extend_from_slice(
+                &[
+                    SEGMENT_COUNT,
+                    b" ",
+                    segments.len().to_string().as_bytes(),
+                    b"\n",
+                ]
+                .concat(),
+            );
+            for (number, level) in segments {
+                data.extend_from_slice(
+                    &[
+                        SEGMENT,
+                        b" ",
+                        number.to_string().as_bytes(),
+                        b" ",
+                        level.to_string().as_bytes(),
+                        b"\n",
+                    ]
+                    .concat(),
+                );
+            }
+        }
+        data
+    }
+}
+
+impl TryFrom<&[u8]> for Manifest {
+    type Error = std::io::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let mut manifest = Manifest::default();
+        let mut segment_count = None;
+
+        for line in value.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+            let mut parts = line.split(|&b| b == b' ');
+            let name = parts.next().unwrap_or_default();
+            let numbers = parts
+                .map(|number| {
+                    std::str::from_utf8(number)
+                        .ok()
+                        .and_then(|number| number.parse::<u64>().ok())
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or(invalid_record())?;
+
+            match (name, numbers.as_slice()) {
+                (NEXT_FILE_NUMBER, &[number]) => manifest.next_file_number = number,
+                (LOG_NUMBER, &[number]) => manifest.log_number = number,
+                (LAST_SEQUENCE, &[number]) => manifest.last_sequence = number,
+                (SEGMENT_COUNT, &[count]) => {
+                    segment_count = Some(count as usize);
+                    manifest.segments.get_or_insert_default();
+                }
+                (SEGMENT, &[number, level]) => {
+                    manifest
+                        .segments
+                        .get_or_insert_default()
+                        .insert(number, level as usize);
+                }
+                (NEXT_FILE_NUMBER | LOG_NUMBER | LAST_SEQUENCE | SEGMENT_COUNT | SEGMENT, _) => {
+                    return Err(invalid_record());
+                }
+                (unknown, _) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "Unknown manifest record: {:?}",
+                            String::from_utf8_lossy(unknown)
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if segment_count != manifest.segments.as_ref().map(BTreeMap::len) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Manifest segment records do not match the segment count",
+            ));
+        }
+
+        Ok(manifest)
+    }
+}
+
+fn invalid_record() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid manifest record")
+}
@@ -9,6 +9,8 @@ const DEFAULT_MAX_TABLE_SIZE: usize = 1000;
 pub struct MemTable {
     table: Table,
     max_table_size: usize,
+    /// Sequence numbers of the oldest and newest write applied to the table
+    sequence_range: Option<(u64, u64)>,
 }
 
 impl MemTable {
@@ -16,6 +18,7 @@ impl MemTable {
         Self {
             table: BTreeMap::new(),
             max_table_size: max_table_size.unwrap_or(DEFAULT_MAX_TABLE_SIZE),
+            sequence_range: None,
         }
     }
 
@@ -26,48 +29,76 @@ impl MemTable {
         self.table.get(key)
     }
 
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Bytes of keys and values held by the table
+    pub fn bytes(&self) -> u64 {
+        self.table
+            .iter()
+            .map(|(key, value)| (key.len() + value.as_ref().map_or(0, Vec::len)) as u64)
+            .sum()
+    }
+
     pub fn should_flush(&self) -> bool {
         self.table.len() >= self.max_table_size
     }
 
-    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+    pub fn sequence_range(&self) -> Option<(u64, u64)> {
+        self.sequence_range
+    }
+
+    pub fn insert(&mut self, key: &[u8], value: &[u8], sequence: u64) {
         self.table.insert(key.to_vec(), Some(value.to_vec()));
+        self.record_sequence(sequence);
     }
 
-    pub fn remove(&mut self, key: &[u8]) {
+    pub fn remove(&mut self, key: &[u8], sequence: u64) {
         self.table.insert(key.to_vec(), None);
+        self.record_sequence(sequence);
     }
 
     pub fn clear(&mut self) {
         self.table.clear();
+        self.sequence_range = None;
     }
 
-    pub fn len(&self) -> usize {
-        self.table.len()
+    fn record_sequence(&mut self, sequence: u64) {
+        let (first, _) = self.sequence_range.unwrap_or((sequence, sequence));
+        self.sequence_range = Some((first, sequence));
     }
 
-    /// Returns an iterator over references to the entries in the table
-    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &Option<Vec<u8>>)> {
-        self.table.iter().map(|(k, v)| (k.as_slice(), v))
+    /// Returns the entries whose key is at or after `start`, in key order
+    pub fn entries_from<'a>(&'a self, start: &[u8]) -> impl Iterator<Item = Entry> + 'a {
+        self.table
+            .range::<[u8], _>((std::ops::Bound::Included(start), std::ops::Bound::Unbounded))
+            .map(|(key, value)| match value {
+                Some(value) => Entry::KeyValue {
+                    key: key.clone(),
+                    value: value.clone(),
+                },
+                None => Entry::Tombstone { key: key.clone() },
+            })
     }
 
+    /// Builds a table from replayed writes, numbering them from `first_sequence` on
     pub fn from_iter<T: IntoIterator<Item = Entry>>(
         iter: T,
         max_table_size: Option<usize>,
+        first_sequence: u64,
     ) -> Self {
-        let mut table = Table::new();
+        let mut mem_table = Self::new(max_table_size);
 
-        for entry in iter {
+        for (sequence, entry) in (first_sequence..).zip(iter) {
             match entry {
-                Entry::KeyValue { key, value } => table.insert(key, Some(value)),
-                Entry::Tombstone { key } => table.insert(key, None),
+                Entry::KeyValue { key, value } => mem_table.table.insert(key, Some(value)),
+                Entry::Tombstone { key } => mem_table.table.insert(key, None),
             };
+            mem_table.record_sequence(sequence);
         }
 
-        Self {
-            table,
-            max_table_size: max_table_size.unwrap_or(DEFAULT_MAX_TABLE_SIZE),
-        }
+        mem_table
     }
 }
 
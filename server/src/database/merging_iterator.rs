@@ -0,0 +1,104 @@
+use std::iter::Peekable;
+
+use crate::database::entry::Entry;
+
+pub type EntryIterator<'a> = Box<dyn Iterator<Item = std::io::Result<Entry>> + 'a>;
+
+/// Merges several key-sorted sources into one key-sorted stream. Sources are given newest
+/// first, and when several hold the same key only the newest entry is returned, which may
+/// be a tombstone
+pub struct MergingIterator<'a> {
+    sources: Vec<Peekable<EntryIterator<'a>>>,
+}
+
+impl<'a> MergingIterator<'a> {
+    pub fn new(sources: Vec<EntryIterator<'a>>) -> Self {
+        Self {
+            sources: sources.into_iter().map(Iterator::peekable).collect(),
+        }
+    }
+}
+
+impl Iterator for MergingIterator<'_> {
+    type Item = std::io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut smallest: Option<(usize, &[u8])> = None;
+        let mut failed = None;
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            match source.peek() {
+                // Ties go to the earlier, newer source
+                Some(Ok(entry)) if smallest.is_none_or(|(_, key)| entry.key() < key) => {
+                    smallest = Some((i, entry.key()));
+                }
+                Some(Ok(_)) => {}
+                Some(Err(_)) => {
+                    failed = Some(i);
+                    break;
+                }
+                None => {}
+            }
+        }
+        if let Some(i) = failed {
+            return self.sources[i].next();
+        }
+
+        let (i, _) = smallest?;
+        let entry = self.sources[i].next()?;
+        if let Ok(entry) = &entry {
+            // Sources before `i` are already past the key, later ones hold older versions
+            for source in &mut self.sources[i + 1..] {
+                while source
+                    .next_if(|older| matches!(older, Ok(older) if older.key() == entry.key()))
+                    .is_some()
+                {}
+            }
+        }
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(entries: &[(&str, Option<&str>)]) -> EntryIterator<'static> {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(key, value)| {
+                Ok(match value {
+                    Some(value) => Entry::KeyValue {
+                        key: key.as_bytes().to_vec(),
+                        value: value.as_bytes().to_vec(),
+                    },
+                    None => Entry::Tombstone {
+                        key: key.as_bytes().to_vec(),
+                    },
+                })
+            })
+            .collect();
+        Box::new(entries.into_iter())
+    }
+
+    #[test]
+    fn test_merging_iterator_prefers_newer_sources() {
+        let newer = source(&[("b", None), ("c", Some("new"))]);
+        let older = source(&[("a", Some("1")), ("b", Some("2")), ("c", Some("old"))]);
+
+        let merged: Vec<_> = MergingIterator::new(vec![newer, older])
+            .map(|entry| match entry.unwrap() {
+                Entry::KeyValue { key, value } => (key, Some(value)),
+                Entry::Tombstone { key } => (key, None),
+            })
+            .collect();
+
+        assert_eq!(
+            merged,
+            vec![
+                (b"a".to_vec(), Some(b"1".to_vec())),
+                (b"b".to_vec(), None),
+                (b"c".to_vec(), Some(b"new".to_vec())),
+            ]
+        );
+    }
+}
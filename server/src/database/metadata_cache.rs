@@ -0,0 +1,133 @@
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
+use crate::database::{
+    bloom_filter_registry::SegmentFilter, index_entry::IndexEntry, lru_cache::LruCache,
+};
+
+pub const DEFAULT_METADATA_CACHE_CAPACITY: usize = 8 * 1024 * 1024;
+
+/// Identifies cached metadata by its kind and the base name of its segment, e.g., "segment_0"
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum MetadataKey {
+    Filter(String),
+    Index(String),
+}
+
+#[derive(Clone)]
+enum Metadata {
+    Filter(Arc<SegmentFilter>),
+    Index(Arc<Vec<IndexEntry>>),
+}
+
+/// Capacity bounded LRU cache of segment filters and indexes, which are loaded on first use
+/// instead of when the database opens. Pinned metadata is kept by its registry and is not
+/// charged against the capacity
+pub struct MetadataCache {
+    entries: Mutex<LruCache<MetadataKey, Metadata>>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl MetadataCache {
+    /// Create a cache holding at most `capacity` bytes of filters and indexes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn get_filter(&self, base_name: &str) -> Option<Arc<SegmentFilter>> {
+        match self.get(MetadataKey::Filter(base_name.to_string())) {
+            Some(Metadata::Filter(filter)) => Some(filter),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert_filter(&self, base_name: &str, filter: Arc<SegmentFilter>, charge: usize) {
+        self.insert(
+            MetadataKey::Filter(base_name.to_string()),
+            Metadata::Filter(filter),
+            charge,
+        );
+    }
+
+    pub(crate) fn get_index(&self, base_name: &str) -> Option<Arc<Vec<IndexEntry>>> {
+        match self.get(MetadataKey::Index(base_name.to_string())) {
+            Some(Metadata::Index(index)) => Some(index),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert_index(&self, base_name: &str, index: Arc<Vec<IndexEntry>>) {
+        let charge = index
+            .iter()
+            .map(|entry| entry.key().len() + std::mem::size_of::<IndexEntry>())
+            .sum();
+        self.insert(
+            MetadataKey::Index(base_name.to_string()),
+            Metadata::Index(index),
+            charge,
+        );
+    }
+
+    /// Drops the cached filter and index of a segment that is no longer live
+    pub(crate) fn remove(&self, base_name: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(&MetadataKey::Filter(base_name.to_string()));
+            entries.remove(&MetadataKey::Index(base_name.to_string()));
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of bytes of filters and indexes currently cached
+    pub fn usage(&self) -> usize {
+        self.entries
+            .lock()
+            .map(|entries| entries.usage())
+            .unwrap_or_default()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn get(&self, key: MetadataKey) -> Option<Metadata> {
+        let metadata = self
+            .entries
+            .lock()
+            .ok()
+            .and_then(|mut entries| entries.get(&key));
+
+        match metadata {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        metadata
+    }
+
+    fn insert(&self, key: MetadataKey, metadata: Metadata, charge: usize) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(key, metadata, charge);
+        }
+    }
+}
+
+impl Default for MetadataCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_METADATA_CACHE_CAPACITY)
+    }
+}
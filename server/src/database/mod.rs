@@ -1,126 +1,508 @@
+mod atomic_file;
+mod backup_engine;
+mod block_cache;
+mod blocked_bloom_filter;
 mod bloom_filter;
 mod bloom_filter_registry;
+mod compaction_filter;
 mod entry;
+pub mod env;
+mod event_listener;
 mod file_directory;
+mod filter_policy;
+mod hash;
 mod index_entry;
 mod index_file;
 mod index_file_registry;
+mod lru_cache;
+mod manifest;
 mod mem_table;
+mod merging_iterator;
+mod metadata_cache;
+mod options;
+mod pinnable_value;
+mod prefix_extractor;
+mod repair;
+mod scrubber;
 mod segment_file;
 mod segment_file_registry;
+mod segment_inspector;
+mod segment_properties;
+mod segment_properties_registry;
+mod segment_writer;
+mod statistics;
+mod table_cache;
+mod value_log;
+mod verification;
 mod wal;
+mod wal_registry;
+mod xor_filter;
 
 use entry::Entry;
-use std::cmp::Ordering;
 use std::path::Path;
+use std::time::Instant;
 
 use crate::database::file_directory::FileDirectory;
 use crate::database::mem_table::MemTable;
+use crate::database::merging_iterator::{EntryIterator, MergingIterator};
+use crate::database::segment_file::SegmentFile;
+use crate::database::table_cache::{Block, BlockHandle};
+
+pub use backup_engine::{BackupEngine, BackupInfo};
+pub use block_cache::{BlockCache, DEFAULT_BLOCK_CACHE_CAPACITY};
+pub use bloom_filter::BloomFilterSize;
+pub use compaction_filter::{CompactionDecision, CompactionFilter};
+pub use event_listener::{
+    BackgroundErrorReason, CompactionBeginInfo, CompactionJobInfo, EventListener, FlushBeginInfo,
+    FlushJobInfo, SegmentCreationInfo, SegmentCreationReason, SegmentDeletionInfo, WalRotationInfo,
+    WriteStallInfo,
+};
+pub use file_directory::{DEFAULT_LEVEL0_COMPACTION_TRIGGER, DEFAULT_TARGET_SEGMENT_SIZE};
+pub use filter_policy::{
+    BlockedBloomFilterPolicy, BloomFilterPolicy, Filter, FilterPolicy, XorFilterPolicy,
+};
+pub use index_entry::IndexEntry;
+pub use metadata_cache::{DEFAULT_METADATA_CACHE_CAPACITY, MetadataCache};
+pub use options::{DatabaseOptions, ReadOptions};
+pub use pinnable_value::PinnableValue;
+pub use prefix_extractor::{FixedPrefix, PrefixExtractor};
+pub use repair::{LOST_DIRECTORY_NAME, RepairReport};
+pub use scrubber::Scrubber;
+pub use segment_inspector::{FilterSummary, RecordValue, SegmentInspector, SegmentRecord};
+pub use segment_properties::SegmentProperties;
+pub use segment_writer::SegmentWriter;
+pub use statistics::DatabaseStats;
+pub use table_cache::DEFAULT_TABLE_CACHE_CAPACITY;
+pub use value_log::DEFAULT_VALUE_LOG_FILE_SIZE;
+pub use verification::{FileReport, VerificationReport};
 
 pub struct Database<P: AsRef<Path> + Clone> {
     file_directory: FileDirectory<P>,
     mem_table: MemTable,
+    /// Sequence number of the newest write
+    last_sequence: u64,
+    sync_wal: bool,
 }
 
 impl<P: AsRef<Path> + Clone> Database<P> {
     pub fn new(directory: P, max_table_size: Option<usize>) -> std::io::Result<Self> {
-        let mut file_directory = FileDirectory::new(directory)?;
+        Self::open(
+            directory,
+            DatabaseOptions {
+                max_table_size,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn open(directory: P, options: DatabaseOptions) -> std::io::Result<Self> {
+        let mut file_directory = FileDirectory::new(directory, &options)?;
         // Collect valid WAL entries into a MemTable using FromIterator
-        let wal_entries = file_directory.wal().entries()?.filter_map(Result::ok);
-        let mem_table = MemTable::from_iter(wal_entries, max_table_size);
+        let flushed_sequence = file_directory.last_sequence();
+        let wal_entries = file_directory.wal_entries()?.filter_map(Result::ok);
+        let mem_table =
+            MemTable::from_iter(wal_entries, options.max_table_size, flushed_sequence + 1);
+        let last_sequence = mem_table
+            .sequence_range()
+            .map_or(flushed_sequence, |(_, last)| last);
 
         Ok(Database {
             file_directory,
             mem_table,
+            last_sequence,
+            sync_wal: options.sync_wal,
         })
     }
 
-    pub fn get(&mut self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+    pub fn get(&self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        self.get_with_options(key, &ReadOptions::default())
+    }
+
+    pub fn get_with_options(
+        &self,
+        key: &[u8],
+        read_options: &ReadOptions,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self
+            .get_pinned_with_options(key, read_options)?
+            .map(|value| value.to_vec()))
+    }
+
+    /// Like `get`, but returns a handle to the value where it already lives in memory
+    /// instead of copying it
+    pub fn get_pinned(&self, key: &[u8]) -> std::io::Result<Option<PinnableValue<'_>>> {
+        self.get_pinned_with_options(key, &ReadOptions::default())
+    }
+
+    pub fn get_pinned_with_options(
+        &self,
+        key: &[u8],
+        read_options: &ReadOptions,
+    ) -> std::io::Result<Option<PinnableValue<'_>>> {
         if let Some(value) = self.mem_table.get(key) {
-            return Ok(value.clone());
+            return Ok(value.as_deref().map(PinnableValue::from_mem_table));
         }
 
         for segment_file in self.file_directory.segment_files() {
+            if !self
+                .file_directory
+                .may_contain_key(segment_file.path(), key)
+            {
+                continue;
+            }
             // Check bloom filter first to skip segments that definitely don't contain the key
             if let Some(bloom_filter) = self.file_directory.get_bloom_filter(segment_file.path()) {
-                if !bloom_filter.might_contain(key) {
+                let useful = !bloom_filter.might_contain(key);
+                self.file_directory.statistics().record_filter_check(useful);
+                if useful {
                     continue;
                 }
             }
 
-            let starting_position = self
-                .file_directory
-                .get_index_file(segment_file.path())
-                .and_then(|index_file| {
-                    index_file.entries().ok().and_then(|mut entry_file| {
-                        let mut position = 0_u64;
-
-                        while let Some(entry) = entry_file.next() {
-                            let entry = entry.ok()?;
-
-                            if entry.key() > key {
-                                return Some(position);
-                            }
-
-                            position = entry.offset();
-                        }
-
-                        None
-                    })
-                });
-
-            'line: for result in segment_file.entries(starting_position)? {
-                let (_, entry) = result?;
-                match entry {
-                    Entry::KeyValue {
-                        key: entry_key,
-                        value,
-                    } => match entry_key.as_slice().cmp(key) {
-                        Ordering::Equal => return Ok(Some(value.to_vec())),
-                        Ordering::Less => continue,
-                        Ordering::Greater => break 'line,
-                    },
-                    Entry::Tombstone { key: entry_key } => match entry_key.as_slice().cmp(key) {
-                        Ordering::Equal => return Ok(None),
-                        Ordering::Less => continue,
-                        Ordering::Greater => break 'line,
-                    },
+            let Some(block_handle) = self.file_directory.find_block(segment_file, key)? else {
+                continue;
+            };
+            let block = self.file_directory.read_block(
+                segment_file,
+                block_handle,
+                read_options.fill_cache,
+            )?;
+
+            match SegmentFile::search_block(&block, key) {
+                Some(Some(range)) => {
+                    return Ok(Some(
+                        match self
+                            .file_directory
+                            .read_value_pointer(&block[range.clone()])?
+                        {
+                            Some(value) => PinnableValue::from_vec(value),
+                            None => PinnableValue::from_block(block, range),
+                        },
+                    ));
                 }
+                Some(None) => return Ok(None),
+                None => continue,
             }
         }
 
         Ok(None)
     }
 
+    /// Looks up several keys at once, returning their values in the same order as `keys`.
+    /// The keys are sorted so each segment is visited once, its bloom filter is checked for
+    /// every remaining key and each block is read only once even when it holds several keys
+    pub fn multi_get(&self, keys: &[&[u8]]) -> std::io::Result<Vec<Option<Vec<u8>>>> {
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by_key(|&i| keys[i]);
+
+        // Outer None means the key has not been found yet, inner None that it was deleted
+        let mut results: Vec<Option<Option<Vec<u8>>>> = keys
+            .iter()
+            .map(|key| self.mem_table.get(key).cloned())
+            .collect();
+
+        for segment_file in self.file_directory.segment_files() {
+            let bloom_filter = self.file_directory.get_bloom_filter(segment_file.path());
+            let mut current_block: Option<(BlockHandle, Block)> = None;
+
+            for &i in &order {
+                if results[i].is_some() {
+                    continue;
+                }
+                let key = keys[i];
+                if !self
+                    .file_directory
+                    .may_contain_key(segment_file.path(), key)
+                {
+                    continue;
+                }
+                if let Some(bloom_filter) = &bloom_filter {
+                    let useful = !bloom_filter.might_contain(key);
+                    self.file_directory.statistics().record_filter_check(useful);
+                    if useful {
+                        continue;
+                    }
+                }
+                let Some(block_handle) = self.file_directory.find_block(segment_file, key)? else {
+                    continue;
+                };
+
+                let block = match &current_block {
+                    Some((handle, block)) if *handle == block_handle => block,
+                    _ => {
+                        let block =
+                            self.file_directory
+                                .read_block(segment_file, block_handle, true)?;
+                        &current_block.insert((block_handle, block)).1
+                    }
+                };
+                results[i] = match SegmentFile::search_block(block, key) {
+                    Some(Some(range)) => Some(Some(
+                        match self
+                            .file_directory
+                            .read_value_pointer(&block[range.clone()])?
+                        {
+                            Some(value) => value,
+                            None => block[range].to_vec(),
+                        },
+                    )),
+                    result => result.map(|_| None),
+                };
+            }
+        }
+
+        Ok(results.into_iter().map(Option::flatten).collect())
+    }
+
+    /// Returns every live key starting with `prefix` together with its value, in key order.
+    /// Segments whose key range or, with a prefix extractor configured, filter rules out the
+    /// prefix are skipped without being read
+    pub fn scan_prefix(&self, prefix: &[u8]) -> std::io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut sources: Vec<EntryIterator> = vec![Box::new(
+            self.mem_table
+                .entries_from(prefix)
+                .take_while(|entry| entry.key().starts_with(prefix))
+                .map(Ok),
+        )];
+
+        for segment_file in self.file_directory.segment_files() {
+            if !self
+                .file_directory
+                .prefix_may_match(segment_file.path(), prefix)
+            {
+                continue;
+            }
+
+            let start = self
+                .file_directory
+                .find_block(segment_file, prefix)?
+                .map(|block_handle| block_handle.offset);
+            let entries = segment_file
+                .entries(start)?
+                .map(|entry| entry.map(|(_, entry)| entry))
+                .skip_while(|entry| matches!(entry, Ok(entry) if entry.key() < prefix))
+                .take_while(
+                    |entry| !matches!(entry, Ok(entry) if !entry.key().starts_with(prefix)),
+                );
+            sources.push(Box::new(entries));
+        }
+
+        MergingIterator::new(sources)
+            .filter_map(|entry| match entry {
+                // Values from the in-memory table are never value log pointers
+                Ok(Entry::KeyValue { key, value }) if self.mem_table.get(&key).is_some() => {
+                    Some(Ok((key, value)))
+                }
+                Ok(Entry::KeyValue { key, value }) => {
+                    match self.file_directory.read_value_pointer(&value) {
+                        Ok(resolved) => Some(Ok((key, resolved.unwrap_or(value)))),
+                        Err(error) => Some(Err(error)),
+                    }
+                }
+                Ok(Entry::Tombstone { .. }) => None,
+                Err(error) => Some(Err(error)),
+            })
+            .collect()
+    }
+
     pub fn set(&mut self, key: &[u8], value: &[u8]) -> std::io::Result<()> {
-        self.file_directory.wal().append(Entry::KeyValue {
+        self.append_to_wal(Entry::KeyValue {
             key: key.to_vec(),
             value: value.to_vec(),
         })?;
-        self.mem_table.insert(key, value);
-        if self.mem_table.should_flush() {
-            self.flush()?;
-        }
-        Ok(())
+        self.last_sequence += 1;
+        self.mem_table.insert(key, value, self.last_sequence);
+        self.flush_if_full()
     }
 
     pub fn delete(&mut self, key: &[u8]) -> std::io::Result<()> {
+        self.append_to_wal(Entry::Tombstone { key: key.to_vec() })?;
+        self.last_sequence += 1;
+        self.mem_table.remove(key, self.last_sequence);
+        self.flush_if_full()
+    }
+
+    /// Checks every live segment along with its index and filter, reporting the problems
+    /// found in each file. See `Scrubber` for running the same checks in the background
+    pub fn verify(&self) -> VerificationReport {
+        VerificationReport {
+            files: self
+                .segment_numbers()
+                .into_iter()
+                .filter_map(|number| self.verify_segment(number))
+                .flatten()
+                .collect(),
+        }
+    }
+
+    /// Creates a copy of the database in `target_directory`, which must not exist yet, that
+    /// can be opened like any other database directory. Immutable files are hard linked
+    /// where possible and the writes still in the in-memory table are captured by copying
+    /// their WAL files, so the checkpoint costs about as much as a flush
+    pub fn checkpoint<Q: AsRef<Path>>(&mut self, target_directory: Q) -> std::io::Result<()> {
+        self.file_directory.checkpoint(target_directory.as_ref())
+    }
+
+    /// Loads segments built with `SegmentWriter` without passing their records through the
+    /// WAL or the in-memory table. The files must not overlap each other's key ranges, and
+    /// together they become a single write that shadows every earlier one, so unflushed writes
+    /// are flushed first. Segments are hard linked into the database where possible, so the
+    /// originals must not be modified afterwards
+    pub fn ingest_external_files<Q: AsRef<Path>>(&mut self, paths: &[Q]) -> std::io::Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        if self.mem_table.sequence_range().is_some() {
+            self.flush()?;
+        }
+
+        let paths: Vec<&Path> = paths.iter().map(AsRef::as_ref).collect();
         self.file_directory
-            .wal()
-            .append(Entry::Tombstone { key: key.to_vec() })?;
-        self.mem_table.remove(key);
-        if self.mem_table.should_flush() {
+            .ingest_external_files(&paths, self.last_sequence + 1)?;
+        self.last_sequence += 1;
+        self.file_directory.compact_if_needed()
+    }
+
+    /// Repairs a database directory that fails to open or verify, without opening it.
+    /// Missing or damaged indexes, filters and properties are rebuilt, and damaged segments
+    /// are rewritten with the records that can still be read while the originals are moved
+    /// to the `lost/` directory
+    pub fn repair(directory: P) -> std::io::Result<RepairReport> {
+        Self::repair_with_options(directory, DatabaseOptions::default())
+    }
+
+    /// Like `repair`, rebuilding filters with the policies and prefix extractor of `options`
+    pub fn repair_with_options(
+        directory: P,
+        options: DatabaseOptions,
+    ) -> std::io::Result<RepairReport> {
+        repair::repair(directory.as_ref(), &options)
+    }
+
+    pub(crate) fn segment_numbers(&self) -> Vec<u64> {
+        self.file_directory.segment_numbers()
+    }
+
+    pub(crate) fn verify_segment(&self, segment_number: u64) -> Option<Vec<FileReport>> {
+        self.file_directory.verify_segment(segment_number)
+    }
+
+    /// Reclaims the oldest value log file that is no longer appended to. Its values that are
+    /// still live are written again, which moves them to the current value log file, and the
+    /// file is deleted once the new locations are flushed. Returns false when there is no
+    /// file to reclaim
+    pub fn collect_value_log_garbage(&mut self) -> std::io::Result<bool> {
+        let Some(file_number) = self.file_directory.oldest_value_log_file() else {
+            return Ok(false);
+        };
+
+        let live_values = self.file_directory.live_values(file_number)?;
+        tracing::info!(
+            "Relocating {} live values out of value log file {}",
+            live_values.len(),
+            file_number
+        );
+        for (key, value) in live_values {
+            // Keys written since the last flush no longer need the old value
+            if self.mem_table.get(&key).is_none() {
+                self.set(&key, &value)?;
+            }
+        }
+        // Pointers into the file must not resurface if unflushed writes shadowing them are lost
+        if self.mem_table.sequence_range().is_some() {
             self.flush()?;
         }
+
+        self.file_directory.remove_value_log_file(file_number)?;
+        Ok(true)
+    }
+
+    /// Counters and sizes describing what the database holds and the work it has done since
+    /// it was opened
+    pub fn stats(&self) -> std::io::Result<DatabaseStats> {
+        let mut stats = self.file_directory.stats()?;
+        stats.mem_table_entries = self.mem_table.len() as u64;
+        stats.mem_table_bytes = self.mem_table.bytes();
+        Ok(stats)
+    }
+
+    /// A single statistic by name, e.g. "segments-at-level0" or "write-amplification", as
+    /// listed by `DatabaseStats::properties`. "stats" returns all of them, one per line.
+    /// Returns None for unknown names
+    pub fn property(&self, name: &str) -> std::io::Result<Option<String>> {
+        let properties = self.stats()?.properties();
+        if name == "stats" {
+            return Ok(Some(
+                properties
+                    .iter()
+                    .map(|(name, value)| format!("{} {}\n", name, value))
+                    .collect(),
+            ));
+        }
+        Ok(properties
+            .into_iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, value)| value))
+    }
+
+    /// Cache holding the segment blocks read by this database
+    pub fn block_cache(&self) -> &BlockCache {
+        self.file_directory.block_cache()
+    }
+
+    /// Cache holding the segment filters and indexes loaded by this database
+    pub fn metadata_cache(&self) -> &MetadataCache {
+        self.file_directory.metadata_cache()
+    }
+
+    fn append_to_wal(&mut self, entry: Entry) -> std::io::Result<()> {
+        let user_bytes = match &entry {
+            Entry::KeyValue { key, value } => key.len() + value.len(),
+            Entry::Tombstone { key } => key.len(),
+        } as u64;
+        let wal = self.file_directory.wal();
+        let wal_bytes = wal.append(entry)?;
+        if self.sync_wal {
+            wal.sync()?;
+        }
+        self.file_directory
+            .statistics()
+            .record_write(user_bytes, wal_bytes);
         Ok(())
     }
 
+    /// Flushes the in-memory table once it is full. The write that filled it waits for the
+    /// flush, which listeners are told about as a write stall
+    fn flush_if_full(&mut self) -> std::io::Result<()> {
+        if !self.mem_table.should_flush() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        self.flush()?;
+        let info = WriteStallInfo {
+            duration: start.elapsed(),
+        };
+        self.file_directory
+            .notify(|listener| listener.on_write_stall(&info));
+        Ok(())
+    }
+
+    /// Tells the listeners about an error hit outside of any call into the database, e.g.
+    /// by the scrubber
+    pub(crate) fn notify_background_error(
+        &self,
+        reason: BackgroundErrorReason,
+        error: &std::io::Error,
+    ) {
+        self.file_directory
+            .notify(|listener| listener.on_background_error(reason, error));
+    }
+
     fn flush(&mut self) -> std::io::Result<()> {
         tracing::info!("Flushing in-memory table to disk");
 
         self.file_directory.store_segment(self.mem_table.clone())?;
-        self.file_directory.wal().clear()?;
         self.mem_table.clear();
-        Ok(())
+        self.file_directory.compact_if_needed()
     }
 }
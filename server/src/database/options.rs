@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use crate::database::{
+    block_cache::BlockCache,
+    bloom_filter::BloomFilterSize,
+    compaction_filter::CompactionFilter,
+    env::{DiskEnv, Env, MemEnv},
+    event_listener::EventListener,
+    filter_policy::{BloomFilterPolicy, FilterPolicy},
+    prefix_extractor::PrefixExtractor,
+};
+
+/// Settings used when opening a `Database`
+#[derive(Clone, Debug, Default)]
+pub struct DatabaseOptions {
+    /// Number of entries the in-memory table holds before it is flushed to a segment
+    pub max_table_size: Option<usize>,
+    /// Move retired WAL files into `archive/` instead of deleting them,
+    /// keeping at most this many of the newest ones around
+    pub wal_archive_limit: Option<usize>,
+    /// Sync the WAL after every write so acknowledged writes survive an operating system
+    /// crash and not just a process crash
+    pub sync_wal: bool,
+    /// Keep every file in memory instead of on disk. Nothing survives the database being
+    /// dropped, but flushes still run as normal. Ignored when `env` is set
+    pub in_memory: bool,
+    /// Filesystem the database lives on. Defaults to the local disk
+    pub env: Option<Arc<dyn Env>>,
+    /// Cache for blocks read from segments, which can be shared between databases.
+    /// Defaults to a cache of `DEFAULT_BLOCK_CACHE_CAPACITY` bytes
+    pub block_cache: Option<Arc<BlockCache>>,
+    /// Number of segment files kept open along with their parsed index.
+    /// Defaults to `DEFAULT_TABLE_CACHE_CAPACITY`
+    pub table_cache_capacity: Option<usize>,
+    /// Number of bytes of segment filters and indexes kept in memory. They are loaded on
+    /// first use rather than when the database opens. A filter or index bigger than the
+    /// whole capacity is never cached and is read from its file on every lookup that needs
+    /// it, so this should exceed the largest one. Defaults to
+    /// `DEFAULT_METADATA_CACHE_CAPACITY`
+    pub metadata_cache_capacity: Option<usize>,
+    /// Load the filters and indexes of level 0 segments up front and keep them in memory
+    /// outside the metadata cache. Level 0 segments are checked by every read that misses
+    /// the in-memory table. Segment properties are always kept in memory
+    pub pin_level0_metadata: bool,
+    /// Read segment and index files through memory maps instead of buffered reads. Blocks
+    /// of mapped segments come straight from the page cache and skip the block cache.
+    /// Falls back to buffered reads when the env cannot map files
+    pub use_mmap: bool,
+    /// Size of the bloom filter written for each new segment when `filter_policies` is
+    /// empty. Existing filters keep the size they were written with
+    pub bloom_filter_size: BloomFilterSize,
+    /// Filter policy for new segments of each level, starting at level 0. Levels past the
+    /// end of the list use its last policy. Defaults to a bloom filter on every level
+    pub filter_policies: Vec<Arc<dyn FilterPolicy>>,
+    /// Number of level 0 segments that triggers merging them into the bottom level.
+    /// Defaults to `DEFAULT_LEVEL0_COMPACTION_TRIGGER`
+    pub level0_compaction_trigger: Option<usize>,
+    /// Size in bytes a compaction output grows to before the compaction moves on to a new
+    /// segment. Smaller segments let compactions rewrite less of the bottom level.
+    /// Defaults to `DEFAULT_TARGET_SEGMENT_SIZE`
+    pub target_segment_size: Option<usize>,
+    /// Adds the prefixes it extracts from keys to the filters of new segments, letting
+    /// `Database::scan_prefix` skip segments without matching keys
+    pub prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    /// Values at least this many bytes long are moved to the value log when flushed and
+    /// segments only keep a pointer to them, so compactions don't copy them around.
+    /// Values are kept in their segments when not set
+    pub value_log_threshold: Option<usize>,
+    /// Size a value log file grows to before a new one is started.
+    /// Defaults to `DEFAULT_VALUE_LOG_FILE_SIZE`
+    pub value_log_file_size: Option<usize>,
+    /// Called by compactions with every live key and value to keep, remove or rewrite it
+    pub compaction_filter: Option<Arc<dyn CompactionFilter>>,
+    /// Notified of flushes, compactions, segment and WAL file changes, write stalls and
+    /// background errors, in the order listed
+    pub listeners: Vec<Arc<dyn EventListener>>,
+}
+
+impl DatabaseOptions {
+    /// The configured env, or a new one for the disk or memory
+    pub(crate) fn env(&self) -> Arc<dyn Env> {
+        match (&self.env, self.in_memory) {
+            (Some(env), _) => Arc::clone(env),
+            (None, true) => Arc::new(MemEnv::new()),
+            (None, false) => Arc::new(DiskEnv::new()),
+        }
+    }
+
+    /// The configured filter policies, or a bloom filter of `bloom_filter_size` for every level
+    pub(crate) fn filter_policies(&self) -> Vec<Arc<dyn FilterPolicy>> {
+        if self.filter_policies.is_empty() {
+            vec![Arc::new(BloomFilterPolicy::new(self.bloom_filter_size))]
+        } else {
+            self.filter_policies.clone()
+        }
+    }
+}
+
+/// Settings for a single read
+#[derive(Clone, Debug)]
+pub struct ReadOptions {
+    /// Add blocks read from segment files to the block cache. Turn off for one-off reads,
+    /// like scans, that would otherwise push hot blocks out of the cache
+    pub fill_cache: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self { fill_cache: true }
+    }
+}
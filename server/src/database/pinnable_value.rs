@@ -0,0 +1,52 @@
+use std::ops::{Deref, Range};
+
+use crate::database::table_cache::Block;
+
+/// Value returned by `Database::get_pinned`. It references the memory the value already
+/// lives in, either the in-memory table or a segment block, instead of copying it out.
+/// Values read from the value log are owned
+#[derive(Debug)]
+pub struct PinnableValue<'a> {
+    data: PinnedData<'a>,
+}
+
+#[derive(Debug)]
+enum PinnedData<'a> {
+    MemTable(&'a [u8]),
+    Block { block: Block, range: Range<usize> },
+    Owned(Vec<u8>),
+}
+
+impl<'a> PinnableValue<'a> {
+    pub(crate) fn from_mem_table(value: &'a [u8]) -> Self {
+        Self {
+            data: PinnedData::MemTable(value),
+        }
+    }
+
+    /// Pins `block` for as long as the value is alive. `range` is the position of the value
+    /// within the block
+    pub(crate) fn from_block(block: Block, range: Range<usize>) -> Self {
+        Self {
+            data: PinnedData::Block { block, range },
+        }
+    }
+
+    pub(crate) fn from_vec(value: Vec<u8>) -> Self {
+        Self {
+            data: PinnedData::Owned(value),
+        }
+    }
+}
+
+impl Deref for PinnableValue<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.data {
+            PinnedData::MemTable(value) => value,
+            PinnedData::Block { block, range } => &block[range.clone()],
+            PinnedData::Owned(value) => value,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt::Debug;
+
+/// Picks the part of a key that is added to segment filters next to the key itself, so
+/// prefix scans can skip segments that hold no key with that prefix
+pub trait PrefixExtractor: Send + Sync + Debug {
+    /// Name recorded with every filter built using this extractor. Filters recorded under a
+    /// different name are never used to skip segments
+    fn name(&self) -> String;
+
+    /// Returns the prefix of `key` to add to filters, or None when the key has none. Every
+    /// key starting with a returned prefix must itself map to that same prefix
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]>;
+}
+
+/// Uses the first `len` bytes of every key. Shorter keys have no prefix
+#[derive(Clone, Copy, Debug)]
+pub struct FixedPrefix(pub usize);
+
+impl PrefixExtractor for FixedPrefix {
+    fn name(&self) -> String {
+        format!("fixed:{}", self.0)
+    }
+
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        key.get(..self.0)
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::database::{
+    atomic_file::remove_temporary_files,
+    bloom_filter_registry::{BLOOM_FILTER_FILE_EXTENSION, BloomFilterRegistry},
+    entry::Entry,
+    env::Env,
+    hash::hash64,
+    index_file::{INDEX_FILE_EXTENSION, IndexFile},
+    manifest::{MANIFEST_FILE_NAME, Manifest},
+    metadata_cache::MetadataCache,
+    options::DatabaseOptions,
+    segment_file::SegmentFile,
+    segment_properties::{PROPERTIES_FILE_EXTENSION, SegmentProperties},
+    segment_properties_registry::SegmentPropertiesRegistry,
+    value_log::ValuePointer,
+    verification::{self, FileReport},
+};
+
+/// Directory inside the database directory that damaged files are moved to
+pub const LOST_DIRECTORY_NAME: &str = "lost";
+
+/// What `Database::repair` changed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepairReport {
+    /// Index, filter and properties files that were missing or damaged and written again
+    pub rebuilt_files: Vec<PathBuf>,
+    /// Damaged segments rewritten with the records that could still be read
+    pub salvaged_segments: Vec<PathBuf>,
+    /// Files moved into the `lost/` directory, under their original name
+    pub lost_files: Vec<PathBuf>,
+}
+
+/// Brings a database directory back into a state it can be opened and verified in, without
+/// opening it. Every live segment is checked: damaged segments are rewritten with their
+/// readable records and the originals moved to `lost/`, and missing or damaged indexes,
+/// filters and properties are rebuilt from the segments. Finally the manifest is rewritten
+/// to list the surviving segments. When the manifest itself is unreadable every segment in
+/// the directory is treated as a live level 0 segment
+pub fn repair(directory: &Path, options: &DatabaseOptions) -> std::io::Result<RepairReport> {
+    let env = options.env();
+    env.create_dir_all(directory)?;
+    remove_temporary_files(env.as_ref(), directory)?;
+    let mut report = RepairReport::default();
+
+    let manifest = match Manifest::load(env.as_ref(), directory) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            tracing::error!("Manifest is unreadable: {}", error);
+            quarantine(
+                env.as_ref(),
+                directory,
+                &directory.join(MANIFEST_FILE_NAME),
+                &mut report,
+            )?;
+            None
+        }
+    };
+    let manifest = manifest.unwrap_or_default();
+
+    let live_segments: BTreeMap<u64, usize> = match manifest.segments() {
+        Some(segments) => segments.clone(),
+        None => env
+            .list_files(directory)?
+            .into_iter()
+            .filter(|path| SegmentFile::is_segment_file(path))
+            .filter_map(|path| Some((file_number(&path)?, 0)))
+            .collect(),
+    };
+
+    let mut bloom_filter_registry = BloomFilterRegistry::new(
+        Arc::clone(&env),
+        directory,
+        options.filter_policies(),
+        options.prefix_extractor.clone(),
+        Arc::new(MetadataCache::default()),
+    )?;
+    let stored_properties = SegmentPropertiesRegistry::new(Arc::clone(&env), directory)?;
+
+    let mut segments = BTreeMap::new();
+    let mut last_sequence = manifest.last_sequence();
+    for (number, level) in live_segments {
+        let path = directory.join(format!("segment_{}.sst", number));
+        let properties = stored_properties.get(&path).cloned();
+        let repaired = repair_segment(
+            &env,
+            directory,
+            &path,
+            level,
+            properties,
+            manifest.last_sequence(),
+            &mut bloom_filter_registry,
+            &mut report,
+        )?;
+        if let Some(properties) = repaired {
+            last_sequence = last_sequence.max(properties.max_sequence);
+            segments.insert(number, level);
+        }
+    }
+
+    // Numbers are shared by every kind of numbered file, so none may be handed out twice
+    let next_file_number = env
+        .list_files(directory)?
+        .iter()
+        .filter_map(|path| file_number(path))
+        .map(|number| number + 1)
+        .max()
+        .unwrap_or_default()
+        .max(manifest.next_file_number());
+    Manifest::new(next_file_number, manifest.log_number())
+        .with_last_sequence(last_sequence)
+        .with_segments(segments)
+        .store(env.as_ref(), directory)?;
+
+    Ok(report)
+}
+
+/// Repairs a single segment and its side files, returning its properties or None when
+/// nothing of it could be kept
+#[allow(clippy::too_many_arguments)]
+fn repair_segment(
+    env: &Arc<dyn Env>,
+    directory: &Path,
+    path: &Path,
+    level: usize,
+    properties: Option<SegmentProperties>,
+    last_sequence: u64,
+    bloom_filter_registry: &mut BloomFilterRegistry,
+    report: &mut RepairReport,
+) -> std::io::Result<Option<SegmentProperties>> {
+    let data = match env.read(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            tracing::error!("Live segment {} is missing", path.display());
+            return Ok(None);
+        }
+        Err(error) => {
+            tracing::error!("Segment {} is unreadable: {}", path.display(), error);
+            quarantine(env.as_ref(), directory, path, report)?;
+            remove_side_files(env.as_ref(), path)?;
+            return Ok(None);
+        }
+    };
+
+    let (entries, complete) = salvage_records(&data);
+    let checksum_matches = properties
+        .as_ref()
+        .and_then(|properties| properties.segment_checksum)
+        .is_none_or(|checksum| checksum == hash64(&data));
+    let damaged = !complete || !checksum_matches;
+
+    let segment_file = if damaged {
+        tracing::warn!(
+            "Salvaging {} records from damaged segment {}",
+            entries.len(),
+            path.display()
+        );
+        quarantine(env.as_ref(), directory, path, report)?;
+        if entries.is_empty() {
+            remove_side_files(env.as_ref(), path)?;
+            return Ok(None);
+        }
+        report.salvaged_segments.push(path.to_path_buf());
+        SegmentFile::create_and_store(
+            Arc::clone(env),
+            path.to_path_buf(),
+            entries.into_iter().map(Ok),
+        )?
+    } else {
+        SegmentFile::from_path(Arc::clone(env), path.to_path_buf())?
+    };
+    let keys: Vec<(u64, Vec<u8>)> = segment_file
+        .entries(None)?
+        .map(|entry| entry.map(|(offset, entry)| (offset, entry.key().to_vec())))
+        .collect::<std::io::Result<_>>()?;
+
+    let mut rebuilt = false;
+
+    let index_path = path.with_extension(INDEX_FILE_EXTENSION);
+    let expected_index = segment_file.build_index()?;
+    let stored_index = read_if_exists(env.as_ref(), &index_path)?;
+    let index_checksum = properties
+        .as_ref()
+        .and_then(|properties| properties.index_checksum);
+    let index_healthy = match &stored_index {
+        Some(data) => {
+            let mut index_report = FileReport::new(index_path.clone());
+            verification::verify_index(
+                data,
+                segment_file.build_index()?,
+                &keys,
+                index_checksum.filter(|_| !damaged),
+                &mut index_report,
+            );
+            index_report.is_healthy()
+        }
+        None => expected_index.is_empty(),
+    };
+    if !index_healthy {
+        env.remove_file_if_exists(&index_path)?;
+        if !expected_index.is_empty() {
+            IndexFile::create_and_store(Arc::clone(env), index_path.clone(), expected_index)?;
+            report.rebuilt_files.push(index_path.clone());
+        }
+        rebuilt = true;
+    }
+
+    let filter_path = path.with_extension(BLOOM_FILTER_FILE_EXTENSION);
+    let filter_healthy = match read_if_exists(env.as_ref(), &filter_path)? {
+        Some(data) => {
+            let mut filter_report = FileReport::new(filter_path.clone());
+            verification::verify_filter(
+                &data,
+                bloom_filter_registry.get(path).as_deref(),
+                &keys,
+                properties
+                    .as_ref()
+                    .and_then(|properties| properties.filter_checksum)
+                    .filter(|_| !damaged),
+                &mut filter_report,
+            );
+            filter_report.is_healthy()
+        }
+        None => false,
+    };
+    if !filter_healthy {
+        bloom_filter_registry.remove(path)?;
+        let keys: Vec<&[u8]> = keys.iter().map(|(_, key)| key.as_slice()).collect();
+        bloom_filter_registry.store(path, level, &keys, false)?;
+        report.rebuilt_files.push(filter_path.clone());
+        rebuilt = true;
+    }
+
+    let mut properties_healthy = properties.is_some() && !damaged && !rebuilt;
+    if let Some(properties) = properties.as_ref().filter(|_| properties_healthy) {
+        let mut segment_report = FileReport::new(path.to_path_buf());
+        verification::verify_segment(&data, Some(properties), &mut segment_report);
+        properties_healthy = segment_report.is_healthy();
+    }
+    if properties_healthy {
+        return Ok(properties);
+    }
+
+    // Without stored properties the segment could hold any write up to the last flushed one
+    let mut new_properties = match &properties {
+        Some(properties) => {
+            SegmentProperties::new(properties.min_sequence, properties.max_sequence)
+        }
+        None => SegmentProperties::new(0, last_sequence),
+    };
+    if let Some(properties) = &properties {
+        new_properties.created_at = properties.created_at;
+    }
+    for entry in segment_file.entries(None)? {
+        new_properties.add(&entry?.1);
+    }
+    new_properties.segment_checksum = read_if_exists(env.as_ref(), path)?.map(|data| hash64(&data));
+    new_properties.index_checksum =
+        read_if_exists(env.as_ref(), &index_path)?.map(|data| hash64(&data));
+    new_properties.filter_checksum =
+        read_if_exists(env.as_ref(), &filter_path)?.map(|data| hash64(&data));
+
+    let mut properties_registry = SegmentPropertiesRegistry::new(Arc::clone(env), directory)?;
+    properties_registry.store(path, new_properties.clone())?;
+    report
+        .rebuilt_files
+        .push(path.with_extension(PROPERTIES_FILE_EXTENSION));
+    Ok(Some(new_properties))
+}
+
+/// Parses every complete record of a segment that can still be read and keeps key order.
+/// Returns the records along with whether every record was kept
+fn salvage_records(data: &[u8]) -> (Vec<Entry>, bool) {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut complete = data.is_empty() || data.ends_with(b"\n");
+
+    let lines = data.split(|&b| b == b'\n');
+    // The last piece is empty after a final newline and a truncated record otherwise
+    let line_count = data.split(|&b| b == b'\n').count().saturating_sub(1);
+    for line in lines.take(line_count) {
+        let readable = !line.is_empty()
+            && std::str::from_utf8(line).is_ok()
+            && !matches!(
+                line.iter()
+                    .position(|&b| b == b' ')
+                    .and_then(|at| ValuePointer::decode(&line[at + 1..])),
+                Some(Err(_))
+            );
+        let entry = Entry::from(line);
+        let in_order = entries
+            .last()
+            .is_none_or(|previous| previous.key() < entry.key());
+
+        if readable && in_order {
+            entries.push(entry);
+        } else {
+            complete = false;
+        }
+    }
+    (entries, complete)
+}
+
+/// Moves a file into `lost/`
+fn quarantine(
+    env: &dyn Env,
+    directory: &Path,
+    path: &Path,
+    report: &mut RepairReport,
+) -> std::io::Result<()> {
+    let lost_directory = directory.join(LOST_DIRECTORY_NAME);
+    env.create_dir_all(&lost_directory)?;
+    let Some(file_name) = path.file_name() else {
+        return Ok(());
+    };
+
+    tracing::warn!("Moving {} to {}", path.display(), lost_directory.display());
+    env.rename(path, &lost_directory.join(file_name))?;
+    env.sync_dir(directory)?;
+    report.lost_files.push(path.to_path_buf());
+    Ok(())
+}
+
+/// Deletes the index, filter and properties of a segment that is gone. They can be rebuilt
+/// from the segment, so there is nothing worth keeping
+fn remove_side_files(env: &dyn Env, path: &Path) -> std::io::Result<()> {
+    for extension in [
+        INDEX_FILE_EXTENSION,
+        BLOOM_FILTER_FILE_EXTENSION,
+        PROPERTIES_FILE_EXTENSION,
+    ] {
+        env.remove_file_if_exists(&path.with_extension(extension))?;
+    }
+    Ok(())
+}
+
+fn read_if_exists(env: &dyn Env, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+    match env.read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Number at the end of a numbered file's name, like the 3 in `segment_3.sst`
+fn file_number(path: &Path) -> Option<u64> {
+    path.file_stem()?.to_str()?.rsplit_once('_')?.1.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_salvage_records_drops_unreadable_and_out_of_order_records() {
+        let (entries, complete) = salvage_records(b"apple red\n\xffbad\ncherry\nbanana 1\ndate");
+
+        let keys: Vec<&[u8]> = entries.iter().map(Entry::key).collect();
+        assert_eq!(keys, vec![b"apple".as_slice(), b"cherry"]);
+        assert!(!complete);
+
+        let (entries, complete) = salvage_records(b"apple red\nbanana\n");
+        assert_eq!(entries.len(), 2);
+        assert!(complete);
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::database::{
+    Database, event_listener::BackgroundErrorReason, verification::VerificationReport,
+};
+
+/// Verifies every live segment of a shared database in the background, once right away and
+/// then every `interval`. The database is locked for one segment at a time, so reads and
+/// writes are only held up for as long as a single segment takes to check. Stops when
+/// dropped. Damaged files are reported to the database's listeners as background errors
+pub struct Scrubber {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    last_report: Arc<Mutex<Option<VerificationReport>>>,
+}
+
+impl Scrubber {
+    pub fn start<P>(database: Arc<Mutex<Database<P>>>, interval: Duration) -> std::io::Result<Self>
+    where
+        P: AsRef<Path> + Clone + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+        let last_report = Arc::new(Mutex::new(None));
+
+        let thread = {
+            let last_report = Arc::clone(&last_report);
+            std::thread::Builder::new()
+                .name("scrubber".to_string())
+                .spawn(move || {
+                    loop {
+                        let Some(report) = Self::scrub(&database) else {
+                            return;
+                        };
+                        if let Ok(mut last_report) = last_report.lock() {
+                            *last_report = Some(report);
+                        }
+
+                        match stopped.recv_timeout(interval) {
+                            Err(RecvTimeoutError::Timeout) => continue,
+                            _ => return,
+                        }
+                    }
+                })?
+        };
+
+        Ok(Self {
+            stop: Some(stop),
+            thread: Some(thread),
+            last_report,
+        })
+    }
+
+    /// Report of the last completed pass, None until the first one finishes
+    pub fn last_report(&self) -> Option<VerificationReport> {
+        self.last_report
+            .lock()
+            .ok()
+            .and_then(|report| report.clone())
+    }
+
+    /// Checks every segment that is live when the pass starts and is still live by the time
+    /// its turn comes. Returns None when the database lock is poisoned
+    fn scrub<P: AsRef<Path> + Clone>(database: &Mutex<Database<P>>) -> Option<VerificationReport> {
+        let segment_numbers = database.lock().ok()?.segment_numbers();
+
+        let mut report = VerificationReport::default();
+        for number in segment_numbers {
+            if let Some(files) = database.lock().ok()?.verify_segment(number) {
+                report.files.extend(files);
+            }
+        }
+
+        for file in report.unhealthy_files() {
+            let problem = format!("{}: {}", file.path.display(), file.problems.join("; "));
+            tracing::error!("Scrubber found problems in {}", problem);
+            let error = std::io::Error::new(std::io::ErrorKind::InvalidData, problem);
+            database
+                .lock()
+                .ok()?
+                .notify_background_error(BackgroundErrorReason::Scrub, &error);
+        }
+        tracing::info!("Scrubber checked {} files", report.files.len());
+        Some(report)
+    }
+}
+
+impl Drop for Scrubber {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            tracing::error!("Scrubber thread panicked");
+        }
+    }
+}
@@ -1,40 +1,53 @@
 use std::{
     cmp::Ordering,
-    fs::File,
     io::{BufRead, BufReader, Seek, SeekFrom, Write},
-    path::PathBuf,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use crate::database::{entry::Entry, mem_table::MemTable};
+use crate::database::{
+    atomic_file::write_atomically,
+    entry::Entry,
+    env::{Env, MappedFile, ReadableFile},
+    index_entry::IndexEntry,
+};
 
 pub const SEGMENT_FILE_EXTENSION: &str = "sst";
+/// Number of entries between two consecutive index entries
+pub(crate) const INDEX_INTERVAL: usize = 100;
 
-#[derive(PartialEq)]
 pub struct SegmentFile {
     path: PathBuf,
+    env: Arc<dyn Env>,
 }
 
 impl SegmentFile {
-    pub fn from_path(path: PathBuf) -> std::io::Result<Self> {
-        if Self::is_segment_file(&path) {
+    pub fn from_path(env: Arc<dyn Env>, path: PathBuf) -> std::io::Result<Self> {
+        if !Self::is_segment_file(&path) {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "Invalid segment file extension",
             ));
         }
 
-        Ok(Self { path })
+        Ok(Self { path, env })
     }
 
-    pub fn create_and_store(path: PathBuf, map: MemTable) -> std::io::Result<Self> {
-        let mut file = File::create(&path)?;
-        for entry in map.into_iter() {
-            file.write_all(Vec::<u8>::from(entry).as_slice())?;
-        }
-
-        file.flush()?;
-
-        Ok(Self { path })
+    /// Writes `entries`, which must already be sorted by key, as a new segment
+    pub fn create_and_store(
+        env: Arc<dyn Env>,
+        path: PathBuf,
+        entries: impl IntoIterator<Item = std::io::Result<Entry>>,
+    ) -> std::io::Result<Self> {
+        write_atomically(env.as_ref(), &path, |file| {
+            for entry in entries {
+                file.write_all(Vec::<u8>::from(entry?).as_slice())?;
+            }
+            Ok(())
+        })?;
+
+        Ok(Self { path, env })
     }
 
     pub fn path(&self) -> &PathBuf {
@@ -44,8 +57,8 @@ impl SegmentFile {
     pub fn entries(
         &self,
         start_position: Option<u64>,
-    ) -> std::io::Result<impl Iterator<Item = std::io::Result<(u64, Entry)>>> {
-        let file = File::open(&self.path)?;
+    ) -> std::io::Result<impl Iterator<Item = std::io::Result<(u64, Entry)>> + use<>> {
+        let file = self.env.open_readable(&self.path)?;
         let mut position = 0_u64;
         let mut reader = BufReader::new(file);
 
@@ -65,25 +78,113 @@ impl SegmentFile {
         }))
     }
 
-    pub fn is_segment_file(path: &PathBuf) -> bool {
+    /// Opens the segment file for reading
+    pub fn open(&self) -> std::io::Result<Box<dyn ReadableFile>> {
+        self.env.open_readable(&self.path)
+    }
+
+    /// Maps the segment file into memory, or returns None when the env cannot map files
+    pub fn map(&self) -> std::io::Result<Option<Arc<dyn MappedFile>>> {
+        self.env.map_readable(&self.path)
+    }
+
+    /// Builds the sparse index of the segment, recording the key and offset of every
+    /// `INDEX_INTERVAL`th entry
+    pub fn build_index(&self) -> std::io::Result<Vec<IndexEntry>> {
+        let mut index_entries = Vec::new();
+        for result in self.entries(None)?.step_by(INDEX_INTERVAL) {
+            let (line_start_position, entry) = result?;
+            index_entries.push(IndexEntry::new(entry.key().to_vec(), line_start_position));
+        }
+        Ok(index_entries)
+    }
+
+    /// Looks `key` up in a block of the segment. Returns Some(None) when the key was deleted
+    /// and otherwise the position of its value within the block, so callers can borrow it
+    pub fn search_block(block: &[u8], key: &[u8]) -> Option<Option<Range<usize>>> {
+        let mut line_start = 0;
+        for line in block.split(|&b| b == b'\n') {
+            let start = line_start;
+            line_start += line.len() + 1;
+            if line.is_empty() {
+                continue;
+            }
+
+            let (entry_key, value) = match line.iter().position(|&b| b == b' ') {
+                Some(at) => (&line[..at], Some(start + at + 1..start + line.len())),
+                None => (line, None),
+            };
+            match entry_key.cmp(key) {
+                Ordering::Equal => return Some(value),
+                Ordering::Less => continue,
+                Ordering::Greater => return None,
+            }
+        }
+        None
+    }
+
+    pub fn is_segment_file(path: &Path) -> bool {
         path.extension()
             .map(|ext| ext == SEGMENT_FILE_EXTENSION)
             .unwrap_or(false)
     }
+
+    /// Extracts the segment number from a file name like `segment_3.sst`
+    pub fn number(&self) -> Option<u64> {
+        Self::number_from_path(&self.path)
+    }
+
+    /// Extracts the segment number from the path of a segment or of one of its side files,
+    /// like `segment_3.idx`
+    pub fn number_from_path(path: &Path) -> Option<u64> {
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem_str| {
+                stem_str
+                    .strip_prefix("segment_")
+                    .and_then(|num_str| num_str.parse().ok())
+            })
+    }
+}
+
+impl PartialEq for SegmentFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
 }
 
 impl PartialOrd for SegmentFile {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let extract_segment_number = |path: &PathBuf| -> Option<usize> {
-            path.file_stem()
-                .and_then(|stem| stem.to_str())
-                .and_then(|stem_str| {
-                    stem_str
-                        .strip_prefix("segment_")
-                        .and_then(|num_str| num_str.parse().ok())
-                })
-        };
-
-        Some(extract_segment_number(&self.path)?.cmp(&extract_segment_number(&other.path)?))
+        Some(self.number()?.cmp(&other.number()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path_accepts_only_segment_files() {
+        let env: Arc<dyn Env> = Arc::new(crate::database::env::MemEnv::new());
+        assert!(SegmentFile::from_path(Arc::clone(&env), "/db/segment_1.sst".into()).is_ok());
+        assert_eq!(
+            SegmentFile::from_path(env, "/db/segment_1.idx".into())
+                .err()
+                .map(|error| error.kind()),
+            Some(std::io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_search_block_finds_values_and_tombstones() {
+        let block = b"apple red\nbanana\ncherry dark red\n";
+
+        let value = SegmentFile::search_block(block, b"cherry")
+            .unwrap()
+            .unwrap();
+        assert_eq!(&block[value], b"dark red");
+        assert_eq!(SegmentFile::search_block(block, b"banana"), Some(None));
+        assert_eq!(SegmentFile::search_block(block, b"blueberry"), None);
+        assert_eq!(SegmentFile::search_block(block, b"zucchini"), None);
     }
 }
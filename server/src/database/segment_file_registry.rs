@@ -1,66 +1,154 @@
 use std::{
-    cmp::Ordering,
-    collections::VecDeque,
+    collections::BTreeMap,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use crate::database::{
-    mem_table::MemTable,
+    entry::Entry,
+    env::Env,
     segment_file::{SEGMENT_FILE_EXTENSION, SegmentFile},
 };
 
 pub struct SegmentFileRegistry {
-    segment_files: VecDeque<SegmentFile>,
+    /// Live segments in lookup order: level by level, newest first within a level
+    segment_files: Vec<SegmentFile>,
+    /// Level of every live segment, keyed by segment number
+    levels: BTreeMap<u64, usize>,
     directory_path: PathBuf,
+    env: Arc<dyn Env>,
 }
 
 impl SegmentFileRegistry {
-    pub fn new<P: AsRef<Path>>(directory_path: P) -> std::io::Result<Self> {
-        let mut segment_files = Self::find_segment_files(&directory_path)?;
-
-        segment_files.sort_by(|a, b| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    /// Loads the segments in the directory. `levels` comes from the manifest; segments it
+    /// does not mention are placed in level 0
+    pub fn new<P: AsRef<Path>>(
+        env: Arc<dyn Env>,
+        directory_path: P,
+        levels: Option<&BTreeMap<u64, usize>>,
+    ) -> std::io::Result<Self> {
+        let segment_files = Self::find_segment_files(&env, directory_path.as_ref())?;
+        let levels = segment_files
+            .iter()
+            .filter_map(SegmentFile::number)
+            .map(|number| {
+                let level = levels.and_then(|levels| levels.get(&number).copied());
+                (number, level.unwrap_or_default())
+            })
+            .collect();
 
-        Ok(Self {
-            segment_files: VecDeque::from(segment_files),
+        let mut registry = Self {
+            segment_files,
+            levels,
             directory_path: directory_path.as_ref().to_path_buf(),
-        })
+            env,
+        };
+        registry.sort();
+        Ok(registry)
     }
 
-    pub fn store_new(&mut self, map: MemTable) -> std::io::Result<PathBuf> {
-        let segment_number = self.segment_files.len();
-
-        let mut file_path = self
-            .directory_path
-            .join(format!("segment_{}", segment_number));
-        file_path.set_extension(SEGMENT_FILE_EXTENSION);
+    /// Writes `entries` out as a new segment in `level`. Within a level segments are kept
+    /// newest first, so the new one is consulted before the existing segments of its level
+    pub fn store_new(
+        &mut self,
+        segment_number: u64,
+        level: usize,
+        entries: impl IntoIterator<Item = std::io::Result<Entry>>,
+    ) -> std::io::Result<PathBuf> {
+        let file_path = self.path(segment_number);
+        SegmentFile::create_and_store(Arc::clone(&self.env), file_path.clone(), entries)?;
+        self.insert(segment_number, level)
+    }
 
-        let segment_file = SegmentFile::create_and_store(file_path.clone(), map)?;
-        self.segment_files.push_back(segment_file);
+    /// Adds a segment file that was already placed in the directory to `level`
+    pub fn insert(&mut self, segment_number: u64, level: usize) -> std::io::Result<PathBuf> {
+        let file_path = self.path(segment_number);
+        let segment_file = SegmentFile::from_path(Arc::clone(&self.env), file_path.clone())?;
+        self.segment_files.push(segment_file);
+        self.levels.insert(segment_number, level);
+        self.sort();
 
         Ok(file_path)
     }
 
-    pub fn get(&self, file_path: &PathBuf) -> Option<&SegmentFile> {
+    /// Path of the segment file with the given number
+    pub fn path(&self, segment_number: u64) -> PathBuf {
+        self.directory_path
+            .join(format!("segment_{}", segment_number))
+            .with_extension(SEGMENT_FILE_EXTENSION)
+    }
+
+    /// Drops the segments from the registry, returning them so their files can be deleted
+    /// once the manifest no longer lists them
+    pub fn remove(&mut self, segment_numbers: &[u64]) -> Vec<SegmentFile> {
+        let (removed, kept) = std::mem::take(&mut self.segment_files)
+            .into_iter()
+            .partition(|file| {
+                file.number()
+                    .is_some_and(|number| segment_numbers.contains(&number))
+            });
+        self.segment_files = kept;
+        for number in segment_numbers {
+            self.levels.remove(number);
+        }
+        removed
+    }
+
+    pub fn get(&self, file_path: &Path) -> Option<&SegmentFile> {
         self.segment_files
             .iter()
             .find(|file| file.path().file_stem() == file_path.file_stem())
     }
 
+    /// Live segments in the order lookups consult them
     pub fn files(&self) -> impl Iterator<Item = &SegmentFile> {
         self.segment_files.iter()
     }
 
-    fn find_segment_files<P: AsRef<Path>>(directory_path: P) -> std::io::Result<Vec<SegmentFile>> {
-        Ok(std::fs::read_dir(directory_path.as_ref())?
-            .filter_map(Result::ok)
-            .filter_map(|entry| {
-                entry
-                    .file_type()
-                    .ok()
-                    .and_then(|ft| ft.is_file().then_some(entry.path()))
-            })
-            .filter(SegmentFile::is_segment_file)
-            .map(|path| SegmentFile::from_path(path))
-            .collect::<Result<Vec<_>, _>>()?)
+    /// Segments of one level, newest first
+    pub fn level_files(&self, level: usize) -> impl Iterator<Item = &SegmentFile> {
+        self.segment_files
+            .iter()
+            .filter(move |file| self.level(file) == level)
+    }
+
+    pub fn level(&self, segment_file: &SegmentFile) -> usize {
+        segment_file
+            .number()
+            .and_then(|number| self.levels.get(&number).copied())
+            .unwrap_or_default()
+    }
+
+    /// Level of every live segment, keyed by segment number
+    pub fn levels(&self) -> &BTreeMap<u64, usize> {
+        &self.levels
+    }
+
+    /// Highest segment number in use, if any segment exists
+    pub fn max_number(&self) -> Option<u64> {
+        self.segment_files
+            .iter()
+            .filter_map(SegmentFile::number)
+            .max()
+    }
+
+    fn sort(&mut self) {
+        let levels = &self.levels;
+        self.segment_files.sort_by_key(|file| {
+            let number = file.number().unwrap_or_default();
+            let level = levels.get(&number).copied().unwrap_or_default();
+            (level, std::cmp::Reverse(number))
+        });
+    }
+
+    fn find_segment_files(
+        env: &Arc<dyn Env>,
+        directory_path: &Path,
+    ) -> std::io::Result<Vec<SegmentFile>> {
+        env.list_files(directory_path)?
+            .into_iter()
+            .filter(|path| SegmentFile::is_segment_file(path))
+            .map(|path| SegmentFile::from_path(Arc::clone(env), path))
+            .collect::<Result<Vec<_>, _>>()
     }
 }
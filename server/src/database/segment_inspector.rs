@@ -0,0 +1,213 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::database::{
+    blocked_bloom_filter::BlockedBloomFilter,
+    bloom_filter::BloomFilter,
+    bloom_filter_registry::{BLOOM_FILTER_FILE_EXTENSION, BloomFilterRegistry},
+    entry::Entry,
+    env::{DiskEnv, Env},
+    index_entry::IndexEntry,
+    index_file::{INDEX_FILE_EXTENSION, IndexFile},
+    segment_file::SegmentFile,
+    segment_properties::{PROPERTIES_FILE_EXTENSION, SegmentProperties},
+    value_log::ValuePointer,
+    xor_filter::XorFilter,
+};
+
+/// Value of a segment record
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordValue {
+    Inline(Vec<u8>),
+    /// Value moved to the value log file `value_<file_number>.vlog`
+    Pointer {
+        file_number: u64,
+        offset: u64,
+        len: u64,
+    },
+    Tombstone,
+}
+
+/// A record of a segment along with the offset it starts at
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentRecord {
+    pub offset: u64,
+    pub key: Vec<u8>,
+    pub value: RecordValue,
+}
+
+/// What is known about a segment's filter file
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterSummary {
+    /// Filter type, e.g. "bloom" or "xor"
+    pub kind: &'static str,
+    pub file_size: u64,
+    /// Name of the prefix extractor whose prefixes the filter also holds
+    pub prefix_extractor: Option<String>,
+    /// Only known for bloom filters
+    pub num_bits: Option<usize>,
+    pub num_hashes: Option<usize>,
+    pub fill_ratio: Option<f64>,
+}
+
+/// Reads a single segment and its side files without opening the database, for debugging
+/// data directories
+pub struct SegmentInspector {
+    segment_file: SegmentFile,
+    env: Arc<dyn Env>,
+}
+
+impl SegmentInspector {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::open_with_env(Arc::new(DiskEnv::new()), path)
+    }
+
+    pub fn open_with_env<P: AsRef<Path>>(env: Arc<dyn Env>, path: P) -> std::io::Result<Self> {
+        let segment_file = SegmentFile::from_path(Arc::clone(&env), path.as_ref().to_path_buf())?;
+        // Fail early instead of on the first section read
+        env.open_readable(segment_file.path())?;
+        Ok(Self { segment_file, env })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.segment_file.path()
+    }
+
+    /// Every record of the segment, in file order
+    pub fn records(&self) -> std::io::Result<Vec<SegmentRecord>> {
+        self.segment_file
+            .entries(None)?
+            .map(|entry| entry.and_then(|(offset, entry)| SegmentRecord::new(offset, entry)))
+            .collect()
+    }
+
+    /// Entries of the segment's index, or None when it has no index file
+    pub fn index_entries(&self) -> std::io::Result<Option<Vec<IndexEntry>>> {
+        let index_path = self.side_file(INDEX_FILE_EXTENSION);
+        if self.read_if_exists(&index_path)?.is_none() {
+            return Ok(None);
+        }
+        IndexFile::from_path(Arc::clone(&self.env), index_path)?
+            .load(false)
+            .map(Some)
+    }
+
+    /// Summary of the segment's filter file, or None when it has none
+    pub fn filter(&self) -> std::io::Result<Option<FilterSummary>> {
+        let Some(data) = self.read_side_file(BLOOM_FILTER_FILE_EXTENSION)? else {
+            return Ok(None);
+        };
+        let (prefix_extractor, filter) = match BloomFilterRegistry::split_prefix_tag(&data) {
+            Some((name, filter)) => (Some(name), filter),
+            None => (None, data.as_slice()),
+        };
+
+        let mut summary = FilterSummary {
+            kind: "bloom",
+            file_size: data.len() as u64,
+            prefix_extractor,
+            num_bits: None,
+            num_hashes: None,
+            fill_ratio: None,
+        };
+        if BlockedBloomFilter::is_blocked_bloom_filter(filter) {
+            summary.kind = "blocked bloom";
+        } else if XorFilter::is_xor_filter(filter) {
+            summary.kind = "xor";
+        } else {
+            let bloom_filter = BloomFilter::deserialize(filter)?;
+            if !BloomFilter::is_versioned(filter) {
+                summary.kind = "legacy bloom";
+            }
+            summary.num_bits = Some(bloom_filter.num_bits());
+            summary.num_hashes = Some(bloom_filter.num_hashes());
+            summary.fill_ratio = Some(bloom_filter.fill_ratio());
+        }
+        Ok(Some(summary))
+    }
+
+    /// The segment's properties, or None when it was written without them
+    pub fn properties(&self) -> std::io::Result<Option<SegmentProperties>> {
+        self.read_side_file(PROPERTIES_FILE_EXTENSION)?
+            .map(|data| SegmentProperties::try_from(data.as_slice()))
+            .transpose()
+    }
+
+    /// Sizes of the segment and of each side file that exists
+    pub fn file_sizes(&self) -> std::io::Result<Vec<(PathBuf, u64)>> {
+        let mut sizes = Vec::new();
+        for path in [
+            self.segment_file.path().clone(),
+            self.side_file(INDEX_FILE_EXTENSION),
+            self.side_file(BLOOM_FILTER_FILE_EXTENSION),
+            self.side_file(PROPERTIES_FILE_EXTENSION),
+        ] {
+            if let Some(data) = self.read_if_exists(&path)? {
+                sizes.push((path, data.len() as u64));
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// Looks `key` up in this segment alone, starting from the block its index points to.
+    /// Returns None when the segment holds no record for the key
+    pub fn get(&self, key: &[u8]) -> std::io::Result<Option<SegmentRecord>> {
+        // An unreadable index only costs a scan from the start
+        let start = self.index_entries().ok().flatten().and_then(|entries| {
+            entries
+                .iter()
+                .take_while(|entry| entry.key() <= key)
+                .last()
+                .map(IndexEntry::offset)
+        });
+
+        for entry in self.segment_file.entries(start)? {
+            let (offset, entry) = entry?;
+            if entry.key() == key {
+                return SegmentRecord::new(offset, entry).map(Some);
+            }
+            if entry.key() > key {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    fn side_file(&self, extension: &str) -> PathBuf {
+        self.segment_file.path().with_extension(extension)
+    }
+
+    fn read_side_file(&self, extension: &str) -> std::io::Result<Option<Vec<u8>>> {
+        self.read_if_exists(&self.side_file(extension))
+    }
+
+    fn read_if_exists(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+        match self.env.read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl SegmentRecord {
+    fn new(offset: u64, entry: Entry) -> std::io::Result<Self> {
+        let (key, value) = match entry {
+            Entry::KeyValue { key, value } => {
+                let value = match ValuePointer::decode(&value).transpose()? {
+                    Some(pointer) => RecordValue::Pointer {
+                        file_number: pointer.file_number,
+                        offset: pointer.offset,
+                        len: pointer.len,
+                    },
+                    None => RecordValue::Inline(value),
+                };
+                (key, value)
+            }
+            Entry::Tombstone { key } => (key, RecordValue::Tombstone),
+        };
+        Ok(Self { offset, key, value })
+    }
+}
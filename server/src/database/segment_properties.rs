@@ -0,0 +1,190 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::database::entry::Entry;
+
+pub const PROPERTIES_FILE_EXTENSION: &str = "props";
+
+const MIN_KEY: &str = "min_key";
+const MAX_KEY: &str = "max_key";
+const NUM_ENTRIES: &str = "num_entries";
+const NUM_TOMBSTONES: &str = "num_tombstones";
+const RAW_KEY_BYTES: &str = "raw_key_bytes";
+const RAW_VALUE_BYTES: &str = "raw_value_bytes";
+const MIN_SEQUENCE: &str = "min_sequence";
+const MAX_SEQUENCE: &str = "max_sequence";
+const CREATED_AT: &str = "created_at";
+const SEGMENT_CHECKSUM: &str = "segment_checksum";
+const INDEX_CHECKSUM: &str = "index_checksum";
+const FILTER_CHECKSUM: &str = "filter_checksum";
+
+/// Summary of a segment's contents, written next to it when it is created
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SegmentProperties {
+    pub min_key: Vec<u8>,
+    pub max_key: Vec<u8>,
+    pub num_entries: u64,
+    pub num_tombstones: u64,
+    pub raw_key_bytes: u64,
+    pub raw_value_bytes: u64,
+    /// Sequence numbers of the oldest and newest write the segment holds
+    pub min_sequence: u64,
+    pub max_sequence: u64,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    /// `hash64` of the segment, index and filter files as written. None for files that were
+    /// not written or segments written before checksums were recorded
+    pub segment_checksum: Option<u64>,
+    pub index_checksum: Option<u64>,
+    pub filter_checksum: Option<u64>,
+}
+
+impl SegmentProperties {
+    /// Empty properties for a segment holding the writes numbered `min_sequence` through
+    /// `max_sequence`
+    pub fn new(min_sequence: u64, max_sequence: u64) -> Self {
+        Self {
+            min_sequence,
+            max_sequence,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Accounts for the next entry written to the segment. Entries arrive in key order
+    pub fn add(&mut self, entry: &Entry) {
+        if self.num_entries == 0 {
+            self.min_key = entry.key().to_vec();
+        }
+        self.max_key = entry.key().to_vec();
+        self.num_entries += 1;
+        self.raw_key_bytes += entry.key().len() as u64;
+        match entry {
+            Entry::KeyValue { value, .. } => self.raw_value_bytes += value.len() as u64,
+            Entry::Tombstone { .. } => self.num_tombstones += 1,
+        }
+    }
+
+    /// Whether `key` falls inside the segment's key range
+    pub fn may_contain_key(&self, key: &[u8]) -> bool {
+        self.num_entries > 0 && self.min_key.as_slice() <= key && key <= self.max_key.as_slice()
+    }
+
+    /// Whether the segment's key range overlaps the keys starting with `prefix`
+    pub fn may_contain_prefix(&self, prefix: &[u8]) -> bool {
+        self.num_entries > 0
+            && self.max_key.as_slice() >= prefix
+            && (self.min_key.as_slice() <= prefix || self.min_key.starts_with(prefix))
+    }
+}
+
+impl From<&SegmentProperties> for Vec<u8> {
+    fn from(value: &SegmentProperties) -> Self {
+        let checksums = [
+            (SEGMENT_CHECKSUM, value.segment_checksum),
+            (INDEX_CHECKSUM, value.index_checksum),
+            (FILTER_CHECKSUM, value.filter_checksum),
+        ]
+        .into_iter()
+        .filter_map(|(name, checksum)| Some((name, checksum?.to_string())));
+
+        [
+            (MIN_KEY, to_hex(&value.min_key)),
+            (MAX_KEY, to_hex(&value.max_key)),
+            (NUM_ENTRIES, value.num_entries.to_string()),
+            (NUM_TOMBSTONES, value.num_tombstones.to_string()),
+            (RAW_KEY_BYTES, value.raw_key_bytes.to_string()),
+            (RAW_VALUE_BYTES, value.raw_value_bytes.to_string()),
+            (MIN_SEQUENCE, value.min_sequence.to_string()),
+            (MAX_SEQUENCE, value.max_sequence.to_string()),
+            (CREATED_AT, value.created_at.to_string()),
+        ]
+        .into_iter()
+        .chain(checksums)
+        .map(|(name, value)| format!("{} {}\n", name, value))
+        .collect::<String>()
+        .into_bytes()
+    }
+}
+
+impl TryFrom<&[u8]> for SegmentProperties {
+    type Error = std::io::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid segment properties record",
+            )
+        };
+        let text = std::str::from_utf8(value).map_err(|_| invalid())?;
+        let mut properties = SegmentProperties::default();
+
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || value.parse::<u64>().map_err(|_| invalid());
+            match name {
+                MIN_KEY => properties.min_key = from_hex(value).ok_or_else(invalid)?,
+                MAX_KEY => properties.max_key = from_hex(value).ok_or_else(invalid)?,
+                NUM_ENTRIES => properties.num_entries = number()?,
+                NUM_TOMBSTONES => properties.num_tombstones = number()?,
+                RAW_KEY_BYTES => properties.raw_key_bytes = number()?,
+                RAW_VALUE_BYTES => properties.raw_value_bytes = number()?,
+                MIN_SEQUENCE => properties.min_sequence = number()?,
+                MAX_SEQUENCE => properties.max_sequence = number()?,
+                CREATED_AT => properties.created_at = number()?,
+                SEGMENT_CHECKSUM => properties.segment_checksum = Some(number()?),
+                INDEX_CHECKSUM => properties.index_checksum = Some(number()?),
+                FILTER_CHECKSUM => properties.filter_checksum = Some(number()?),
+                // Properties added by later versions are skipped
+                _ => {}
+            }
+        }
+
+        Ok(properties)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_properties_round_trip() {
+        let mut properties = SegmentProperties::new(4, 9);
+        properties.add(&Entry::KeyValue {
+            key: b"apple".to_vec(),
+            value: b"red".to_vec(),
+        });
+        properties.add(&Entry::Tombstone {
+            key: b"cherry\xff".to_vec(),
+        });
+        properties.segment_checksum = Some(u64::MAX);
+
+        let loaded = SegmentProperties::try_from(Vec::<u8>::from(&properties).as_slice()).unwrap();
+        assert_eq!(loaded, properties);
+        assert_eq!(loaded.num_entries, 2);
+        assert_eq!(loaded.num_tombstones, 1);
+        assert!(loaded.may_contain_key(b"banana"));
+        assert!(!loaded.may_contain_key(b"date"));
+        assert!(loaded.may_contain_prefix(b"ch"));
+        assert!(loaded.may_contain_prefix(b"a"));
+        assert!(!loaded.may_contain_prefix(b"aa"));
+    }
+}
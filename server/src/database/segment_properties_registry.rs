@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::database::{
+    atomic_file::write_atomically,
+    env::Env,
+    segment_properties::{PROPERTIES_FILE_EXTENSION, SegmentProperties},
+};
+
+pub struct SegmentPropertiesRegistry {
+    /// Properties keyed by base file name (without extension), e.g., "segment_0"
+    properties: HashMap<String, SegmentProperties>,
+    env: Arc<dyn Env>,
+}
+
+impl SegmentPropertiesRegistry {
+    /// Loads the properties of every segment in the directory. Segments written before
+    /// properties existed, or whose properties cannot be read, simply have none
+    pub fn new<P: AsRef<Path>>(env: Arc<dyn Env>, directory: P) -> std::io::Result<Self> {
+        let properties = env
+            .list_files(directory.as_ref())?
+            .into_iter()
+            .filter(|path| {
+                path.extension()
+                    .map(|ext| ext == PROPERTIES_FILE_EXTENSION)
+                    .unwrap_or(false)
+            })
+            .filter_map(|path| {
+                let base_name = path.file_stem()?.to_str()?.to_string();
+                let loaded = env
+                    .read(&path)
+                    .and_then(|data| SegmentProperties::try_from(data.as_slice()));
+                match loaded {
+                    Ok(properties) => Some((base_name, properties)),
+                    Err(error) => {
+                        tracing::error!(
+                            "Failed to load segment properties {}: {}",
+                            path.display(),
+                            error
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Ok(Self { properties, env })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&SegmentProperties> {
+        let base_name = path.file_stem()?.to_str()?;
+        self.properties.get(base_name)
+    }
+
+    pub fn store(&mut self, path: &Path, properties: SegmentProperties) -> std::io::Result<()> {
+        let properties_path = Self::properties_path(path);
+        write_atomically(self.env.as_ref(), &properties_path, |file| {
+            file.write_all(&Vec::<u8>::from(&properties))
+        })?;
+
+        if let Some(base_name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            self.properties.insert(base_name.to_string(), properties);
+        }
+        Ok(())
+    }
+
+    /// Deletes the properties of a segment that is no longer live
+    pub fn remove(&mut self, path: &Path) -> std::io::Result<()> {
+        if let Some(base_name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            self.properties.remove(base_name);
+        }
+        self.env.remove_file_if_exists(&Self::properties_path(path))
+    }
+
+    fn properties_path(path: &Path) -> PathBuf {
+        let mut properties_path = path.to_path_buf();
+        properties_path.set_extension(PROPERTIES_FILE_EXTENSION);
+        properties_path
+    }
+}
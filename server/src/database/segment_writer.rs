@@ -0,0 +1,176 @@
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::database::{
+    atomic_file::{temp_path, write_atomically},
+    bloom_filter_registry::{BLOOM_FILTER_FILE_EXTENSION, BloomFilterRegistry},
+    entry::Entry,
+    env::{Env, WritableFile},
+    filter_policy::FilterPolicy,
+    hash::hash64,
+    index_entry::IndexEntry,
+    index_file::{INDEX_FILE_EXTENSION, IndexFile},
+    options::DatabaseOptions,
+    prefix_extractor::PrefixExtractor,
+    segment_file::{INDEX_INTERVAL, SegmentFile},
+    segment_properties::{PROPERTIES_FILE_EXTENSION, SegmentProperties},
+    value_log::ValuePointer,
+};
+
+/// Builds a finished segment, with its index, filter and properties, outside of any
+/// database, for loading with `Database::ingest_external_files`. Records must be added in
+/// strictly increasing key order. The segment is written under a temporary name and only
+/// appears at its path once `finish` succeeds
+pub struct SegmentWriter {
+    path: PathBuf,
+    file: BufWriter<Box<dyn WritableFile>>,
+    offset: u64,
+    keys: Vec<Vec<u8>>,
+    index_entries: Vec<IndexEntry>,
+    properties: SegmentProperties,
+    policy: Arc<dyn FilterPolicy>,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    env: Arc<dyn Env>,
+}
+
+impl SegmentWriter {
+    /// Starts a segment at `path`, which must end in `.sst`, with a bloom filter
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::create_with_options(path, &DatabaseOptions::default())
+    }
+
+    /// Like `create`, writing through the env of `options` and building the filter with its
+    /// first filter policy and its prefix extractor
+    pub fn create_with_options<P: AsRef<Path>>(
+        path: P,
+        options: &DatabaseOptions,
+    ) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if !SegmentFile::is_segment_file(&path) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid segment file extension",
+            ));
+        }
+
+        let env = options.env();
+        let file = BufWriter::new(env.create_writable(&temp_path(&path))?);
+        Ok(Self {
+            path,
+            file,
+            offset: 0,
+            keys: Vec::new(),
+            index_entries: Vec::new(),
+            // Ingestion assigns the segment its sequence number
+            properties: SegmentProperties::new(0, 0),
+            policy: Arc::clone(&options.filter_policies()[0]),
+            prefix_extractor: options.prefix_extractor.clone(),
+            env,
+        })
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> std::io::Result<()> {
+        if std::str::from_utf8(value).is_err()
+            || value.contains(&b'\n')
+            || value.ends_with(b"\r")
+            || ValuePointer::decode(value).is_some()
+        {
+            return Err(invalid_input("Value cannot be stored in a segment"));
+        }
+        self.add(Entry::KeyValue {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        })
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> std::io::Result<()> {
+        self.add(Entry::Tombstone { key: key.to_vec() })
+    }
+
+    fn add(&mut self, entry: Entry) -> std::io::Result<()> {
+        let key = entry.key();
+        if key.is_empty()
+            || std::str::from_utf8(key).is_err()
+            || key.iter().any(|&b| matches!(b, b' ' | b'\n' | b'\r'))
+        {
+            return Err(invalid_input("Key cannot be stored in a segment"));
+        }
+        if self.keys.last().is_some_and(|last| last.as_slice() >= key) {
+            return Err(invalid_input(
+                "Keys must be added in strictly increasing order",
+            ));
+        }
+
+        if self.keys.len().is_multiple_of(INDEX_INTERVAL) {
+            self.index_entries
+                .push(IndexEntry::new(key.to_vec(), self.offset));
+        }
+        self.keys.push(key.to_vec());
+        self.properties.add(&entry);
+
+        let record = Vec::<u8>::from(entry);
+        self.file.write_all(&record)?;
+        self.offset += record.len() as u64;
+        Ok(())
+    }
+
+    /// Number of records added so far
+    pub fn num_entries(&self) -> u64 {
+        self.properties.num_entries
+    }
+
+    /// Makes the segment durable at its path along with its side files and returns its
+    /// properties
+    pub fn finish(mut self) -> std::io::Result<SegmentProperties> {
+        self.file.flush()?;
+        self.file.get_mut().sync()?;
+        drop(self.file);
+        self.env.rename(&temp_path(&self.path), &self.path)?;
+        if let Some(directory) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            self.env.sync_dir(directory)?;
+        }
+
+        let index_path = self.path.with_extension(INDEX_FILE_EXTENSION);
+        if !self.index_entries.is_empty() {
+            IndexFile::create_and_store(
+                Arc::clone(&self.env),
+                index_path.clone(),
+                self.index_entries,
+            )?;
+        }
+        let keys: Vec<&[u8]> = self.keys.iter().map(Vec::as_slice).collect();
+        BloomFilterRegistry::write_filter(
+            self.env.as_ref(),
+            &self.path,
+            self.policy.as_ref(),
+            self.prefix_extractor.as_deref(),
+            &keys,
+        )?;
+
+        let index_checksum = if self.keys.is_empty() {
+            None
+        } else {
+            Some(hash64(&self.env.read(&index_path)?))
+        };
+        let filter_path = self.path.with_extension(BLOOM_FILTER_FILE_EXTENSION);
+        let properties = SegmentProperties {
+            segment_checksum: Some(hash64(&self.env.read(&self.path)?)),
+            index_checksum,
+            filter_checksum: Some(hash64(&self.env.read(&filter_path)?)),
+            ..self.properties
+        };
+        write_atomically(
+            self.env.as_ref(),
+            &self.path.with_extension(PROPERTIES_FILE_EXTENSION),
+            |file| file.write_all(&Vec::<u8>::from(&properties)),
+        )?;
+        Ok(properties)
+    }
+}
+
+fn invalid_input(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
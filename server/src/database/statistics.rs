@@ -0,0 +1,232 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Counters the engine updates as it works. Reads update them through a shared reference,
+/// so they are atomic
+#[derive(Debug, Default)]
+pub struct Statistics {
+    filter_checks: AtomicU64,
+    filter_useful: AtomicU64,
+    flushes: AtomicU64,
+    flush_micros: AtomicU64,
+    flush_bytes_written: AtomicU64,
+    compactions: AtomicU64,
+    compaction_micros: AtomicU64,
+    compaction_bytes_read: AtomicU64,
+    compaction_bytes_written: AtomicU64,
+    wal_bytes_written: AtomicU64,
+    user_bytes_written: AtomicU64,
+}
+
+impl Statistics {
+    /// Records a filter lookup, which is useful when it rules the segment out
+    pub fn record_filter_check(&self, useful: bool) {
+        self.filter_checks.fetch_add(1, Ordering::Relaxed);
+        if useful {
+            self.filter_useful.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_flush(&self, duration: Duration, bytes_written: u64) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        self.flush_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.flush_bytes_written
+            .fetch_add(bytes_written, Ordering::Relaxed);
+    }
+
+    pub fn record_compaction(&self, duration: Duration, bytes_read: u64, bytes_written: u64) {
+        self.compactions.fetch_add(1, Ordering::Relaxed);
+        self.compaction_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.compaction_bytes_read
+            .fetch_add(bytes_read, Ordering::Relaxed);
+        self.compaction_bytes_written
+            .fetch_add(bytes_written, Ordering::Relaxed);
+    }
+
+    /// Records a write of `user_bytes` of keys and values that took `wal_bytes` in the WAL
+    pub fn record_write(&self, user_bytes: u64, wal_bytes: u64) {
+        self.user_bytes_written
+            .fetch_add(user_bytes, Ordering::Relaxed);
+        self.wal_bytes_written
+            .fetch_add(wal_bytes, Ordering::Relaxed);
+    }
+
+    /// Copies the counters into `stats`
+    pub fn fill(&self, stats: &mut DatabaseStats) {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        stats.filter_checks = load(&self.filter_checks);
+        stats.filter_useful = load(&self.filter_useful);
+        stats.flushes = load(&self.flushes);
+        stats.flush_duration = Duration::from_micros(load(&self.flush_micros));
+        stats.flush_bytes_written = load(&self.flush_bytes_written);
+        stats.compactions = load(&self.compactions);
+        stats.compaction_duration = Duration::from_micros(load(&self.compaction_micros));
+        stats.compaction_bytes_read = load(&self.compaction_bytes_read);
+        stats.compaction_bytes_written = load(&self.compaction_bytes_written);
+        stats.wal_bytes_written = load(&self.wal_bytes_written);
+        stats.user_bytes_written = load(&self.user_bytes_written);
+    }
+}
+
+/// What a database holds and the work it has done since it was opened, as returned by
+/// `Database::stats`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatabaseStats {
+    pub mem_table_entries: u64,
+    /// Bytes of keys and values in the in-memory table
+    pub mem_table_bytes: u64,
+    /// Number of live segments in each level, starting at level 0
+    pub segments_per_level: Vec<u64>,
+    /// Bytes on disk per file type, keyed by extension, e.g. "sst" or "log". Files without
+    /// an extension are keyed by their name
+    pub disk_bytes: BTreeMap<String, u64>,
+    /// Segment filter lookups, and how many of them ruled the segment out
+    pub filter_checks: u64,
+    pub filter_useful: u64,
+    pub block_cache_hits: u64,
+    pub block_cache_misses: u64,
+    pub metadata_cache_hits: u64,
+    pub metadata_cache_misses: u64,
+    pub flushes: u64,
+    pub flush_duration: Duration,
+    /// Bytes of segments and value log records written by flushes
+    pub flush_bytes_written: u64,
+    pub compactions: u64,
+    pub compaction_duration: Duration,
+    pub compaction_bytes_read: u64,
+    pub compaction_bytes_written: u64,
+    pub wal_bytes_written: u64,
+    /// Bytes of keys and values passed to `set` and `delete`
+    pub user_bytes_written: u64,
+}
+
+impl DatabaseStats {
+    pub fn block_cache_hit_rate(&self) -> Option<f64> {
+        hit_rate(self.block_cache_hits, self.block_cache_misses)
+    }
+
+    pub fn metadata_cache_hit_rate(&self) -> Option<f64> {
+        hit_rate(self.metadata_cache_hits, self.metadata_cache_misses)
+    }
+
+    /// Bytes written to the WAL, flushed segments and compacted segments for every byte
+    /// of keys and values written. None before anything was written
+    pub fn write_amplification(&self) -> Option<f64> {
+        if self.user_bytes_written == 0 {
+            return None;
+        }
+        let written =
+            self.wal_bytes_written + self.flush_bytes_written + self.compaction_bytes_written;
+        Some(written as f64 / self.user_bytes_written as f64)
+    }
+
+    /// Every statistic as a name and value, under the names `Database::property` accepts.
+    /// Ratios that are not known yet are left out
+    pub fn properties(&self) -> Vec<(String, String)> {
+        let mut properties = vec![
+            (
+                "mem-table-entries".to_string(),
+                self.mem_table_entries.to_string(),
+            ),
+            (
+                "mem-table-bytes".to_string(),
+                self.mem_table_bytes.to_string(),
+            ),
+        ];
+        for (level, count) in self.segments_per_level.iter().enumerate() {
+            properties.push((format!("segments-at-level{}", level), count.to_string()));
+        }
+        for (file_type, bytes) in &self.disk_bytes {
+            properties.push((format!("disk-bytes-{}", file_type), bytes.to_string()));
+        }
+
+        let counters = [
+            ("filter-checks", self.filter_checks),
+            ("filter-useful", self.filter_useful),
+            ("block-cache-hits", self.block_cache_hits),
+            ("block-cache-misses", self.block_cache_misses),
+            ("metadata-cache-hits", self.metadata_cache_hits),
+            ("metadata-cache-misses", self.metadata_cache_misses),
+            ("flushes", self.flushes),
+            ("flush-micros", self.flush_duration.as_micros() as u64),
+            ("flush-bytes-written", self.flush_bytes_written),
+            ("compactions", self.compactions),
+            (
+                "compaction-micros",
+                self.compaction_duration.as_micros() as u64,
+            ),
+            ("compaction-bytes-read", self.compaction_bytes_read),
+            ("compaction-bytes-written", self.compaction_bytes_written),
+            ("wal-bytes-written", self.wal_bytes_written),
+            ("user-bytes-written", self.user_bytes_written),
+        ];
+        properties.extend(
+            counters
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        );
+
+        let ratios = [
+            ("block-cache-hit-rate", self.block_cache_hit_rate()),
+            ("metadata-cache-hit-rate", self.metadata_cache_hit_rate()),
+            ("write-amplification", self.write_amplification()),
+        ];
+        properties.extend(
+            ratios
+                .into_iter()
+                .filter_map(|(name, ratio)| Some((name.to_string(), format!("{:.3}", ratio?)))),
+        );
+        properties
+    }
+}
+
+fn hit_rate(hits: u64, misses: u64) -> Option<f64> {
+    match hits + misses {
+        0 => None,
+        lookups => Some(hits as f64 / lookups as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics_fill_snapshot_and_ratios() {
+        let statistics = Statistics::default();
+        statistics.record_write(100, 120);
+        statistics.record_flush(Duration::from_millis(2), 80);
+        statistics.record_compaction(Duration::from_millis(3), 80, 60);
+        statistics.record_filter_check(true);
+        statistics.record_filter_check(false);
+
+        let mut stats = DatabaseStats {
+            block_cache_hits: 3,
+            block_cache_misses: 1,
+            ..Default::default()
+        };
+        statistics.fill(&mut stats);
+        assert_eq!(stats.filter_checks, 2);
+        assert_eq!(stats.filter_useful, 1);
+        assert_eq!(stats.flush_duration, Duration::from_millis(2));
+        assert_eq!(stats.write_amplification(), Some(2.6));
+        assert_eq!(stats.block_cache_hit_rate(), Some(0.75));
+        assert_eq!(stats.metadata_cache_hit_rate(), None);
+
+        let properties = stats.properties();
+        let property = |name: &str| {
+            properties
+                .iter()
+                .find(|(property, _)| property == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(property("compaction-micros"), Some("3000"));
+        assert_eq!(property("write-amplification"), Some("2.600"));
+        assert_eq!(property("metadata-cache-hit-rate"), None);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use crate::database::{
+    env::{MappedFile, ReadableFile},
+    index_entry::IndexEntry,
+    lru_cache::LruCache,
+    segment_file::SegmentFile,
+};
+
+pub const DEFAULT_TABLE_CACHE_CAPACITY: usize = 64;
+
+/// Location of a block inside a segment file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockHandle {
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Contents of a block, either read into memory or borrowed from a memory mapped segment
+#[derive(Clone, Debug)]
+pub enum Block {
+    Read(Arc<Vec<u8>>),
+    Mapped {
+        file: Arc<dyn MappedFile>,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl Deref for Block {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Block::Read(data) => data,
+            Block::Mapped { file, start, end } => &file.bytes()[*start..*end],
+        }
+    }
+}
+
+enum TableFile {
+    Buffered(Mutex<Box<dyn ReadableFile>>),
+    Mapped(Arc<dyn MappedFile>),
+}
+
+/// An open segment file
+pub struct Table {
+    file: TableFile,
+    file_len: u64,
+}
+
+impl Table {
+    fn open(segment_file: &SegmentFile, use_mmap: bool) -> std::io::Result<Self> {
+        let mapped = if use_mmap { segment_file.map()? } else { None };
+        let (file, file_len) = match mapped {
+            Some(mapped) => {
+                let file_len = mapped.bytes().len() as u64;
+                (TableFile::Mapped(mapped), file_len)
+            }
+            None => {
+                let mut file = segment_file.open()?;
+                let file_len = file.seek(SeekFrom::End(0))?;
+                (TableFile::Buffered(Mutex::new(file)), file_len)
+            }
+        };
+
+        Ok(Self { file, file_len })
+    }
+
+    /// Finds the block that would hold `key` with a binary search over the segment's index,
+    /// sorted by key. The block starts at the last indexed entry at or before the key and
+    /// ends at the next indexed entry, or at the end of the file. Returns None when the key
+    /// sorts before everything in the segment
+    pub fn find_block(&self, index: &[IndexEntry], key: &[u8]) -> Option<BlockHandle> {
+        let position = index.partition_point(|entry| entry.key() <= key);
+        let start = index.get(position.checked_sub(1)?)?.offset();
+        let end = index
+            .get(position)
+            .map(IndexEntry::offset)
+            .unwrap_or(self.file_len);
+
+        Some(BlockHandle {
+            offset: start,
+            len: end.saturating_sub(start),
+        })
+    }
+
+    /// Whether blocks are borrowed from a memory map rather than read from the file
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.file, TableFile::Mapped(_))
+    }
+
+    /// Returns the raw bytes of a block, either borrowed from the memory map or read through
+    /// the already open file handle
+    pub fn read_block(&self, block: BlockHandle) -> std::io::Result<Block> {
+        match &self.file {
+            TableFile::Mapped(file) => {
+                let start = block.offset as usize;
+                let end = start.saturating_add(block.len as usize);
+                if end > file.bytes().len() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "Block extends past the end of the segment",
+                    ));
+                }
+                Ok(Block::Mapped {
+                    file: Arc::clone(file),
+                    start,
+                    end,
+                })
+            }
+            TableFile::Buffered(file) => {
+                let mut file = file
+                    .lock()
+                    .map_err(|error| std::io::Error::other(error.to_string()))?;
+                file.seek(SeekFrom::Start(block.offset))?;
+
+                let mut data = vec![0; block.len as usize];
+                file.read_exact(&mut data)?;
+                Ok(Block::Read(Arc::new(data)))
+            }
+        }
+    }
+}
+
+/// Bounded cache of open segment files keyed by segment number, so lookups don't reopen
+/// the file
+pub struct TableCache {
+    tables: Mutex<LruCache<u64, Arc<Table>>>,
+    use_mmap: bool,
+}
+
+impl TableCache {
+    /// Create a cache keeping at most `capacity` segment files open, memory mapping them
+    /// when `use_mmap` is set
+    pub fn new(capacity: usize, use_mmap: bool) -> Self {
+        Self {
+            tables: Mutex::new(LruCache::new(capacity)),
+            use_mmap,
+        }
+    }
+
+    /// Returns the open table for the segment, opening it on a miss
+    pub fn get(&self, segment_file: &SegmentFile) -> std::io::Result<Arc<Table>> {
+        let Some(number) = segment_file.number() else {
+            return Ok(Arc::new(Table::open(segment_file, self.use_mmap)?));
+        };
+
+        if let Some(table) = self.lock()?.get(&number) {
+            return Ok(table);
+        }
+
+        let table = Arc::new(Table::open(segment_file, self.use_mmap)?);
+        self.lock()?.insert(number, Arc::clone(&table), 1);
+        Ok(table)
+    }
+
+    /// Closes the table of a segment that is no longer live
+    pub fn evict(&self, segment_number: u64) -> std::io::Result<()> {
+        self.lock()?.remove(&segment_number);
+        Ok(())
+    }
+
+    fn lock(&self) -> std::io::Result<std::sync::MutexGuard<'_, LruCache<u64, Arc<Table>>>> {
+        self.tables
+            .lock()
+            .map_err(|error| std::io::Error::other(error.to_string()))
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::BTreeSet,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::database::env::{Env, WritableFile};
+
+pub const VALUE_LOG_FILE_EXTENSION: &str = "vlog";
+/// Size a value log file grows to before writes move on to a new one, when not configured
+pub const DEFAULT_VALUE_LOG_FILE_SIZE: usize = 64 * 1024 * 1024;
+/// First byte of a segment value that points into the value log. Values starting with it
+/// are always moved to the value log, so an inline value is never mistaken for a pointer
+const POINTER_TAG: u8 = 0;
+
+/// Location of a value that was moved out of its segment into a value log file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ValuePointer {
+    pub file_number: u64,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl ValuePointer {
+    /// Parses a segment value, returning None when it holds the value itself
+    pub fn decode(value: &[u8]) -> Option<std::io::Result<Self>> {
+        let fields = value.strip_prefix(&[POINTER_TAG])?;
+        let numbers = std::str::from_utf8(fields)
+            .ok()
+            .map(|fields| {
+                fields
+                    .split(' ')
+                    .map(|number| number.parse::<u64>().ok())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .unwrap_or_default();
+
+        Some(match numbers.as_deref() {
+            Some(&[file_number, offset, len]) => Ok(Self {
+                file_number,
+                offset,
+                len,
+            }),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid value log pointer",
+            )),
+        })
+    }
+}
+
+impl From<ValuePointer> for Vec<u8> {
+    fn from(value: ValuePointer) -> Self {
+        let mut data = vec![POINTER_TAG];
+        data.extend_from_slice(
+            format!("{} {} {}", value.file_number, value.offset, value.len).as_bytes(),
+        );
+        data
+    }
+}
+
+/// Append-only files holding values at least `threshold` bytes long, so flushes and
+/// compactions only copy a small pointer around instead of the value itself. A new file is
+/// started on every open and whenever the current one reaches `file_size`
+pub struct ValueLog {
+    directory: PathBuf,
+    threshold: Option<usize>,
+    file_size: usize,
+    /// Numbers of every value log file in the directory, including the active one
+    file_numbers: BTreeSet<u64>,
+    /// File new values are appended to, along with its number and length
+    active: Option<(u64, Box<dyn WritableFile>, u64)>,
+    env: Arc<dyn Env>,
+}
+
+impl ValueLog {
+    pub fn new<P: AsRef<Path>>(
+        env: Arc<dyn Env>,
+        directory: P,
+        threshold: Option<usize>,
+        file_size: Option<usize>,
+    ) -> std::io::Result<Self> {
+        let file_numbers = env
+            .list_files(directory.as_ref())?
+            .iter()
+            .filter_map(|path| Self::file_number(path))
+            .collect();
+
+        Ok(Self {
+            directory: directory.as_ref().to_path_buf(),
+            threshold,
+            file_size: file_size.unwrap_or(DEFAULT_VALUE_LOG_FILE_SIZE),
+            file_numbers,
+            active: None,
+            env,
+        })
+    }
+
+    /// Whether the value is stored in the value log rather than in its segment
+    pub fn should_separate(&self, value: &[u8]) -> bool {
+        value.first() == Some(&POINTER_TAG)
+            || self
+                .threshold
+                .is_some_and(|threshold| value.len() >= threshold)
+    }
+
+    /// Whether the next value needs a new file, which the caller numbers and passes to
+    /// `create_file`
+    pub fn needs_new_file(&self) -> bool {
+        self.active
+            .as_ref()
+            .is_none_or(|(_, _, len)| *len >= self.file_size as u64)
+    }
+
+    /// Seals the active file, if any, and starts appending to a new one
+    pub fn create_file(&mut self, number: u64) -> std::io::Result<()> {
+        self.sync()?;
+        let file = self.env.create_writable(&self.path(number))?;
+        // Pointers into the file are only useful if the file outlives a crash
+        self.env.sync_dir(&self.directory)?;
+
+        self.file_numbers.insert(number);
+        self.active = Some((number, file, 0));
+        Ok(())
+    }
+
+    /// Appends a value to the active file. It is only durable after the next `sync`
+    pub fn append(&mut self, value: &[u8]) -> std::io::Result<ValuePointer> {
+        let Some((file_number, file, len)) = &mut self.active else {
+            return Err(std::io::Error::other("No active value log file"));
+        };
+        file.write_all(value)?;
+
+        let pointer = ValuePointer {
+            file_number: *file_number,
+            offset: *len,
+            len: value.len() as u64,
+        };
+        *len += value.len() as u64;
+        Ok(pointer)
+    }
+
+    /// Makes every appended value durable
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        match &mut self.active {
+            Some((_, file, _)) => file.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Stops appending to the active file, so every file is immutable until the next value
+    /// is separated and starts a new one
+    pub fn seal(&mut self) -> std::io::Result<()> {
+        self.sync()?;
+        self.active = None;
+        Ok(())
+    }
+
+    /// Paths of every value log file, oldest first
+    pub fn file_paths(&self) -> Vec<PathBuf> {
+        self.file_numbers
+            .iter()
+            .map(|number| self.path(*number))
+            .collect()
+    }
+
+    pub fn read(&self, pointer: ValuePointer) -> std::io::Result<Vec<u8>> {
+        self.reader().read(pointer)
+    }
+
+    /// Reads values without borrowing the value log, e.g. while a compaction writes out the
+    /// entries pointing at them
+    pub fn reader(&self) -> ValueLogReader {
+        ValueLogReader {
+            directory: self.directory.clone(),
+            env: Arc::clone(&self.env),
+        }
+    }
+
+    /// Oldest file no longer being appended to, which garbage collection reclaims first
+    pub fn oldest_sealed_file(&self) -> Option<u64> {
+        let active = self.active.as_ref().map(|(number, _, _)| *number);
+        self.file_numbers
+            .iter()
+            .copied()
+            .find(|number| Some(*number) != active)
+    }
+
+    /// Deletes a sealed file once no live segment entry points into it
+    pub fn remove_file(&mut self, number: u64) -> std::io::Result<()> {
+        tracing::info!("Deleting value log file {}", self.path(number).display());
+        self.file_numbers.remove(&number);
+        self.env.remove_file_if_exists(&self.path(number))
+    }
+
+    pub fn max_number(&self) -> Option<u64> {
+        self.file_numbers.last().copied()
+    }
+
+    fn path(&self, number: u64) -> PathBuf {
+        value_log_path(&self.directory, number)
+    }
+
+    /// Extracts the file number from a value log file name like `value_3.vlog`
+    pub fn file_number(path: &Path) -> Option<u64> {
+        if path.extension()? != VALUE_LOG_FILE_EXTENSION {
+            return None;
+        }
+        path.file_stem()?
+            .to_str()?
+            .strip_prefix("value_")?
+            .parse()
+            .ok()
+    }
+}
+
+/// Reads values out of the value log files of a directory
+#[derive(Clone)]
+pub struct ValueLogReader {
+    directory: PathBuf,
+    env: Arc<dyn Env>,
+}
+
+impl ValueLogReader {
+    pub fn read(&self, pointer: ValuePointer) -> std::io::Result<Vec<u8>> {
+        let path = value_log_path(&self.directory, pointer.file_number);
+        let mut file = self.env.open_readable(&path)?;
+        file.seek(SeekFrom::Start(pointer.offset))?;
+
+        let mut value = vec![0; pointer.len as usize];
+        file.read_exact(&mut value)?;
+        Ok(value)
+    }
+}
+
+fn value_log_path(directory: &Path, number: u64) -> PathBuf {
+    directory
+        .join(format!("value_{}", number))
+        .with_extension(VALUE_LOG_FILE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_pointer_round_trip() {
+        let pointer = ValuePointer {
+            file_number: 7,
+            offset: 4096,
+            len: 100_000,
+        };
+
+        let encoded = Vec::<u8>::from(pointer);
+        assert_eq!(ValuePointer::decode(&encoded).unwrap().unwrap(), pointer);
+        assert!(ValuePointer::decode(b"inline value").is_none());
+        assert!(ValuePointer::decode(b"\x00garbage").unwrap().is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+
+use crate::database::{
+    filter_policy::Filter, hash::hash64, index_entry::IndexEntry,
+    segment_properties::SegmentProperties, value_log::ValuePointer,
+};
+
+/// Problems found in a single file. A file without problems is healthy
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub problems: Vec<String>,
+}
+
+impl FileReport {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            problems: Vec::new(),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    fn problem(&mut self, problem: impl Into<String>) {
+        self.problems.push(problem.into());
+    }
+}
+
+/// Result of `Database::verify`, with one report for every segment, index and filter file
+/// that was checked
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VerificationReport {
+    pub files: Vec<FileReport>,
+}
+
+impl VerificationReport {
+    pub fn is_healthy(&self) -> bool {
+        self.files.iter().all(FileReport::is_healthy)
+    }
+
+    pub fn unhealthy_files(&self) -> impl Iterator<Item = &FileReport> {
+        self.files.iter().filter(|file| !file.is_healthy())
+    }
+}
+
+/// Checks a segment file against its recorded properties and returns the offset and key of
+/// every record it could read
+pub fn verify_segment(
+    data: &[u8],
+    properties: Option<&SegmentProperties>,
+    report: &mut FileReport,
+) -> Vec<(u64, Vec<u8>)> {
+    verify_checksum(
+        data,
+        properties.and_then(|properties| properties.segment_checksum),
+        report,
+    );
+    if !data.is_empty() && !data.ends_with(b"\n") {
+        report.problem("last record is truncated");
+    }
+
+    let mut records: Vec<(u64, Vec<u8>)> = Vec::new();
+    let mut offset = 0;
+    for line in data.split(|&b| b == b'\n') {
+        let line_offset = offset;
+        offset += line.len() as u64 + 1;
+        if line.is_empty() {
+            continue;
+        }
+        if std::str::from_utf8(line).is_err() {
+            report.problem(format!(
+                "record at offset {} is not valid UTF-8",
+                line_offset
+            ));
+        }
+
+        let (key, value) = match line.iter().position(|&b| b == b' ') {
+            Some(at) => (&line[..at], Some(&line[at + 1..])),
+            None => (line, None),
+        };
+        if let Some(Some(Err(error))) = value.map(ValuePointer::decode) {
+            report.problem(format!("record at offset {}: {}", line_offset, error));
+        }
+        if let Some((_, previous)) = records.last()
+            && previous.as_slice() >= key
+        {
+            report.problem(format!(
+                "key at offset {} is not greater than the key before it",
+                line_offset
+            ));
+        }
+        records.push((line_offset, key.to_vec()));
+    }
+
+    if let Some(properties) = properties {
+        if properties.num_entries != records.len() as u64 {
+            report.problem(format!(
+                "holds {} records but its properties record {}",
+                records.len(),
+                properties.num_entries
+            ));
+        }
+        let first = records.first().map(|(_, key)| key.as_slice());
+        let last = records.last().map(|(_, key)| key.as_slice());
+        if !records.is_empty()
+            && (first != Some(properties.min_key.as_slice())
+                || last != Some(properties.max_key.as_slice()))
+        {
+            report.problem("key range does not match its properties");
+        }
+    }
+    records
+}
+
+/// Checks an index file against the segment's records. Every indexed offset must start a
+/// record holding the indexed key
+pub fn verify_index(
+    data: &[u8],
+    expected: Vec<IndexEntry>,
+    records: &[(u64, Vec<u8>)],
+    checksum: Option<u64>,
+    report: &mut FileReport,
+) {
+    verify_checksum(data, checksum, report);
+    let expected = IndexEntry::encode_all(expected);
+    if data == expected.as_slice() {
+        return;
+    }
+
+    let entries = match IndexEntry::parse_all(data) {
+        Ok(entries) => entries,
+        Err(error) => {
+            report.problem(format!("index is unreadable: {}", error));
+            return;
+        }
+    };
+
+    let problems_before = report.problems.len();
+    for entry in &entries {
+        match records.binary_search_by_key(&entry.offset(), |(offset, _)| *offset) {
+            Ok(i) if records[i].1 == entry.key() => {}
+            Ok(_) => report.problem(format!(
+                "index entry for offset {} names a different key",
+                entry.offset()
+            )),
+            Err(_) => report.problem(format!(
+                "index offset {} is not at a record boundary",
+                entry.offset()
+            )),
+        }
+    }
+    if report.problems.len() == problems_before {
+        report.problem("index does not match the one built from the segment");
+    }
+}
+
+/// Checks a filter file and that the filter holds every key of the segment
+pub fn verify_filter(
+    data: &[u8],
+    filter: Option<&dyn Filter>,
+    records: &[(u64, Vec<u8>)],
+    checksum: Option<u64>,
+    report: &mut FileReport,
+) {
+    verify_checksum(data, checksum, report);
+    let Some(filter) = filter else {
+        report.problem("filter could not be loaded");
+        return;
+    };
+
+    let missing = records
+        .iter()
+        .filter(|(_, key)| !filter.might_contain(key))
+        .count();
+    if missing > 0 {
+        report.problem(format!("filter rules out {} keys of the segment", missing));
+    }
+}
+
+fn verify_checksum(data: &[u8], checksum: Option<u64>, report: &mut FileReport) {
+    if let Some(checksum) = checksum
+        && hash64(data) != checksum
+    {
+        report.problem("checksum mismatch");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_segment_reports_unsorted_keys_and_truncation() {
+        let mut report = FileReport::new(PathBuf::from("segment_1.sst"));
+        let records = verify_segment(b"apple red\ncherry\nbanana yellow\ndate", None, &mut report);
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[2], (17, b"banana".to_vec()));
+        assert_eq!(
+            report.problems,
+            vec![
+                "last record is truncated".to_string(),
+                "key at offset 17 is not greater than the key before it".to_string(),
+            ]
+        );
+    }
+}
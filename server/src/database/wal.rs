@@ -1,45 +1,103 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::{BufRead, BufReader, Seek, SeekFrom, Write},
-    path::Path,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use crate::database::entry::Entry;
+use crate::database::{
+    entry::Entry,
+    env::{Env, WritableFile},
+};
+
+pub const WAL_FILE_EXTENSION: &str = "log";
+/// Name of the single WAL file written before WAL files were numbered
+const LEGACY_WAL_FILE_NAME: &str = "wal.log";
 
 pub struct Wal {
-    file: File,
+    number: u64,
+    path: PathBuf,
+    file: Box<dyn WritableFile>,
+    env: Arc<dyn Env>,
 }
 
 impl Wal {
-    pub fn new<P: AsRef<Path>>(database_dir: P) -> std::io::Result<Self> {
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .truncate(false)
-            .read(true)
-            .write(true)
-            .open(database_dir.as_ref().join("wal.log"))?;
+    /// Creates a new, empty WAL file with the given number in the database directory
+    pub fn create<P: AsRef<Path>>(
+        env: Arc<dyn Env>,
+        database_dir: P,
+        number: u64,
+    ) -> std::io::Result<Self> {
+        let mut path = database_dir.as_ref().join(format!("wal_{}", number));
+        path.set_extension(WAL_FILE_EXTENSION);
 
-        Ok(Self { file })
+        let file = env.create_writable(&path)?;
+        // The file has to outlive a crash for the entries synced into it to be of any use
+        env.sync_dir(database_dir.as_ref())?;
+
+        Ok(Self {
+            number,
+            path,
+            file,
+            env,
+        })
     }
 
-    pub fn append(&mut self, entry: Entry) -> std::io::Result<()> {
-        self.file.seek(SeekFrom::End(0))?;
-        self.file.write(Vec::<u8>::from(entry).as_slice())?;
-        Ok(())
+    /// Opens an existing WAL file to replay the entries it holds
+    pub fn open(env: Arc<dyn Env>, path: PathBuf) -> std::io::Result<Self> {
+        let number = Self::file_number(&path).ok_or(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Invalid WAL file name",
+        ))?;
+        let file = env.open_appendable(&path)?;
+
+        Ok(Self {
+            number,
+            path,
+            file,
+            env,
+        })
     }
 
-    pub fn clear(&mut self) -> std::io::Result<()> {
-        self.file.set_len(0)?;
-        Ok(())
+    pub fn number(&self) -> u64 {
+        self.number
     }
 
-    pub fn entries(&mut self) -> std::io::Result<impl Iterator<Item = std::io::Result<Entry>>> {
-        self.file.seek(SeekFrom::Start(0))?;
-        let reader = BufReader::new(&self.file);
-        Ok(reader.lines().map(|line| {
-            line.map(|line| Entry::from(line.as_bytes()))
-                .map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error))
-        }))
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the entry, returning the number of bytes written
+    pub fn append(&mut self, entry: Entry) -> std::io::Result<u64> {
+        let record = Vec::<u8>::from(entry);
+        self.file.write_all(&record)?;
+        Ok(record.len() as u64)
+    }
+
+    /// Makes every appended entry durable
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        self.file.sync()
+    }
+
+    pub fn entries(&self) -> std::io::Result<impl Iterator<Item = std::io::Result<Entry>>> {
+        let reader = BufReader::new(self.env.open_readable(&self.path)?);
+        Ok(reader
+            .lines()
+            .map(|line| line.map(|line| Entry::from(line.as_bytes()))))
+    }
+
+    /// Extracts the WAL number from a file name like `wal_3.log`.
+    /// The legacy unnumbered `wal.log` is treated as number 0 so it is replayed first
+    pub fn file_number(path: &Path) -> Option<u64> {
+        let file_name = path.file_name()?.to_str()?;
+        if file_name == LEGACY_WAL_FILE_NAME {
+            return Some(0);
+        }
+
+        file_name
+            .strip_prefix("wal_")?
+            .strip_suffix(WAL_FILE_EXTENSION)?
+            .strip_suffix('.')?
+            .parse()
+            .ok()
     }
 }
@@ -0,0 +1,153 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::database::{
+    entry::Entry,
+    env::Env,
+    wal::{WAL_FILE_EXTENSION, Wal},
+};
+
+pub const WAL_ARCHIVE_DIRECTORY: &str = "archive";
+
+pub struct WalRegistry {
+    /// WAL currently receiving writes for the in-memory table
+    active: Wal,
+    /// Older WAL files whose entries have not been flushed to a segment yet, oldest first.
+    /// These only exist when a previous run stopped before it could flush, and are never
+    /// appended to since a crash may have left a torn record at their end
+    unflushed: Vec<PathBuf>,
+    directory_path: PathBuf,
+    archive_limit: Option<usize>,
+    env: Arc<dyn Env>,
+}
+
+impl WalRegistry {
+    /// Opens the WAL files in the directory. Files numbered below `log_number` were already
+    /// flushed before the last shutdown and are retired straight away, along with empty
+    /// ones. Writes go to a new WAL numbered by `allocate_number`, or past the newest
+    /// existing WAL file if that is higher
+    pub fn new<P: AsRef<Path>>(
+        env: Arc<dyn Env>,
+        directory_path: P,
+        log_number: u64,
+        archive_limit: Option<usize>,
+        allocate_number: impl FnOnce() -> u64,
+    ) -> std::io::Result<Self> {
+        let mut wal_files = Self::find_wal_files(env.as_ref(), directory_path.as_ref())?;
+        wal_files.sort_by_key(|(number, _)| *number);
+
+        // The new WAL must not reuse the number of a file that is about to be retired
+        let number = match wal_files.last() {
+            Some((newest, _)) => allocate_number().max(newest + 1),
+            None => allocate_number(),
+        };
+        let (obsolete, unflushed): (Vec<_>, Vec<_>) =
+            wal_files.into_iter().partition(|(number, path)| {
+                *number < log_number || matches!(env.file_size(path), Ok(0))
+            });
+
+        let active = Wal::create(Arc::clone(&env), &directory_path, number)?;
+
+        let registry = Self {
+            active,
+            unflushed: unflushed.into_iter().map(|(_, path)| path).collect(),
+            directory_path: directory_path.as_ref().to_path_buf(),
+            archive_limit,
+            env,
+        };
+        registry.retire(obsolete.into_iter().map(|(_, path)| path).collect())?;
+
+        Ok(registry)
+    }
+
+    pub fn active(&mut self) -> &mut Wal {
+        &mut self.active
+    }
+
+    pub fn active_number(&self) -> u64 {
+        self.active.number()
+    }
+
+    /// Paths of every WAL file holding writes not yet flushed, in the order they were written
+    pub fn live_paths(&self) -> Vec<PathBuf> {
+        self.unflushed
+            .iter()
+            .cloned()
+            .chain(std::iter::once(self.active.path().to_path_buf()))
+            .collect()
+    }
+
+    /// Replays every unflushed WAL in the order the entries were written
+    pub fn entries(&mut self) -> std::io::Result<impl Iterator<Item = std::io::Result<Entry>>> {
+        let mut entries = Vec::new();
+        for path in &self.unflushed {
+            entries.extend(Wal::open(Arc::clone(&self.env), path.clone())?.entries()?);
+        }
+
+        Ok(entries.into_iter().chain(self.active.entries()?))
+    }
+
+    /// Switches writes over to a brand new WAL file. Returns the paths of the WAL files that
+    /// backed the previous in-memory table, which must only be retired once that table's
+    /// segment has been durably recorded
+    pub fn rotate(&mut self, number: u64) -> std::io::Result<Vec<PathBuf>> {
+        let previous = std::mem::replace(
+            &mut self.active,
+            Wal::create(Arc::clone(&self.env), &self.directory_path, number)?,
+        );
+
+        let mut retired = std::mem::take(&mut self.unflushed);
+        retired.push(previous.path().to_path_buf());
+        Ok(retired)
+    }
+
+    /// Deletes WAL files that are no longer needed, or moves them into the archive
+    /// directory when an archive limit is configured
+    pub fn retire(&self, paths: Vec<PathBuf>) -> std::io::Result<()> {
+        let Some(archive_limit) = self.archive_limit else {
+            for path in paths {
+                tracing::info!("Deleting retired WAL file {}", path.display());
+                self.env.remove_file(&path)?;
+            }
+            return Ok(());
+        };
+
+        let archive_path = self.directory_path.join(WAL_ARCHIVE_DIRECTORY);
+        self.env.create_dir_all(&archive_path)?;
+
+        for path in paths {
+            if let Some(file_name) = path.file_name() {
+                tracing::info!("Archiving retired WAL file {}", path.display());
+                self.env.rename(&path, &archive_path.join(file_name))?;
+            }
+        }
+
+        let mut archived = Self::find_wal_files(self.env.as_ref(), &archive_path)?;
+        archived.sort_by_key(|(number, _)| *number);
+        let excess = archived.len().saturating_sub(archive_limit);
+        for (_, path) in archived.into_iter().take(excess) {
+            tracing::info!("Deleting archived WAL file {}", path.display());
+            self.env.remove_file(&path)?;
+        }
+
+        Ok(())
+    }
+
+    fn find_wal_files(
+        env: &dyn Env,
+        directory_path: &Path,
+    ) -> std::io::Result<Vec<(u64, PathBuf)>> {
+        Ok(env
+            .list_files(directory_path)?
+            .into_iter()
+            .filter(|path| {
+                path.extension()
+                    .map(|ext| ext == WAL_FILE_EXTENSION)
+                    .unwrap_or(false)
+            })
+            .filter_map(|path| Wal::file_number(&path).map(|number| (number, path)))
+            .collect())
+    }
+}
@@ -0,0 +1,188 @@
+use std::io;
+
+use crate::database::{
+    filter_policy::Filter,
+    hash::{hash64, mix64, read_u64},
+};
+
+const FORMAT_MAGIC: &[u8; 7] = b"KVXOR8F";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = FORMAT_MAGIC.len() + 1 + 16;
+
+/// Xor filter with 8-bit fingerprints, as described by Graf and Lemire. Every key maps to
+/// one slot in each third of the table, and construction picks fingerprints so the three
+/// slots of every key XOR to the key's fingerprint
+pub struct XorFilter {
+    seed: u64,
+    block_length: usize,
+    fingerprints: Vec<u8>,
+}
+
+impl XorFilter {
+    /// Builds a filter over exactly `keys`. Construction retries with a new seed in the rare
+    /// case the keys cannot be peeled off the table
+    pub fn build(keys: &[&[u8]]) -> Self {
+        // Duplicate keys would make peeling impossible whatever the seed
+        let mut hashes: Vec<u64> = keys.iter().map(|key| hash64(key)).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+
+        let capacity = (32 + (1.23 * hashes.len() as f64).ceil() as usize) / 3 * 3;
+        let block_length = capacity / 3;
+
+        let mut attempt = 0_u64;
+        loop {
+            let seed = mix64(attempt.wrapping_add(0x9e37_79b9_7f4a_7c15));
+            if let Some(fingerprints) = Self::try_build(&hashes, seed, block_length) {
+                return Self {
+                    seed,
+                    block_length,
+                    fingerprints,
+                };
+            }
+            attempt += 1;
+        }
+    }
+
+    fn try_build(hashes: &[u64], seed: u64, block_length: usize) -> Option<Vec<u8>> {
+        let capacity = block_length * 3;
+        let mut counts = vec![0_u32; capacity];
+        let mut masks = vec![0_u64; capacity];
+        for &hash in hashes {
+            let hash = mix64(hash.wrapping_add(seed));
+            for slot in slots(hash, block_length) {
+                counts[slot] += 1;
+                masks[slot] ^= hash;
+            }
+        }
+
+        // Repeatedly remove keys that are alone in one of their slots
+        let mut queue: Vec<usize> = (0..capacity).filter(|&slot| counts[slot] == 1).collect();
+        let mut peeled = Vec::with_capacity(hashes.len());
+        while let Some(slot) = queue.pop() {
+            if counts[slot] != 1 {
+                continue;
+            }
+            let hash = masks[slot];
+            peeled.push((hash, slot));
+            for other in slots(hash, block_length) {
+                counts[other] -= 1;
+                masks[other] ^= hash;
+                if counts[other] == 1 {
+                    queue.push(other);
+                }
+            }
+        }
+        if peeled.len() != hashes.len() {
+            return None;
+        }
+
+        // Assign in reverse peeling order so every key's own slot is written last
+        let mut fingerprints = vec![0_u8; capacity];
+        for (hash, slot) in peeled.into_iter().rev() {
+            let [a, b, c] = slots(hash, block_length);
+            fingerprints[slot] =
+                fingerprint(hash) ^ fingerprints[a] ^ fingerprints[b] ^ fingerprints[c];
+        }
+        Some(fingerprints)
+    }
+
+    /// Whether `data` was written by `serialize`
+    pub fn is_xor_filter(data: &[u8]) -> bool {
+        data.starts_with(FORMAT_MAGIC)
+    }
+
+    /// Format: [magic (7 bytes), version (1 byte), seed (8 bytes), block_length (8 bytes),
+    /// fingerprints (3 * block_length bytes)]
+    pub fn deserialize(data: &[u8]) -> io::Result<Self> {
+        if data.len() < HEADER_LEN || data[FORMAT_MAGIC.len()] != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid xor filter header",
+            ));
+        }
+
+        let seed = read_u64(&data[8..16]);
+        // The header comes straight from disk, so a damaged one must not overflow
+        let block_length = usize::try_from(read_u64(&data[16..24])).unwrap_or(usize::MAX);
+        let fingerprints = data[HEADER_LEN..].to_vec();
+        if block_length == 0 || block_length.checked_mul(3) != Some(fingerprints.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Xor filter size does not match its header",
+            ));
+        }
+
+        Ok(Self {
+            seed,
+            block_length,
+            fingerprints,
+        })
+    }
+}
+
+impl Filter for XorFilter {
+    fn might_contain(&self, key: &[u8]) -> bool {
+        let hash = mix64(hash64(key).wrapping_add(self.seed));
+        let [a, b, c] = slots(hash, self.block_length);
+        fingerprint(hash) == self.fingerprints[a] ^ self.fingerprints[b] ^ self.fingerprints[c]
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(HEADER_LEN + self.fingerprints.len());
+        result.extend_from_slice(FORMAT_MAGIC);
+        result.push(FORMAT_VERSION);
+        result.extend_from_slice(&self.seed.to_le_bytes());
+        result.extend_from_slice(&(self.block_length as u64).to_le_bytes());
+        result.extend_from_slice(&self.fingerprints);
+        result
+    }
+}
+
+fn fingerprint(hash: u64) -> u8 {
+    (hash ^ (hash >> 32)) as u8
+}
+
+/// One slot in each third of the table
+fn slots(hash: u64, block_length: usize) -> [usize; 3] {
+    let reduce = |bits: u64| ((bits as u32 as u64 * block_length as u64) >> 32) as usize;
+    [
+        reduce(hash),
+        block_length + reduce(hash.rotate_left(21)),
+        2 * block_length + reduce(hash.rotate_left(42)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xor_filter_has_no_false_negatives_and_few_false_positives() {
+        let keys: Vec<Vec<u8>> = (0..10_000)
+            .map(|i| format!("key_{}", i).into_bytes())
+            .collect();
+        let key_refs: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+        let filter = XorFilter::deserialize(&XorFilter::build(&key_refs).serialize()).unwrap();
+
+        assert!(key_refs.iter().all(|key| filter.might_contain(key)));
+        let false_positives = (0..100_000)
+            .filter(|i| filter.might_contain(format!("other_{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 1_000);
+    }
+
+    #[test]
+    fn test_xor_filter_rejects_a_huge_block_length() {
+        let mut data = XorFilter::build(&[b"key".as_slice()]).serialize();
+        for block_length in [u64::MAX, u64::MAX / 3 + 1] {
+            data[16..24].copy_from_slice(&block_length.to_le_bytes());
+            assert_eq!(
+                XorFilter::deserialize(&data)
+                    .err()
+                    .map(|error| error.kind()),
+                Some(io::ErrorKind::InvalidData)
+            );
+        }
+    }
+}
@@ -2,15 +2,22 @@ use std::io::{Read, Write};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use metrics::ServerMetrics;
 use protocol::{Command, Response};
 use server::database;
 use thread_pool::ThreadPool;
 
+mod metrics;
 mod thread_pool;
 
 const THREAD_POOL_SIZE: usize = 4;
+const IN_MEMORY_FLAG: &str = "--in-memory";
 const LISTEN_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8080);
+/// Prometheus scrapes metrics from here, which is only reachable from the local machine
+const METRICS_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9090);
+const SCRUB_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 fn main() -> std::io::Result<()> {
     // Initialize tracing
@@ -19,15 +26,36 @@ fn main() -> std::io::Result<()> {
     tracing::info!("Simple LSM DB Server starting...");
 
     let listener = TcpListener::bind(LISTEN_ADDRESS)?;
-    let pool = ThreadPool::new(THREAD_POOL_SIZE)?;
+    let pool = Arc::new(ThreadPool::new(THREAD_POOL_SIZE)?);
+    let in_memory = std::env::args().skip(1).any(|arg| arg == IN_MEMORY_FLAG);
+    if in_memory {
+        tracing::info!("Running with an in-memory database, nothing will be persisted");
+    }
+
     let database_dir = std::env::temp_dir().join("simple_lsm_db");
-    let database = Arc::new(Mutex::new(database::Database::new(database_dir, None)?));
+    let database = Arc::new(Mutex::new(database::Database::open(
+        database_dir,
+        database::DatabaseOptions {
+            in_memory,
+            ..Default::default()
+        },
+    )?));
+    let _scrubber = database::Scrubber::start(Arc::clone(&database), SCRUB_INTERVAL)?;
+    let metrics = Arc::new(ServerMetrics::default());
+    metrics::serve(
+        METRICS_ADDRESS,
+        Arc::clone(&metrics),
+        Arc::clone(&database),
+        Arc::clone(&pool),
+    )?;
 
     for stream_result in listener.incoming() {
         match stream_result {
             Ok(stream) => {
                 let database = Arc::clone(&database);
-                if let Err(e) = pool.execute(move || handle_connection(stream, database)) {
+                let metrics = Arc::clone(&metrics);
+                if let Err(e) = pool.execute(move || handle_connection(stream, database, &metrics))
+                {
                     tracing::error!("Failed to execute task in thread pool: {}", e);
                 }
             }
@@ -42,7 +70,12 @@ fn main() -> std::io::Result<()> {
     Ok(())
 }
 
-fn handle_connection(mut stream: TcpStream, database: Arc<Mutex<database::Database<PathBuf>>>) {
+fn handle_connection(
+    mut stream: TcpStream,
+    database: Arc<Mutex<database::Database<PathBuf>>>,
+    metrics: &ServerMetrics,
+) {
+    let _connection = metrics.connection_opened();
     let peer_addr = stream.peer_addr().ok();
     tracing::info!("New connection from {:?}", peer_addr);
 
@@ -51,7 +84,6 @@ fn handle_connection(mut stream: TcpStream, database: Arc<Mutex<database::Databa
     match stream.read(&mut buffer) {
         Ok(0) => {
             tracing::info!("Connection closed by {:?}", peer_addr);
-            return;
         }
         Ok(n) => {
             let bytes = &buffer[..n];
@@ -59,6 +91,8 @@ fn handle_connection(mut stream: TcpStream, database: Arc<Mutex<database::Databa
             match Command::try_from(bytes) {
                 Ok(cmd) => {
                     tracing::info!("Received command from {:?}: {:?}", peer_addr, cmd);
+                    let start = Instant::now();
+                    let name = cmd.name();
 
                     let mut database = match database.lock() {
                         Ok(db) => db,
@@ -69,8 +103,21 @@ fn handle_connection(mut stream: TcpStream, database: Arc<Mutex<database::Databa
                     };
 
                     let response = match cmd {
-                        Command::Get { key } => match database.get(key) {
-                            Ok(possible_value) => Response::Ok(possible_value),
+                        Command::Get { key } => match database.get_pinned(key) {
+                            Ok(possible_value) => {
+                                // Write the value from where it is held instead of copying it
+                                if let Err(e) =
+                                    Response::write_ok(&mut stream, possible_value.as_deref())
+                                {
+                                    tracing::error!(
+                                        "Failed to write response to {:?}: {}",
+                                        peer_addr,
+                                        e
+                                    );
+                                }
+                                metrics.record_command(name, start.elapsed(), false);
+                                return;
+                            }
                             Err(error) => {
                                 tracing::error!("Failed to get value from database: {}", error);
                                 Response::Err(error.to_string())
@@ -92,12 +139,15 @@ fn handle_connection(mut stream: TcpStream, database: Arc<Mutex<database::Databa
                         },
                     };
 
+                    let failed = matches!(response, Response::Err(_));
                     if let Err(e) = stream.write_all(Vec::<u8>::from(response).as_slice()) {
                         tracing::error!("Failed to write response to {:?}: {}", peer_addr, e);
                     }
+                    metrics.record_command(name, start.elapsed(), failed);
                 }
                 Err(e) => {
                     tracing::error!("Invalid command from {:?}: {}", peer_addr, e);
+                    metrics.record_invalid_command();
                     let response = Response::Err(e.to_string());
                     if let Err(e) = stream.write_all(Vec::<u8>::from(response).as_slice()) {
                         tracing::error!("Failed to write error to {:?}: {}", peer_addr, e);
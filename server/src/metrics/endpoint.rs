@@ -0,0 +1,77 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use server::database::Database;
+
+use super::ServerMetrics;
+use crate::thread_pool::ThreadPool;
+
+const METRICS_PATH: &str = "/metrics";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// Keeps a client that never finishes its request from blocking every later scrape
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the metrics over HTTP at `/metrics` on `address` from a thread of its own, so
+/// scrapes never wait behind client connections for a worker
+pub fn serve(
+    address: SocketAddr,
+    metrics: Arc<ServerMetrics>,
+    database: Arc<Mutex<Database<PathBuf>>>,
+    pool: Arc<ThreadPool>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    tracing::info!("Serving metrics on http://{}{}", address, METRICS_PATH);
+
+    std::thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || {
+            for stream_result in listener.incoming() {
+                let result = stream_result.and_then(|stream| {
+                    respond(stream, || {
+                        let stats = match database.lock() {
+                            Ok(database) => database.stats().ok(),
+                            Err(_) => None,
+                        };
+                        metrics.render(pool.queue_depth(), stats.as_ref())
+                    })
+                });
+                if let Err(error) = result {
+                    tracing::error!("Failed to serve metrics: {}", error);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Answers a single HTTP request, with the output of `render` for `GET /metrics` and 404
+/// otherwise
+fn respond(mut stream: TcpStream, render: impl FnOnce() -> String) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Closing the connection with unread headers would reset it before the response arrives
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(METRICS_PATH)) => ("200 OK", render()),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )
+}
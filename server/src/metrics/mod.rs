@@ -0,0 +1,340 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use protocol::Command;
+use server::database::DatabaseStats;
+
+mod endpoint;
+mod histogram;
+
+pub use endpoint::serve;
+use histogram::Histogram;
+
+/// Prefix of every metric name
+const NAMESPACE: &str = "lsm";
+
+#[derive(Debug, Default)]
+struct CommandMetrics {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    latency: Histogram,
+}
+
+/// Request and connection metrics of the server, rendered in the Prometheus text format
+/// together with the engine statistics
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    /// One entry per command, in the order of `Command::NAMES`
+    commands: [CommandMetrics; Command::NAMES.len()],
+    invalid_commands: AtomicU64,
+    active_connections: AtomicU64,
+}
+
+/// Counts a connection as active until it is dropped
+pub struct ConnectionGuard<'a> {
+    metrics: &'a ServerMetrics,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.metrics
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ServerMetrics {
+    pub fn connection_opened(&self) -> ConnectionGuard<'_> {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { metrics: self }
+    }
+
+    /// Records a command that took `duration` to execute and answer
+    pub fn record_command(&self, name: &str, duration: Duration, failed: bool) {
+        let Some(index) = Command::NAMES.iter().position(|&known| known == name) else {
+            return;
+        };
+        let command = &self.commands[index];
+        command.requests.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            command.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        command.latency.observe(duration);
+    }
+
+    /// Records a request that could not be parsed into a command
+    pub fn record_invalid_command(&self) {
+        self.invalid_commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Renders every metric. The engine section is left out when `stats` is None, e.g.
+    /// because the database could not be locked
+    pub fn render(&self, queue_depth: usize, stats: Option<&DatabaseStats>) -> String {
+        let mut out = String::new();
+
+        header(&mut out, "requests_total", "counter", "Commands handled");
+        for (name, command) in Command::NAMES.iter().zip(&self.commands) {
+            sample(
+                &mut out,
+                "requests_total",
+                &format!("command=\"{}\"", name),
+                command.requests.load(Ordering::Relaxed),
+            );
+        }
+        header(
+            &mut out,
+            "request_errors_total",
+            "counter",
+            "Commands the database failed to execute",
+        );
+        for (name, command) in Command::NAMES.iter().zip(&self.commands) {
+            sample(
+                &mut out,
+                "request_errors_total",
+                &format!("command=\"{}\"", name),
+                command.errors.load(Ordering::Relaxed),
+            );
+        }
+        header(
+            &mut out,
+            "request_duration_seconds",
+            "histogram",
+            "Time taken to execute and answer a command",
+        );
+        for (name, command) in Command::NAMES.iter().zip(&self.commands) {
+            command.latency.render(
+                &mut out,
+                &format!("{}_request_duration_seconds", NAMESPACE),
+                &format!("command=\"{}\"", name),
+            );
+        }
+
+        header(
+            &mut out,
+            "invalid_requests_total",
+            "counter",
+            "Requests that could not be parsed",
+        );
+        sample(
+            &mut out,
+            "invalid_requests_total",
+            "",
+            self.invalid_commands.load(Ordering::Relaxed),
+        );
+        header(
+            &mut out,
+            "active_connections",
+            "gauge",
+            "Open client connections",
+        );
+        sample(
+            &mut out,
+            "active_connections",
+            "",
+            self.active_connections.load(Ordering::Relaxed),
+        );
+        header(
+            &mut out,
+            "thread_pool_queue_depth",
+            "gauge",
+            "Connections waiting for a worker",
+        );
+        sample(&mut out, "thread_pool_queue_depth", "", queue_depth);
+
+        if let Some(stats) = stats {
+            render_engine_stats(&mut out, stats);
+        }
+        out
+    }
+}
+
+fn render_engine_stats(out: &mut String, stats: &DatabaseStats) {
+    let gauges = [
+        (
+            "engine_mem_table_entries",
+            "Entries in the in-memory table",
+            stats.mem_table_entries,
+        ),
+        (
+            "engine_mem_table_bytes",
+            "Bytes of keys and values in the in-memory table",
+            stats.mem_table_bytes,
+        ),
+    ];
+    for (name, help, value) in gauges {
+        header(out, name, "gauge", help);
+        sample(out, name, "", value);
+    }
+
+    header(out, "engine_segments", "gauge", "Live segments per level");
+    for (level, count) in stats.segments_per_level.iter().enumerate() {
+        sample(
+            out,
+            "engine_segments",
+            &format!("level=\"{}\"", level),
+            count,
+        );
+    }
+    header(
+        out,
+        "engine_disk_bytes",
+        "gauge",
+        "Bytes on disk per file type",
+    );
+    for (file_type, bytes) in &stats.disk_bytes {
+        sample(
+            out,
+            "engine_disk_bytes",
+            &format!("type=\"{}\"", file_type),
+            bytes,
+        );
+    }
+
+    let counters = [
+        (
+            "engine_filter_checks_total",
+            "Segment filter lookups",
+            stats.filter_checks,
+        ),
+        (
+            "engine_filter_useful_total",
+            "Segment filter lookups that ruled the segment out",
+            stats.filter_useful,
+        ),
+        (
+            "engine_block_cache_hits_total",
+            "Block cache hits",
+            stats.block_cache_hits,
+        ),
+        (
+            "engine_block_cache_misses_total",
+            "Block cache misses",
+            stats.block_cache_misses,
+        ),
+        (
+            "engine_metadata_cache_hits_total",
+            "Metadata cache hits",
+            stats.metadata_cache_hits,
+        ),
+        (
+            "engine_metadata_cache_misses_total",
+            "Metadata cache misses",
+            stats.metadata_cache_misses,
+        ),
+        ("engine_flushes_total", "Memtable flushes", stats.flushes),
+        (
+            "engine_flush_bytes_written_total",
+            "Bytes written by flushes",
+            stats.flush_bytes_written,
+        ),
+        ("engine_compactions_total", "Compactions", stats.compactions),
+        (
+            "engine_compaction_bytes_read_total",
+            "Bytes read by compactions",
+            stats.compaction_bytes_read,
+        ),
+        (
+            "engine_compaction_bytes_written_total",
+            "Bytes written by compactions",
+            stats.compaction_bytes_written,
+        ),
+        (
+            "engine_wal_bytes_written_total",
+            "Bytes appended to the WAL",
+            stats.wal_bytes_written,
+        ),
+        (
+            "engine_user_bytes_written_total",
+            "Bytes of keys and values written",
+            stats.user_bytes_written,
+        ),
+    ];
+    for (name, help, value) in counters {
+        header(out, name, "counter", help);
+        sample(out, name, "", value);
+    }
+
+    let durations = [
+        (
+            "engine_flush_seconds_total",
+            "Time spent flushing",
+            stats.flush_duration,
+        ),
+        (
+            "engine_compaction_seconds_total",
+            "Time spent compacting",
+            stats.compaction_duration,
+        ),
+    ];
+    for (name, help, duration) in durations {
+        header(out, name, "counter", help);
+        sample(out, name, "", duration.as_secs_f64());
+    }
+
+    if let Some(write_amplification) = stats.write_amplification() {
+        header(
+            out,
+            "engine_write_amplification",
+            "gauge",
+            "Bytes written to disk per byte of keys and values written",
+        );
+        sample(out, "engine_write_amplification", "", write_amplification);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {}_{} {}", NAMESPACE, name, help);
+    let _ = writeln!(out, "# TYPE {}_{} {}", NAMESPACE, name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    let _ = match labels {
+        "" => writeln!(out, "{}_{} {}", NAMESPACE, name, value),
+        labels => writeln!(out, "{}_{}{{{}}} {}", NAMESPACE, name, labels, value),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_metrics_render_commands_and_engine_stats() {
+        let metrics = ServerMetrics::default();
+        let connection = metrics.connection_opened();
+        metrics.record_command("GET", Duration::from_micros(200), false);
+        metrics.record_command("SET", Duration::from_millis(2), true);
+        metrics.record_invalid_command();
+
+        let stats = DatabaseStats {
+            segments_per_level: vec![3, 1],
+            user_bytes_written: 10,
+            wal_bytes_written: 15,
+            ..Default::default()
+        };
+        let out = metrics.render(2, Some(&stats));
+        let lines: Vec<&str> = out.lines().collect();
+        for expected in [
+            "lsm_requests_total{command=\"GET\"} 1",
+            "lsm_requests_total{command=\"DELETE\"} 0",
+            "lsm_request_errors_total{command=\"SET\"} 1",
+            "lsm_request_duration_seconds_count{command=\"SET\"} 1",
+            "lsm_invalid_requests_total 1",
+            "lsm_active_connections 1",
+            "lsm_thread_pool_queue_depth 2",
+            "lsm_engine_segments{level=\"0\"} 3",
+            "lsm_engine_write_amplification 1.5",
+            "# TYPE lsm_engine_compactions_total counter",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+
+        drop(connection);
+        let out = metrics.render(0, None);
+        assert!(out.lines().any(|line| line == "lsm_active_connections 0"));
+        assert!(!out.contains("lsm_engine_"));
+    }
+}
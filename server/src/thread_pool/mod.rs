@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
 
 mod job;
 mod worker;
@@ -9,11 +12,13 @@ const MAX_THREAD_POOL_SIZE: usize = 10;
 pub struct ThreadPool {
     sender: Option<std::sync::mpsc::Sender<Box<job::Job>>>,
     workers: Vec<worker::Worker>,
+    /// Jobs handed to the pool that no worker has picked up yet
+    queue_depth: Arc<AtomicUsize>,
 }
 
 impl ThreadPool {
     pub fn new(size: usize) -> std::io::Result<Self> {
-        if size < MIN_THREAD_POOL_SIZE || size > MAX_THREAD_POOL_SIZE {
+        if !(MIN_THREAD_POOL_SIZE..=MAX_THREAD_POOL_SIZE).contains(&size) {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "Invalid thread pool size",
@@ -23,16 +28,27 @@ impl ThreadPool {
         let mut workers = Vec::with_capacity(size);
         let (sender, receiver) = std::sync::mpsc::channel();
         let receiver = Arc::new(Mutex::new(receiver));
+        let queue_depth = Arc::new(AtomicUsize::new(0));
         for id in 0..size {
-            workers.push(worker::Worker::new(id, Arc::clone(&receiver)));
+            workers.push(worker::Worker::new(
+                id,
+                Arc::clone(&receiver),
+                Arc::clone(&queue_depth),
+            ));
         }
 
         Ok(Self {
             workers,
             sender: Some(sender),
+            queue_depth,
         })
     }
 
+    /// Number of jobs waiting for a free worker
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+
     pub fn execute<F>(&self, f: F) -> Result<(), std::io::Error>
     where
         F: FnOnce() + Send + 'static,
@@ -40,13 +56,14 @@ impl ThreadPool {
         let job = Box::new(f);
 
         match self.sender.as_ref() {
-            Some(sender) => sender
-                .send(job)
-                .map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error.to_string())),
-            None => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Sender not found",
-            )),
+            Some(sender) => {
+                self.queue_depth.fetch_add(1, Ordering::Relaxed);
+                sender.send(job).map_err(|error| {
+                    self.queue_depth.fetch_sub(1, Ordering::Relaxed);
+                    std::io::Error::other(error.to_string())
+                })
+            }
+            None => Err(std::io::Error::other("Sender not found")),
         }
     }
 }
@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
 
 pub struct Worker {
     _id: usize,
@@ -9,6 +12,7 @@ impl Worker {
     pub fn new(
         id: usize,
         receiver: Arc<Mutex<std::sync::mpsc::Receiver<Box<super::job::Job>>>>,
+        queue_depth: Arc<AtomicUsize>,
     ) -> Self {
         let thread = std::thread::spawn(move || {
             while let Ok(Ok(message)) = receiver
@@ -23,6 +27,7 @@ impl Worker {
                 .map(|lock| lock.recv())
             {
                 tracing::info!("Worker {} received message.", id);
+                queue_depth.fetch_sub(1, Ordering::Relaxed);
                 message();
             }
 
@@ -0,0 +1,230 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use server::database::{
+    BackupEngine, Database, DatabaseOptions,
+    env::{Env, MemEnv, ReadableFile, WritableFile},
+};
+use tempfile::TempDir;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:04}", i).into_bytes()
+}
+
+fn write(db: &mut Database<std::path::PathBuf>, keys: std::ops::Range<usize>, value: &[u8]) {
+    for i in keys {
+        db.set(&key(i), value).unwrap();
+    }
+}
+
+fn shared_files(backup_dir: &Path) -> usize {
+    std::fs::read_dir(backup_dir.join("shared"))
+        .unwrap()
+        .count()
+}
+
+#[test]
+fn backups_only_store_new_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let backup_dir = temp_dir.path().join("backups");
+    let mut db = Database::new(temp_dir.path().join("db"), Some(100)).unwrap();
+    let mut engine = BackupEngine::open(&backup_dir).unwrap();
+
+    write(&mut db, 0..200, b"first");
+    let first = engine.create_backup(&mut db).unwrap();
+    // Two segments, each with an index, a filter and properties
+    assert_eq!(shared_files(&backup_dir), 8);
+
+    write(&mut db, 200..300, b"second");
+    let second = engine.create_backup(&mut db).unwrap();
+    assert_eq!(shared_files(&backup_dir), 12);
+    assert_eq!(second, first + 1);
+
+    let infos = engine.backup_infos();
+    assert_eq!(infos.len(), 2);
+    assert!(infos[1].size > infos[0].size);
+    assert!(engine.verify_backup(first).unwrap().is_healthy());
+    assert!(engine.verify_backup(second).unwrap().is_healthy());
+}
+
+#[test]
+fn restored_backups_hold_the_data_at_backup_time() {
+    let temp_dir = TempDir::new().unwrap();
+    let backup_dir = temp_dir.path().join("backups");
+    let mut db = Database::new(temp_dir.path().join("db"), Some(100)).unwrap();
+    let mut engine = BackupEngine::open(&backup_dir).unwrap();
+
+    // The unflushed tail is captured from the WAL
+    write(&mut db, 0..150, b"first");
+    let first = engine.create_backup(&mut db).unwrap();
+    write(&mut db, 0..500, b"second");
+    let second = engine.create_backup(&mut db).unwrap();
+
+    // Reopening the engine finds both backups
+    let engine = BackupEngine::open(&backup_dir).unwrap();
+    for (id, keys, value) in [(first, 150, b"first".as_slice()), (second, 500, b"second")] {
+        let restore_dir = temp_dir.path().join(format!("restore_{}", id));
+        engine.restore(id, &restore_dir).unwrap();
+
+        let restored = Database::new(&restore_dir, Some(100)).unwrap();
+        for i in 0..keys {
+            assert_eq!(restored.get(&key(i)).unwrap(), Some(value.to_vec()));
+        }
+        assert_eq!(restored.get(&key(keys)).unwrap(), None);
+        assert!(restored.verify().is_healthy());
+    }
+
+    let error = engine
+        .restore(first, temp_dir.path().join(format!("restore_{}", second)))
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn purging_old_backups_removes_unreferenced_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let backup_dir = temp_dir.path().join("backups");
+    let mut db = Database::new(temp_dir.path().join("db"), Some(100)).unwrap();
+    let mut engine = BackupEngine::open(&backup_dir).unwrap();
+
+    for round in 0..3 {
+        write(&mut db, round * 100..(round + 1) * 100, b"value");
+        engine.create_backup(&mut db).unwrap();
+    }
+    engine.purge_old_backups(1).unwrap();
+
+    let infos = engine.backup_infos();
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0].id, 3);
+    assert!(engine.verify_backup(3).unwrap().is_healthy());
+    assert!(engine.verify_backup(1).is_err());
+    assert_eq!(
+        std::fs::read_dir(backup_dir.join("private"))
+            .unwrap()
+            .count(),
+        1
+    );
+    // Only the files of the remaining backup are left
+    let remaining = infos[0].num_files;
+    assert!(shared_files(&backup_dir) < remaining);
+
+    engine.restore(3, temp_dir.path().join("restore")).unwrap();
+    let restored = Database::new(temp_dir.path().join("restore"), Some(100)).unwrap();
+    assert_eq!(restored.get(&key(0)).unwrap(), Some(b"value".to_vec()));
+}
+
+#[test]
+fn corrupted_backups_fail_verification() {
+    let temp_dir = TempDir::new().unwrap();
+    let backup_dir = temp_dir.path().join("backups");
+    let mut db = Database::new(temp_dir.path().join("db"), Some(100)).unwrap();
+    let mut engine = BackupEngine::open(&backup_dir).unwrap();
+    write(&mut db, 0..100, b"value");
+    let id = engine.create_backup(&mut db).unwrap();
+
+    let segment = std::fs::read_dir(backup_dir.join("shared"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "sst"))
+        .unwrap();
+    let mut data = std::fs::read(&segment).unwrap();
+    data[10] ^= 0x01;
+    std::fs::write(&segment, data).unwrap();
+
+    let report = engine.verify_backup(id).unwrap();
+    let unhealthy: Vec<_> = report.unhealthy_files().collect();
+    assert_eq!(unhealthy.len(), 1);
+    assert_eq!(unhealthy[0].path, segment);
+    assert!(engine.restore(id, temp_dir.path().join("restore")).is_err());
+}
+
+/// Memory env without hard links, like a backup directory on another device, that records
+/// every file created through it
+#[derive(Debug, Default)]
+struct CrossDeviceEnv {
+    inner: MemEnv,
+    created: Mutex<Vec<PathBuf>>,
+}
+
+impl Env for CrossDeviceEnv {
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        self.inner.create_dir_all(path)
+    }
+
+    fn list_files(&self, directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+        self.inner.list_files(directory)
+    }
+
+    fn open_readable(&self, path: &Path) -> std::io::Result<Box<dyn ReadableFile>> {
+        self.inner.open_readable(path)
+    }
+
+    fn create_writable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>> {
+        self.created.lock().unwrap().push(path.to_path_buf());
+        self.inner.create_writable(path)
+    }
+
+    fn open_appendable(&self, path: &Path) -> std::io::Result<Box<dyn WritableFile>> {
+        self.inner.open_appendable(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn hard_link(&self, _from: &Path, _to: &Path) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::CrossesDevices.into())
+    }
+
+    fn remove_file(&self, path: &Path) -> std::io::Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> std::io::Result<()> {
+        self.inner.sync_dir(path)
+    }
+}
+
+#[test]
+fn backups_across_devices_only_copy_new_segments() {
+    let env = Arc::new(CrossDeviceEnv::default());
+    let mut db = Database::open(
+        PathBuf::from("/db"),
+        DatabaseOptions {
+            max_table_size: Some(100),
+            env: Some(Arc::clone(&env) as Arc<dyn Env>),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let mut engine =
+        BackupEngine::open_with_env(Arc::clone(&env) as Arc<dyn Env>, "/backups").unwrap();
+    let copied = || {
+        std::mem::take(&mut *env.created.lock().unwrap())
+            .into_iter()
+            .filter(|path| {
+                path.starts_with("/backups") && path.to_string_lossy().contains("segment_")
+            })
+            .count()
+    };
+
+    write(&mut db, 0..200, b"first");
+    copied();
+    let first = engine.create_backup(&mut db).unwrap();
+    // Two segments, each with an index, a filter and properties
+    assert_eq!(copied(), 8);
+
+    write(&mut db, 200..300, b"second");
+    copied();
+    let second = engine.create_backup(&mut db).unwrap();
+    assert_eq!(copied(), 4);
+
+    assert!(engine.verify_backup(first).unwrap().is_healthy());
+    assert!(engine.verify_backup(second).unwrap().is_healthy());
+}
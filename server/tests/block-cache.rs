@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use server::database::{BlockCache, Database, DatabaseOptions, ReadOptions};
+use tempfile::TempDir;
+
+fn populated_database(
+    temp_dir: &TempDir,
+    block_cache: Arc<BlockCache>,
+) -> Database<&std::path::Path> {
+    let mut db = Database::open(
+        temp_dir.path(),
+        DatabaseOptions {
+            max_table_size: Some(1000),
+            block_cache: Some(block_cache),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    for i in 0..3000 {
+        db.set(
+            format!("key_{}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+    db
+}
+
+#[test]
+fn repeated_reads_are_served_from_the_block_cache() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = populated_database(&temp_dir, Arc::new(BlockCache::default()));
+
+    assert_eq!(db.get(b"key_1500").unwrap(), Some(b"value_1500".to_vec()));
+    assert_eq!(db.block_cache().misses(), 1);
+    assert_eq!(db.block_cache().hits(), 0);
+
+    assert_eq!(db.get(b"key_1500").unwrap(), Some(b"value_1500".to_vec()));
+    assert_eq!(db.block_cache().misses(), 1);
+    assert_eq!(db.block_cache().hits(), 1);
+}
+
+#[test]
+fn reads_can_skip_filling_the_block_cache() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = populated_database(&temp_dir, Arc::new(BlockCache::default()));
+    let read_options = ReadOptions { fill_cache: false };
+
+    for _ in 0..2 {
+        assert_eq!(
+            db.get_with_options(b"key_10", &read_options).unwrap(),
+            Some(b"value_10".to_vec())
+        );
+    }
+
+    assert_eq!(db.block_cache().hits(), 0);
+    assert_eq!(db.block_cache().misses(), 2);
+    assert_eq!(db.block_cache().usage(), 0);
+}
+
+#[test]
+fn block_cache_stays_within_its_capacity() {
+    let temp_dir = TempDir::new().unwrap();
+    let block_cache = Arc::new(BlockCache::new(16 * 1024));
+    let db = populated_database(&temp_dir, Arc::clone(&block_cache));
+
+    for i in (0..3000).step_by(7) {
+        assert_eq!(
+            db.get(format!("key_{}", i).as_bytes()).unwrap(),
+            Some(format!("value_{}", i).into_bytes())
+        );
+    }
+
+    assert!(block_cache.usage() > 0);
+    assert!(block_cache.usage() <= block_cache.capacity());
+}
+
+#[test]
+fn databases_sharing_a_block_cache_read_their_own_blocks() {
+    let block_cache = Arc::new(BlockCache::default());
+    let open = |temp_dir: &TempDir| {
+        Database::open(
+            temp_dir.path().to_path_buf(),
+            DatabaseOptions {
+                max_table_size: Some(1),
+                block_cache: Some(Arc::clone(&block_cache)),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let (dir_a, dir_b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+    let mut db_a = open(&dir_a);
+    let mut db_b = open(&dir_b);
+
+    // Both writes land at the start of segment 1 of their database
+    db_a.set(b"k1", b"from_a").unwrap();
+    db_b.set(b"k1", b"from_b").unwrap();
+    assert_eq!(db_a.get(b"k1").unwrap(), Some(b"from_a".to_vec()));
+    assert_eq!(db_b.get(b"k1").unwrap(), Some(b"from_b".to_vec()));
+    assert_eq!(block_cache.misses(), 2);
+}
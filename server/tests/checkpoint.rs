@@ -0,0 +1,93 @@
+use std::{os::unix::fs::MetadataExt, path::Path, sync::Arc};
+
+use server::database::{
+    Database, DatabaseOptions,
+    env::{Env, MemEnv},
+};
+use tempfile::TempDir;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:04}", i).into_bytes()
+}
+
+#[test]
+fn checkpoint_holds_flushed_and_unflushed_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let checkpoint_dir = temp_dir.path().join("checkpoint");
+    let mut db = Database::new(temp_dir.path().join("db"), Some(100)).unwrap();
+    for i in 0..250 {
+        db.set(&key(i), b"before").unwrap();
+    }
+    db.delete(&key(0)).unwrap();
+
+    db.checkpoint(&checkpoint_dir).unwrap();
+
+    // Writes and compactions after the checkpoint must not show up in it
+    for i in 0..500 {
+        db.set(&key(i), b"after").unwrap();
+    }
+    let checkpoint = Database::new(&checkpoint_dir, Some(100)).unwrap();
+    assert_eq!(checkpoint.get(&key(0)).unwrap(), None);
+    for i in 1..250 {
+        assert_eq!(checkpoint.get(&key(i)).unwrap(), Some(b"before".to_vec()));
+    }
+    assert_eq!(checkpoint.get(&key(300)).unwrap(), None);
+    assert!(checkpoint.verify().is_healthy());
+    assert_eq!(db.get(&key(1)).unwrap(), Some(b"after".to_vec()));
+}
+
+#[test]
+fn checkpoint_links_segment_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let checkpoint_dir = temp_dir.path().join("checkpoint");
+    let mut db = Database::new(temp_dir.path().join("db"), Some(100)).unwrap();
+    for i in 0..100 {
+        db.set(&key(i), b"value").unwrap();
+    }
+
+    db.checkpoint(&checkpoint_dir).unwrap();
+
+    let segment = checkpoint_dir.join("segment_1.sst");
+    let original = temp_dir.path().join("db").join("segment_1.sst");
+    assert_eq!(
+        std::fs::metadata(&segment).unwrap().ino(),
+        std::fs::metadata(&original).unwrap().ino()
+    );
+    assert!(checkpoint_dir.join("MANIFEST").exists());
+}
+
+#[test]
+fn checkpoint_refuses_existing_directories() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::new(temp_dir.path().join("db"), None).unwrap();
+    db.set(b"key", b"value").unwrap();
+
+    let error = db.checkpoint(temp_dir.path()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn checkpoint_captures_separated_values() {
+    let env: Arc<dyn Env> = Arc::new(MemEnv::new());
+    let options = || DatabaseOptions {
+        max_table_size: Some(10),
+        value_log_threshold: Some(64),
+        env: Some(Arc::clone(&env)),
+        ..Default::default()
+    };
+    let mut db = Database::open(Path::new("/db"), options()).unwrap();
+    for i in 0..25 {
+        db.set(&key(i), &[b'a'; 100]).unwrap();
+    }
+
+    db.checkpoint(Path::new("/checkpoint")).unwrap();
+    for i in 0..25 {
+        db.set(&key(i), &[b'b'; 100]).unwrap();
+    }
+    while db.collect_value_log_garbage().unwrap() {}
+
+    let checkpoint = Database::open(Path::new("/checkpoint"), options()).unwrap();
+    for i in 0..25 {
+        assert_eq!(checkpoint.get(&key(i)).unwrap(), Some(vec![b'a'; 100]));
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use server::database::{CompactionDecision, CompactionFilter, Database, DatabaseOptions};
+use tempfile::TempDir;
+
+/// Purges the keys of deleted tenants and expires values of the form
+/// "<expiry timestamp>:<payload>" that expired before `now`
+#[derive(Debug)]
+struct TenantFilter {
+    deleted_tenants: Vec<&'static str>,
+    now: u64,
+}
+
+impl CompactionFilter for TenantFilter {
+    fn filter(&self, key: &[u8], value: &[u8]) -> CompactionDecision {
+        let tenant = key.split(|&b| b == b'/').next().unwrap_or_default();
+        if self
+            .deleted_tenants
+            .iter()
+            .any(|deleted| deleted.as_bytes() == tenant)
+        {
+            return CompactionDecision::Remove;
+        }
+
+        let value = std::str::from_utf8(value).unwrap();
+        match value.split_once(':') {
+            Some((expiry, _)) if expiry.parse::<u64>().unwrap() < self.now => {
+                CompactionDecision::Remove
+            }
+            Some((_, payload)) if payload.starts_with("stale") => {
+                CompactionDecision::ChangeValue(format!("{}:fresh", self.now + 100).into_bytes())
+            }
+            _ => CompactionDecision::Keep,
+        }
+    }
+}
+
+fn options(compaction_filter: Option<Arc<dyn CompactionFilter>>) -> DatabaseOptions {
+    DatabaseOptions {
+        max_table_size: Some(100),
+        level0_compaction_trigger: Some(2),
+        value_log_threshold: Some(32),
+        compaction_filter,
+        ..Default::default()
+    }
+}
+
+fn key(tenant: usize, i: usize) -> Vec<u8> {
+    format!("tenant{}/key_{:03}", tenant, i).into_bytes()
+}
+
+fn get(db: &Database<&std::path::Path>, key: &[u8]) -> Option<String> {
+    db.get(key)
+        .unwrap()
+        .map(|value| String::from_utf8(value).unwrap())
+}
+
+#[test]
+fn compaction_filter_removes_and_rewrites_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    let filter = TenantFilter {
+        deleted_tenants: vec!["tenant1"],
+        now: 1000,
+    };
+    let mut db = Database::open(temp_dir.path(), options(Some(Arc::new(filter)))).unwrap();
+
+    for i in 0..50 {
+        db.set(&key(1, i), b"2000:payload").unwrap();
+        db.set(&key(2, i), format!("{}:payload", 990 + i).as_bytes())
+            .unwrap();
+    }
+    // Long enough to be moved to the value log, which the filter still sees through
+    let long_stale = format!("2000:stale{}", "_".repeat(40));
+    db.set(&key(3, 0), b"2000:stale").unwrap();
+    db.set(&key(3, 1), long_stale.as_bytes()).unwrap();
+    assert_eq!(get(&db, &key(1, 0)).as_deref(), Some("2000:payload"));
+
+    // Fill two more tables so the first one is flushed and compacted
+    for i in 0..198 {
+        db.set(&key(4, i), b"2000:payload").unwrap();
+    }
+    assert_eq!(db.stats().unwrap().compactions, 1);
+
+    for i in 0..50 {
+        assert_eq!(get(&db, &key(1, i)), None);
+        let expected = (990 + i >= 1000).then(|| format!("{}:payload", 990 + i));
+        assert_eq!(get(&db, &key(2, i)), expected);
+    }
+    assert_eq!(get(&db, &key(3, 0)).as_deref(), Some("1100:fresh"));
+    assert_eq!(get(&db, &key(3, 1)).as_deref(), Some("1100:fresh"));
+    assert_eq!(get(&db, &key(4, 0)).as_deref(), Some("2000:payload"));
+
+    // Writes made after the compaction are only filtered by the next one
+    db.set(&key(1, 0), b"2000:payload").unwrap();
+    assert_eq!(get(&db, &key(1, 0)).as_deref(), Some("2000:payload"));
+    drop(db);
+
+    // The removed keys stay removed without a filter
+    let db = Database::open(temp_dir.path(), options(None)).unwrap();
+    assert_eq!(get(&db, &key(1, 1)), None);
+    assert_eq!(get(&db, &key(2, 0)), None);
+    assert_eq!(get(&db, &key(3, 1)).as_deref(), Some("1100:fresh"));
+}
+
+#[derive(Debug)]
+struct BrokenFilter;
+
+impl CompactionFilter for BrokenFilter {
+    fn filter(&self, _key: &[u8], _value: &[u8]) -> CompactionDecision {
+        CompactionDecision::ChangeValue(b"two\nlines".to_vec())
+    }
+}
+
+#[test]
+fn compaction_fails_on_values_segments_cannot_hold() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(temp_dir.path(), options(Some(Arc::new(BrokenFilter)))).unwrap();
+    for i in 0..199 {
+        db.set(&key(1, i), b"value").unwrap();
+    }
+
+    let error = db.set(&key(1, 199), b"value").unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    // The compaction's inputs are left in place
+    assert_eq!(db.stats().unwrap().segments_per_level, vec![2, 0]);
+    for i in 0..200 {
+        assert_eq!(get(&db, &key(1, i)).as_deref(), Some("value"));
+    }
+}
@@ -0,0 +1,206 @@
+use std::sync::Arc;
+
+use server::database::{
+    BlockedBloomFilterPolicy, Database, DatabaseOptions, FilterPolicy, XorFilterPolicy,
+    env::{Env, FaultInjectionEnv, MemEnv},
+};
+use tempfile::TempDir;
+
+fn options(filter_policies: Vec<Arc<dyn FilterPolicy>>) -> DatabaseOptions {
+    DatabaseOptions {
+        max_table_size: Some(100),
+        level0_compaction_trigger: Some(2),
+        filter_policies,
+        ..Default::default()
+    }
+}
+
+fn files_with_extension(temp_dir: &TempDir, extension: &str) -> Vec<std::path::PathBuf> {
+    std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect()
+}
+
+fn write_and_overwrite(db: &mut Database<&std::path::Path>) {
+    for round in 0..3 {
+        for i in 0..300 {
+            db.set(
+                format!("key_{:03}", i).as_bytes(),
+                format!("value_{}_{}", i, round).as_bytes(),
+            )
+            .unwrap();
+        }
+    }
+    for i in (0..300).step_by(3) {
+        db.delete(format!("key_{:03}", i).as_bytes()).unwrap();
+    }
+}
+
+fn assert_contents(db: &Database<&std::path::Path>) {
+    for i in 0..300 {
+        let expected = (i % 3 != 0).then(|| format!("value_{}_2", i).into_bytes());
+        assert_eq!(
+            db.get(format!("key_{:03}", i).as_bytes()).unwrap(),
+            expected
+        );
+    }
+}
+
+#[test]
+fn compaction_merges_segments_and_keeps_the_newest_values() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::open(temp_dir.path(), options(Vec::new())).unwrap();
+        write_and_overwrite(&mut db);
+        assert_contents(&db);
+
+        // 1000 writes in tables of 100 leave at most one level 0 segment beside the bottom one
+        assert!(files_with_extension(&temp_dir, "sst").len() <= 2);
+        assert_eq!(
+            files_with_extension(&temp_dir, "sst").len(),
+            files_with_extension(&temp_dir, "bf").len()
+        );
+        assert_eq!(
+            files_with_extension(&temp_dir, "sst").len(),
+            files_with_extension(&temp_dir, "props").len()
+        );
+    }
+
+    let db = Database::open(temp_dir.path(), options(Vec::new())).unwrap();
+    assert_contents(&db);
+}
+
+#[test]
+fn filter_policies_are_chosen_per_level() {
+    let temp_dir = TempDir::new().unwrap();
+    let policies: Vec<Arc<dyn FilterPolicy>> = vec![
+        Arc::new(BlockedBloomFilterPolicy::default()),
+        Arc::new(XorFilterPolicy),
+    ];
+    {
+        let mut db = Database::open(temp_dir.path(), options(policies)).unwrap();
+        write_and_overwrite(&mut db);
+        assert_contents(&db);
+    }
+
+    let filter_formats: Vec<Vec<u8>> = files_with_extension(&temp_dir, "bf")
+        .into_iter()
+        .map(|path| std::fs::read(path).unwrap()[..7].to_vec())
+        .collect();
+    assert!(filter_formats.contains(&b"KVXOR8F".to_vec()));
+
+    // Filter files identify their policy, so they stay readable under the default one
+    let db = Database::open(temp_dir.path(), options(Vec::new())).unwrap();
+    assert_contents(&db);
+}
+
+#[test]
+fn acknowledged_writes_survive_a_crash_during_compaction() {
+    const WRITES: usize = 24;
+    let open = |env: &Arc<FaultInjectionEnv>| {
+        Database::open(
+            std::path::Path::new("/db"),
+            DatabaseOptions {
+                max_table_size: Some(4),
+                level0_compaction_trigger: Some(2),
+                sync_wal: true,
+                env: Some(Arc::clone(env) as Arc<dyn Env>),
+                ..Default::default()
+            },
+        )
+    };
+
+    let mut operation = 0;
+    loop {
+        let env = Arc::new(FaultInjectionEnv::new(Arc::new(MemEnv::new())));
+        let mut acknowledged = 0;
+        {
+            let mut db = open(&env).unwrap();
+            env.crash_after(operation).unwrap();
+            for i in 0..WRITES {
+                let key = format!("key{}", i % 6);
+                if db.set(key.as_bytes(), i.to_string().as_bytes()).is_err() {
+                    break;
+                }
+                acknowledged = i + 1;
+            }
+        }
+        env.crash().unwrap();
+
+        // The failed write may still have reached the WAL before the crash
+        let db = open(&env).unwrap();
+        for i in acknowledged.saturating_sub(6)..acknowledged {
+            let key = format!("key{}", i % 6);
+            let value = db.get(key.as_bytes()).unwrap();
+            let in_flight = acknowledged < WRITES && acknowledged % 6 == i % 6;
+            assert!(
+                value == Some(i.to_string().into_bytes())
+                    || (in_flight && value == Some(acknowledged.to_string().into_bytes())),
+                "wrong value {:?} for {} after crashing at operation {}",
+                value,
+                key,
+                operation
+            );
+        }
+
+        if acknowledged == WRITES {
+            break;
+        }
+        operation += 1;
+    }
+}
+
+#[test]
+fn compaction_only_rewrites_overlapping_bottom_level_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let options = || DatabaseOptions {
+        target_segment_size: Some(1000),
+        ..options(Vec::new())
+    };
+    let key = |prefix: &str, i: usize| format!("{}_{:03}", prefix, i).into_bytes();
+    let mut db = Database::open(temp_dir.path(), options()).unwrap();
+
+    for i in 0..400 {
+        db.set(&key("a", i), b"value").unwrap();
+    }
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.compactions, 2);
+    assert_eq!(stats.segments_per_level[0], 0);
+    assert!(stats.segments_per_level[1] > 2);
+
+    // Keys after every existing one leave the bottom level segments alone
+    let bottom_level = files_with_extension(&temp_dir, "sst");
+    let bottom_level_size: u64 = bottom_level
+        .iter()
+        .map(|path| std::fs::metadata(path).unwrap().len())
+        .sum();
+    let bytes_read = stats.compaction_bytes_read;
+    for i in 0..200 {
+        db.set(&key("b", i), b"value").unwrap();
+    }
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.compactions, 3);
+    assert!(bottom_level.iter().all(|path| path.exists()));
+    assert!(stats.compaction_bytes_read - bytes_read < bottom_level_size);
+
+    // Tombstones reach the older versions of their keys in the bottom level
+    for i in 0..100 {
+        db.delete(&key("a", i)).unwrap();
+    }
+    for i in 0..100 {
+        db.set(&key("c", i), b"value").unwrap();
+    }
+    assert_eq!(db.stats().unwrap().compactions, 4);
+    drop(db);
+
+    let db = Database::open(temp_dir.path(), options()).unwrap();
+    for i in 0..400 {
+        let expected = (i >= 100).then(|| b"value".to_vec());
+        assert_eq!(db.get(&key("a", i)).unwrap(), expected);
+    }
+    assert_eq!(db.get(&key("b", 199)).unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get(&key("c", 0)).unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.stats().unwrap().mem_table_entries, 0);
+}
@@ -0,0 +1,101 @@
+use std::{path::Path, sync::Arc};
+
+use server::database::{
+    Database, DatabaseOptions,
+    env::{Env, FaultInjectionEnv, MemEnv},
+};
+
+const DIRECTORY: &str = "/db";
+const KEY_COUNT: usize = 12;
+
+fn open(env: &Arc<FaultInjectionEnv>, sync_wal: bool) -> std::io::Result<Database<&'static Path>> {
+    Database::open(
+        Path::new(DIRECTORY),
+        DatabaseOptions {
+            max_table_size: Some(4),
+            sync_wal,
+            env: Some(Arc::clone(env) as Arc<dyn Env>),
+            ..Default::default()
+        },
+    )
+}
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key{:02}", i).into_bytes()
+}
+
+#[test]
+fn acknowledged_writes_survive_a_crash_at_any_operation() {
+    let mut operation = 0;
+    loop {
+        let env = Arc::new(FaultInjectionEnv::new(Arc::new(MemEnv::new())));
+        let mut acknowledged = 0;
+        {
+            let mut db = open(&env, true).unwrap();
+            env.crash_after(operation).unwrap();
+            for i in 0..KEY_COUNT {
+                if db.set(&key(i), b"value").is_err() {
+                    break;
+                }
+                acknowledged = i + 1;
+            }
+        }
+        env.crash().unwrap();
+
+        let db = open(&env, true).unwrap();
+        for i in 0..acknowledged {
+            assert_eq!(
+                db.get(&key(i)).unwrap(),
+                Some(b"value".to_vec()),
+                "lost {:?} after crashing at operation {}",
+                String::from_utf8_lossy(&key(i)),
+                operation
+            );
+        }
+
+        if acknowledged == KEY_COUNT {
+            break;
+        }
+        operation += 1;
+    }
+}
+
+#[test]
+fn crash_only_loses_writes_since_the_last_flush_when_the_wal_is_not_synced() {
+    let env = Arc::new(FaultInjectionEnv::new(Arc::new(MemEnv::new())));
+    {
+        let mut db = open(&env, false).unwrap();
+        for i in 0..6 {
+            db.set(&key(i), b"value").unwrap();
+        }
+    }
+    env.crash().unwrap();
+
+    let db = open(&env, false).unwrap();
+    for i in 0..4 {
+        assert_eq!(db.get(&key(i)).unwrap(), Some(b"value".to_vec()));
+    }
+    assert_eq!(db.get(&key(4)).unwrap(), None);
+    assert_eq!(db.get(&key(5)).unwrap(), None);
+}
+
+#[test]
+fn failed_writes_are_reported_and_the_database_reopens() {
+    let env = Arc::new(FaultInjectionEnv::new(Arc::new(MemEnv::new())));
+    {
+        let mut db = open(&env, true).unwrap();
+        db.set(&key(0), b"value").unwrap();
+
+        env.fail_writes(true).unwrap();
+        assert!(db.set(&key(1), b"value").is_err());
+        env.fail_writes(false).unwrap();
+
+        db.set(&key(2), b"value").unwrap();
+    }
+    env.crash().unwrap();
+
+    let db = open(&env, true).unwrap();
+    assert_eq!(db.get(&key(0)).unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get(&key(1)).unwrap(), None);
+    assert_eq!(db.get(&key(2)).unwrap(), Some(b"value".to_vec()));
+}
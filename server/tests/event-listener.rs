@@ -0,0 +1,203 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use server::database::{
+    BackgroundErrorReason, CompactionBeginInfo, CompactionJobInfo, Database, DatabaseOptions,
+    EventListener, FlushBeginInfo, FlushJobInfo, Scrubber, SegmentCreationInfo,
+    SegmentDeletionInfo, SegmentWriter, WalRotationInfo, WriteStallInfo,
+};
+use tempfile::TempDir;
+
+#[derive(Debug, Default)]
+struct RecordingListener {
+    events: Mutex<Vec<String>>,
+}
+
+impl RecordingListener {
+    fn record(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.events.lock().unwrap())
+    }
+}
+
+impl EventListener for RecordingListener {
+    fn on_flush_begin(&self, info: &FlushBeginInfo) {
+        self.record(format!("flush begin {}", info.num_entries));
+    }
+
+    fn on_flush_completed(&self, info: &FlushJobInfo) {
+        assert!(info.bytes_written > 0);
+        self.record(format!(
+            "flush completed {} {}",
+            info.segment_number, info.num_entries
+        ));
+    }
+
+    fn on_compaction_begin(&self, info: &CompactionBeginInfo) {
+        self.record(format!("compaction begin {:?}", info.input_segments));
+    }
+
+    fn on_compaction_completed(&self, info: &CompactionJobInfo) {
+        assert!(info.bytes_read > 0);
+        assert!(info.bytes_written > 0);
+        self.record(format!(
+            "compaction completed {:?} {:?}",
+            info.input_segments, info.output_segments
+        ));
+    }
+
+    fn on_segment_created(&self, info: &SegmentCreationInfo) {
+        assert!(info.path.exists());
+        self.record(format!(
+            "segment created {} {} {:?}",
+            info.segment_number, info.level, info.reason
+        ));
+    }
+
+    fn on_segment_deleted(&self, info: &SegmentDeletionInfo) {
+        assert!(!info.path.exists());
+        self.record(format!("segment deleted {}", info.segment_number));
+    }
+
+    fn on_wal_rotated(&self, info: &WalRotationInfo) {
+        self.record(format!(
+            "wal rotated {} {} {}",
+            info.previous_number,
+            info.new_number,
+            info.retired.len()
+        ));
+    }
+
+    fn on_write_stall(&self, _info: &WriteStallInfo) {
+        self.record("write stall".to_string());
+    }
+
+    fn on_background_error(&self, reason: BackgroundErrorReason, error: &std::io::Error) {
+        self.record(format!("background error {:?} {:?}", reason, error.kind()));
+    }
+}
+
+fn open(temp_dir: &TempDir, listener: &Arc<RecordingListener>) -> Database<std::path::PathBuf> {
+    Database::open(
+        temp_dir.path().to_path_buf(),
+        DatabaseOptions {
+            max_table_size: Some(100),
+            listeners: vec![Arc::clone(listener) as Arc<dyn EventListener>],
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:04}", i).into_bytes()
+}
+
+#[test]
+fn flushes_and_compactions_are_reported_in_order() {
+    let temp_dir = TempDir::new().unwrap();
+    let listener = Arc::new(RecordingListener::default());
+    let mut db = open(&temp_dir, &listener);
+
+    for i in 0..100 {
+        db.set(&key(i), b"value").unwrap();
+    }
+    // File numbers are handed out in turn to segments and WAL files, starting after WAL 0
+    assert_eq!(
+        listener.take(),
+        vec![
+            "flush begin 100",
+            "segment created 1 0 Flush",
+            "wal rotated 0 2 1",
+            "flush completed 1 100",
+            "write stall",
+        ]
+    );
+
+    for i in 100..400 {
+        db.delete(&key(i)).unwrap();
+    }
+    let events = listener.take();
+    assert_eq!(
+        events[events.len() - 9..],
+        [
+            "flush completed 7 100",
+            "compaction begin [7, 5, 3, 1]",
+            "segment created 9 1 Compaction",
+            "segment deleted 7",
+            "segment deleted 5",
+            "segment deleted 3",
+            "segment deleted 1",
+            "compaction completed [7, 5, 3, 1] [9]",
+            "write stall",
+        ]
+    );
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| *event == "write stall")
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn ingested_segments_are_reported() {
+    let temp_dir = TempDir::new().unwrap();
+    let listener = Arc::new(RecordingListener::default());
+    let mut db = open(&temp_dir, &listener);
+
+    let external_dir = TempDir::new().unwrap();
+    let path = external_dir.path().join("0.sst");
+    let mut writer = SegmentWriter::create(&path).unwrap();
+    writer.put(b"a", b"1").unwrap();
+    writer.put(b"b", b"2").unwrap();
+    writer.finish().unwrap();
+
+    db.ingest_external_files(&[&path]).unwrap();
+    assert_eq!(listener.take(), vec!["segment created 1 1 Ingestion"]);
+}
+
+#[test]
+fn scrubber_reports_damaged_files_as_background_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    let listener = Arc::new(RecordingListener::default());
+    let mut db = open(&temp_dir, &listener);
+    for i in 0..100 {
+        db.set(&key(i), b"value").unwrap();
+    }
+    listener.take();
+
+    let segment_path = temp_dir.path().join("segment_1.sst");
+    let mut data = std::fs::read(&segment_path).unwrap();
+    let middle = data.len() / 2;
+    data[middle] ^= 0x01;
+    std::fs::write(&segment_path, data).unwrap();
+
+    let db = Arc::new(Mutex::new(db));
+    let scrubber = Scrubber::start(Arc::clone(&db), Duration::from_secs(3600)).unwrap();
+    let started = Instant::now();
+    let report = loop {
+        if let Some(report) = scrubber.last_report() {
+            break report;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    drop(scrubber);
+
+    // One error for each unhealthy file
+    let events = listener.take();
+    assert!(!events.is_empty());
+    assert_eq!(events.len(), report.unhealthy_files().count());
+    assert!(
+        events
+            .iter()
+            .all(|event| event == "background error Scrub InvalidData")
+    );
+}
@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+use server::database::{Database, SegmentInspector, SegmentWriter};
+use tempfile::TempDir;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:04}", i).into_bytes()
+}
+
+fn write_segment(path: &Path, keys: std::ops::Range<usize>, value: &[u8]) -> PathBuf {
+    let mut writer = SegmentWriter::create(path).unwrap();
+    for i in keys {
+        writer.put(&key(i), value).unwrap();
+    }
+    writer.finish().unwrap();
+    path.to_path_buf()
+}
+
+#[test]
+fn segment_writer_builds_a_complete_segment() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("import.sst");
+    let mut writer = SegmentWriter::create(&path).unwrap();
+    for i in 0..250 {
+        writer.put(&key(i), b"value").unwrap();
+    }
+    writer.delete(&key(250)).unwrap();
+    assert_eq!(writer.num_entries(), 251);
+    assert!(!path.exists());
+
+    let properties = writer.finish().unwrap();
+    assert_eq!(properties.num_entries, 251);
+    assert_eq!(properties.num_tombstones, 1);
+    assert_eq!(properties.min_key, key(0));
+    assert_eq!(properties.max_key, key(250));
+
+    let inspector = SegmentInspector::open(&path).unwrap();
+    assert_eq!(inspector.records().unwrap().len(), 251);
+    assert_eq!(inspector.index_entries().unwrap().unwrap().len(), 3);
+    assert!(inspector.filter().unwrap().is_some());
+    assert_eq!(inspector.properties().unwrap(), Some(properties));
+}
+
+#[test]
+fn segment_writer_rejects_unsorted_and_unstorable_records() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut writer = SegmentWriter::create(temp_dir.path().join("import.sst")).unwrap();
+    writer.put(b"b", b"value").unwrap();
+
+    for (key, value) in [
+        (b"a".as_slice(), b"value".as_slice()),
+        (b"b", b"value"),
+        (b"c d", b"value"),
+        (b"", b"value"),
+        (b"c", b"two\nlines"),
+        (b"c", b"\x001 0 5"),
+    ] {
+        let error = writer.put(key, value).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+    writer.put(b"c", b"value").unwrap();
+    assert!(SegmentWriter::create(temp_dir.path().join("import.txt")).is_err());
+}
+
+#[test]
+fn ingested_files_are_visible_and_survive_reopening() {
+    let temp_dir = TempDir::new().unwrap();
+    let db_dir = temp_dir.path().join("db");
+    let first = write_segment(&temp_dir.path().join("first.sst"), 0..150, b"imported");
+    let second = write_segment(&temp_dir.path().join("second.sst"), 150..300, b"imported");
+
+    let mut db = Database::new(&db_dir, Some(100)).unwrap();
+    db.ingest_external_files(&[second, first]).unwrap();
+    assert_eq!(db.get(&key(0)).unwrap(), Some(b"imported".to_vec()));
+    assert_eq!(db.get(&key(299)).unwrap(), Some(b"imported".to_vec()));
+    assert_eq!(db.get(&key(300)).unwrap(), None);
+    assert_eq!(db.scan_prefix(b"key_01").unwrap().len(), 100);
+    assert!(db.verify().is_healthy());
+    drop(db);
+
+    let db = Database::new(&db_dir, Some(100)).unwrap();
+    for i in 0..300 {
+        assert_eq!(db.get(&key(i)).unwrap(), Some(b"imported".to_vec()));
+    }
+    assert!(db.verify().is_healthy());
+}
+
+#[test]
+fn ingested_files_shadow_earlier_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let db_dir = temp_dir.path().join("db");
+    let mut db = Database::new(db_dir.clone(), Some(100)).unwrap();
+    for i in 0..150 {
+        db.set(&key(i), b"written").unwrap();
+    }
+
+    let mut writer = SegmentWriter::create(temp_dir.path().join("import.sst")).unwrap();
+    for i in 50..100 {
+        writer.put(&key(i), b"imported").unwrap();
+    }
+    writer.delete(&key(100)).unwrap();
+    writer.finish().unwrap();
+    db.ingest_external_files(&[temp_dir.path().join("import.sst")])
+        .unwrap();
+
+    // Later writes shadow the ingested records in turn
+    db.set(&key(51), b"rewritten").unwrap();
+    let check = |db: &Database<PathBuf>| {
+        assert_eq!(db.get(&key(49)).unwrap(), Some(b"written".to_vec()));
+        assert_eq!(db.get(&key(50)).unwrap(), Some(b"imported".to_vec()));
+        assert_eq!(db.get(&key(51)).unwrap(), Some(b"rewritten".to_vec()));
+        assert_eq!(db.get(&key(100)).unwrap(), None);
+        assert_eq!(db.get(&key(149)).unwrap(), Some(b"written".to_vec()));
+    };
+    check(&db);
+    drop(db);
+    check(&Database::new(db_dir, Some(100)).unwrap());
+}
+
+#[test]
+fn overlapping_or_damaged_files_are_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::new(temp_dir.path().join("db"), None).unwrap();
+    let first = write_segment(&temp_dir.path().join("first.sst"), 0..100, b"value");
+    let second = write_segment(&temp_dir.path().join("second.sst"), 50..150, b"value");
+
+    let error = db.ingest_external_files(&[&first, &second]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    let mut data = std::fs::read(&first).unwrap();
+    data[3] ^= 0x01;
+    std::fs::write(&first, data).unwrap();
+    let error = db.ingest_external_files(&[&first]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    assert_eq!(db.get(&key(0)).unwrap(), None);
+    assert!(db.verify().files.is_empty());
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use server::database::{
+    BlockedBloomFilterPolicy, Database, DatabaseOptions, FilterPolicy, XorFilterPolicy,
+};
+use tempfile::TempDir;
+
+fn options(filter_policies: Vec<Arc<dyn FilterPolicy>>) -> DatabaseOptions {
+    DatabaseOptions {
+        max_table_size: Some(100),
+        filter_policies,
+        ..Default::default()
+    }
+}
+
+fn filter_formats(temp_dir: &TempDir) -> Vec<Vec<u8>> {
+    std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bf"))
+        .map(|path| std::fs::read(path).unwrap()[..7].to_vec())
+        .collect()
+}
+
+fn populate(db: &mut Database<&std::path::Path>) {
+    for i in 0..300 {
+        db.set(
+            format!("key_{:03}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+}
+
+fn assert_contents(db: &Database<&std::path::Path>) {
+    for i in 0..300 {
+        assert_eq!(
+            db.get(format!("key_{:03}", i).as_bytes()).unwrap(),
+            Some(format!("value_{}", i).into_bytes())
+        );
+    }
+    assert_eq!(db.get(b"key_999").unwrap(), None);
+}
+
+#[test]
+fn configured_policy_writes_the_filters_of_new_segments() {
+    for (policy, format) in [
+        (
+            Arc::new(BlockedBloomFilterPolicy::default()) as Arc<dyn FilterPolicy>,
+            b"KVBLKBF",
+        ),
+        (
+            Arc::new(XorFilterPolicy) as Arc<dyn FilterPolicy>,
+            b"KVXOR8F",
+        ),
+    ] {
+        let temp_dir = TempDir::new().unwrap();
+        let mut db = Database::open(temp_dir.path(), options(vec![policy])).unwrap();
+        populate(&mut db);
+        assert_contents(&db);
+
+        let formats = filter_formats(&temp_dir);
+        assert!(!formats.is_empty());
+        assert!(formats.iter().all(|tag| tag == format));
+    }
+}
+
+#[test]
+fn filters_stay_readable_after_the_policy_changes() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db =
+            Database::open(temp_dir.path(), options(vec![Arc::new(XorFilterPolicy)])).unwrap();
+        populate(&mut db);
+    }
+
+    // Filter files identify their policy, so they load under the default one
+    let db = Database::open(temp_dir.path(), options(Vec::new())).unwrap();
+    assert_contents(&db);
+}
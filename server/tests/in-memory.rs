@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use server::database::{Database, DatabaseOptions};
+use tempfile::TempDir;
+
+fn in_memory(directory: PathBuf) -> Database<PathBuf> {
+    Database::open(
+        directory,
+        DatabaseOptions {
+            max_table_size: Some(10),
+            in_memory: true,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn in_memory_database_flushes_without_touching_disk() {
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("data");
+    let mut db = in_memory(directory.clone());
+
+    for i in 0..95 {
+        db.set(format!("key_{}", i).as_bytes(), b"value").unwrap();
+    }
+    db.delete(b"key_3").unwrap();
+
+    assert_eq!(db.get(b"key_0").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get(b"key_94").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get(b"key_3").unwrap(), None);
+    assert!(!directory.exists());
+}
+
+#[test]
+fn in_memory_databases_do_not_share_state() {
+    let mut first = in_memory(PathBuf::from("memory"));
+    first.set(b"key", b"value").unwrap();
+
+    let second = in_memory(PathBuf::from("memory"));
+    assert_eq!(second.get(b"key").unwrap(), None);
+}
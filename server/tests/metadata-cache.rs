@@ -0,0 +1,87 @@
+use server::database::{Database, DatabaseOptions};
+use tempfile::TempDir;
+
+fn options(metadata_cache_capacity: usize, pin_level0_metadata: bool) -> DatabaseOptions {
+    DatabaseOptions {
+        max_table_size: Some(500),
+        metadata_cache_capacity: Some(metadata_cache_capacity),
+        pin_level0_metadata,
+        // Keep every segment in level 0
+        level0_compaction_trigger: Some(100),
+        ..Default::default()
+    }
+}
+
+fn populate(temp_dir: &TempDir) {
+    let mut db = Database::open(temp_dir.path(), options(1024 * 1024, false)).unwrap();
+    for i in 0..3000 {
+        db.set(
+            format!("key_{:04}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+}
+
+fn assert_contents(db: &Database<&std::path::Path>) {
+    for i in (0..3000).step_by(7) {
+        assert_eq!(
+            db.get(format!("key_{:04}", i).as_bytes()).unwrap(),
+            Some(format!("value_{}", i).into_bytes())
+        );
+    }
+    assert_eq!(db.get(b"key_9999").unwrap(), None);
+}
+
+#[test]
+fn filters_and_indexes_are_loaded_on_first_use() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+
+    let db = Database::open(temp_dir.path(), options(1024 * 1024, false)).unwrap();
+    assert_eq!(db.metadata_cache().usage(), 0);
+
+    assert_contents(&db);
+    assert!(db.metadata_cache().usage() > 0);
+    assert!(db.metadata_cache().hits() > 0);
+}
+
+#[test]
+fn metadata_stays_within_the_cache_capacity() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+
+    let db = Database::open(temp_dir.path(), options(2048, false)).unwrap();
+    assert_contents(&db);
+    assert!(db.metadata_cache().usage() <= 2048);
+}
+
+#[test]
+fn pinned_level0_metadata_bypasses_the_cache() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+
+    let mut db = Database::open(temp_dir.path(), options(1024 * 1024, true)).unwrap();
+    db.set(b"key_3000", b"value_3000").unwrap();
+    assert_contents(&db);
+
+    assert_eq!(db.metadata_cache().usage(), 0);
+    assert_eq!(db.metadata_cache().misses(), 0);
+}
+
+#[test]
+fn metadata_too_big_for_the_cache_is_not_kept() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+
+    // No filter or index fits, so each lookup reads the ones it needs and drops them
+    let db = Database::open(temp_dir.path(), options(16, false)).unwrap();
+    assert_contents(&db);
+    assert_eq!(db.metadata_cache().usage(), 0);
+
+    let misses = db.metadata_cache().misses();
+    assert_contents(&db);
+    assert_eq!(db.metadata_cache().usage(), 0);
+    assert_eq!(db.metadata_cache().hits(), 0);
+    assert!(db.metadata_cache().misses() > misses);
+}
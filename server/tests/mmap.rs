@@ -0,0 +1,79 @@
+use server::database::{Database, DatabaseOptions};
+use tempfile::TempDir;
+
+fn mmap_options() -> DatabaseOptions {
+    DatabaseOptions {
+        max_table_size: Some(1000),
+        use_mmap: true,
+        ..Default::default()
+    }
+}
+
+fn populate(db: &mut Database<&std::path::Path>) {
+    for i in 0..3000 {
+        db.set(
+            format!("key_{:04}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn mapped_segments_are_read_without_the_block_cache() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(temp_dir.path(), mmap_options()).unwrap();
+    populate(&mut db);
+
+    for i in (0..3000).step_by(9) {
+        assert_eq!(
+            db.get(format!("key_{:04}", i).as_bytes()).unwrap(),
+            Some(format!("value_{}", i).into_bytes())
+        );
+    }
+    assert_eq!(db.get(b"key_9999").unwrap(), None);
+
+    assert_eq!(db.block_cache().hits(), 0);
+    assert_eq!(db.block_cache().misses(), 0);
+    assert_eq!(db.block_cache().usage(), 0);
+}
+
+#[test]
+fn mapped_segments_stay_readable_after_their_files_are_removed() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(temp_dir.path(), mmap_options()).unwrap();
+    populate(&mut db);
+    assert_eq!(db.get(b"key_0500").unwrap(), Some(b"value_500".to_vec()));
+
+    for entry in std::fs::read_dir(temp_dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "sst") {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    assert_eq!(db.get(b"key_0500").unwrap(), Some(b"value_500".to_vec()));
+    assert_eq!(db.get(b"key_0999").unwrap(), Some(b"value_999".to_vec()));
+}
+
+#[test]
+fn in_memory_databases_can_use_mmap() {
+    let mut db = Database::open(
+        "/db",
+        DatabaseOptions {
+            in_memory: true,
+            ..mmap_options()
+        },
+    )
+    .unwrap();
+    for i in 0..2500 {
+        db.set(
+            format!("key_{:04}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+
+    assert_eq!(db.get(b"key_1234").unwrap(), Some(b"value_1234".to_vec()));
+    assert_eq!(db.get(b"key_2499").unwrap(), Some(b"value_2499".to_vec()));
+}
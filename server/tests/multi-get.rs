@@ -0,0 +1,54 @@
+use server::database::Database;
+use tempfile::TempDir;
+
+fn populated_database(temp_dir: &TempDir) -> Database<&std::path::Path> {
+    let mut db = Database::new(temp_dir.path(), Some(1000)).unwrap();
+    for i in 0..2500 {
+        db.set(
+            format!("key_{:04}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+    db.delete(b"key_0042").unwrap();
+    db.set(b"key_0007", b"updated").unwrap();
+    db
+}
+
+#[test]
+fn multi_get_matches_individual_gets() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = populated_database(&temp_dir);
+
+    let keys: Vec<&[u8]> = vec![
+        b"key_2400",
+        b"key_0042",
+        b"missing",
+        b"key_0007",
+        b"key_1500",
+        b"key_0001",
+        b"key_1500",
+    ];
+    let expected: Vec<_> = keys.iter().map(|key| db.get(key).unwrap()).collect();
+
+    assert_eq!(db.multi_get(&keys).unwrap(), expected);
+    assert_eq!(expected[0], Some(b"value_2400".to_vec()));
+    assert_eq!(expected[1], None);
+    assert_eq!(expected[3], Some(b"updated".to_vec()));
+}
+
+#[test]
+fn multi_get_reads_each_block_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = populated_database(&temp_dir);
+
+    let keys: Vec<Vec<u8>> = (1100..1150)
+        .map(|i| format!("key_{:04}", i).into_bytes())
+        .collect();
+    let key_refs: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+
+    let values = db.multi_get(&key_refs).unwrap();
+    assert!(values.iter().all(Option::is_some));
+    assert_eq!(db.block_cache().misses(), 1);
+    assert_eq!(db.block_cache().hits(), 0);
+}
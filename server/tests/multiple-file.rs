@@ -1,8 +1,18 @@
 use std::path::PathBuf;
 
-use server::database::Database;
+use server::database::{Database, DatabaseOptions};
 use tempfile::TempDir; // Fixed unresolved import
 
+fn list_files(directory: &std::path::Path) -> Vec<PathBuf> {
+    let mut files = std::fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .inspect(|file| println!("File: {:?}", file))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
 #[test]
 fn insert_multiple_records_into_multiple_files() {
     let temp_dir = TempDir::new().unwrap();
@@ -12,16 +22,10 @@ fn insert_multiple_records_into_multiple_files() {
     db.set(b"key4", b"value4").unwrap();
     db.set(b"key2", b"value2").unwrap();
 
-    // List files in temp_dir
-    let directory = std::fs::read_dir(temp_dir.path()).unwrap();
-
-    let files = directory
-        .map(|entry| entry.unwrap().path())
-        .inspect(|file| println!("File: {:?}", file))
-        .collect::<Vec<_>>();
+    let files = list_files(temp_dir.path());
 
     assert_eq!(files.len(), 1);
-    assert_eq!(files[0].clone().file_name().unwrap(), "wal.log");
+    assert_eq!(files[0].clone().file_name().unwrap(), "wal_0.log");
     let wal_contents = std::fs::read_to_string(files[0].clone()).unwrap();
     assert_eq!(
         wal_contents,
@@ -30,30 +34,84 @@ fn insert_multiple_records_into_multiple_files() {
 
     db.set(b"key5", b"value5").unwrap();
 
-    let files = std::fs::read_dir(temp_dir.path()).unwrap();
-    let files = files
-        .map(|entry| entry.unwrap().path())
-        .inspect(|file| println!("File: {:?}", file))
-        .collect::<Vec<_>>();
+    let files = list_files(temp_dir.path());
 
-    assert_eq!(files.len(), 4);
-    assert_eq!(files[0].clone().file_name().unwrap(), "wal.log");
-    assert_eq!(files[1].clone().file_name().unwrap(), "segment_0.bf");
-    assert_eq!(files[2].clone().file_name().unwrap(), "segment_0.sst");
-    assert_eq!(files[3].clone().file_name().unwrap(), "segment_0.idx");
+    assert_eq!(files.len(), 6);
+    assert_eq!(files[0].clone().file_name().unwrap(), "MANIFEST");
+    assert_eq!(files[1].clone().file_name().unwrap(), "segment_1.bf");
+    assert_eq!(files[2].clone().file_name().unwrap(), "segment_1.idx");
+    assert_eq!(files[3].clone().file_name().unwrap(), "segment_1.props");
+    assert_eq!(files[4].clone().file_name().unwrap(), "segment_1.sst");
+    assert_eq!(files[5].clone().file_name().unwrap(), "wal_2.log");
 
-    let wal_contents = std::fs::read_to_string(files[0].clone()).unwrap();
+    let wal_contents = std::fs::read_to_string(files[5].clone()).unwrap();
     assert_eq!(wal_contents, "");
 
-    let segment_contents = std::fs::read_to_string(files[2].clone()).unwrap();
+    let segment_contents = std::fs::read_to_string(files[4].clone()).unwrap();
     assert_eq!(
         segment_contents,
         "key1 value1\nkey2 value2\nkey3 value3\nkey4 value4\nkey5 value5\n"
     );
 
-    let index_contents = std::fs::read_to_string(files[3].clone()).unwrap();
+    let index_contents = std::fs::read_to_string(files[2].clone()).unwrap();
     assert_eq!(
         index_contents.as_bytes(),
-        [b"key1".as_slice(), &0u64.to_le_bytes(), b"\n".as_slice()].concat()
+        [
+            b"KVINDEX\x01".as_slice(),
+            b"key1 ",
+            &0u64.to_le_bytes(),
+            b"\n"
+        ]
+        .concat()
     );
 }
+
+#[test]
+fn unversioned_index_files_are_rewritten_on_open() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::new(temp_dir.path(), Some(5)).unwrap();
+        for i in 0..5 {
+            db.set(format!("key{}", i).as_bytes(), b"value").unwrap();
+        }
+    }
+
+    // Older releases wrote the records without a header or a space before the offset
+    let index_path = temp_dir.path().join("segment_1.idx");
+    let legacy_index = [b"key0".as_slice(), &0u64.to_le_bytes(), b"\n"].concat();
+    std::fs::write(&index_path, legacy_index).unwrap();
+
+    let db = Database::new(temp_dir.path(), Some(5)).unwrap();
+    assert!(
+        std::fs::read(&index_path)
+            .unwrap()
+            .starts_with(b"KVINDEX\x01")
+    );
+    assert_eq!(db.get(b"key3").unwrap(), Some(b"value".to_vec()));
+    let report = db.verify();
+    assert!(report.is_healthy(), "{:?}", report);
+}
+
+#[test]
+fn newer_segments_shadow_older_ones() {
+    let temp_dir = TempDir::new().unwrap();
+    let options = || DatabaseOptions {
+        max_table_size: Some(2),
+        level0_compaction_trigger: Some(10),
+        ..Default::default()
+    };
+    {
+        let mut db = Database::open(temp_dir.path(), options()).unwrap();
+        for round in 0..3 {
+            db.set(b"key", format!("value{}", round).as_bytes())
+                .unwrap();
+            db.set(format!("other{}", round).as_bytes(), b"value")
+                .unwrap();
+        }
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value2".to_vec()));
+    }
+
+    let db = Database::open(temp_dir.path(), options()).unwrap();
+    assert_eq!(db.stats().unwrap().segments_per_level, vec![3, 0]);
+    assert_eq!(db.get(b"key").unwrap(), Some(b"value2".to_vec()));
+}
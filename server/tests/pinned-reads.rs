@@ -0,0 +1,44 @@
+use server::database::{Database, DatabaseOptions};
+use tempfile::TempDir;
+
+fn assert_pinned_reads_match(use_mmap: bool) {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(
+        temp_dir.path(),
+        DatabaseOptions {
+            max_table_size: Some(100),
+            use_mmap,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    for i in 0..250 {
+        db.set(
+            format!("key_{:03}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+    db.delete(b"key_010").unwrap();
+    db.delete(b"key_240").unwrap();
+
+    // key_005 lives in a segment, key_245 in the in-memory table
+    for key in [b"key_005".as_slice(), b"key_245"] {
+        let pinned = db.get_pinned(key).unwrap().unwrap();
+        assert_eq!(&*pinned, db.get(key).unwrap().unwrap().as_slice());
+    }
+    assert!(db.get_pinned(b"key_010").unwrap().is_none());
+    assert!(db.get_pinned(b"key_240").unwrap().is_none());
+    assert!(db.get_pinned(b"missing").unwrap().is_none());
+}
+
+#[test]
+fn pinned_reads_return_the_same_values_as_get() {
+    assert_pinned_reads_match(false);
+}
+
+#[test]
+fn pinned_reads_borrow_from_memory_mapped_segments() {
+    assert_pinned_reads_match(true);
+}
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use server::database::{BloomFilterSize, Database, DatabaseOptions, FixedPrefix};
+use tempfile::TempDir;
+
+const TENANTS: [&str; 3] = ["aaaa", "bbbb", "cccc"];
+
+fn open(temp_dir: &TempDir) -> Database<&std::path::Path> {
+    Database::open(
+        temp_dir.path(),
+        DatabaseOptions {
+            max_table_size: Some(10),
+            prefix_extractor: Some(Arc::new(FixedPrefix(4))),
+            bloom_filter_size: BloomFilterSize::BitsPerKey(20.0),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+/// Writes one segment per tenant, then a few unflushed updates
+fn populate(db: &mut Database<&std::path::Path>) {
+    for tenant in TENANTS {
+        for i in 0..10 {
+            db.set(
+                format!("{}:{}", tenant, i).as_bytes(),
+                format!("value_{}", i).as_bytes(),
+            )
+            .unwrap();
+        }
+    }
+    db.set(b"bbbb:3", b"updated").unwrap();
+    db.delete(b"bbbb:4").unwrap();
+    db.set(b"bbbb:a", b"new").unwrap();
+}
+
+#[test]
+fn scan_prefix_merges_the_memtable_and_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = open(&temp_dir);
+    populate(&mut db);
+
+    let results = db.scan_prefix(b"bbbb:").unwrap();
+    let keys: Vec<_> = results
+        .iter()
+        .map(|(key, _)| String::from_utf8_lossy(key).into_owned())
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            "bbbb:0", "bbbb:1", "bbbb:2", "bbbb:3", "bbbb:5", "bbbb:6", "bbbb:7", "bbbb:8",
+            "bbbb:9", "bbbb:a"
+        ]
+    );
+    assert_eq!(results[3].1, b"updated");
+    assert!(db.scan_prefix(b"dddd").unwrap().is_empty());
+}
+
+#[test]
+fn scan_prefix_skips_segments_ruled_out_by_their_filter() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = open(&temp_dir);
+    populate(&mut db);
+
+    // Segments of other tenants are never opened, so removing them goes unnoticed
+    for entry in std::fs::read_dir(temp_dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "sst")
+            && !std::fs::read(&path).unwrap().starts_with(b"bbbb")
+        {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    assert_eq!(db.scan_prefix(b"bbbb").unwrap().len(), 10);
+}
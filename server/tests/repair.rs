@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+use server::database::{Database, LOST_DIRECTORY_NAME};
+use tempfile::TempDir;
+
+fn populate(temp_dir: &TempDir) {
+    let mut db = Database::new(temp_dir.path(), Some(500)).unwrap();
+    for i in 0..1500 {
+        db.set(
+            format!("key_{:04}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+}
+
+fn files_with_extension(directory: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn missing_side_files_are_rebuilt() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+    for extension in ["idx", "bf", "props"] {
+        for path in files_with_extension(temp_dir.path(), extension) {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    let report = Database::repair(temp_dir.path()).unwrap();
+    assert!(report.lost_files.is_empty());
+    assert!(report.salvaged_segments.is_empty());
+    assert_eq!(files_with_extension(temp_dir.path(), "idx").len(), 3);
+    assert_eq!(files_with_extension(temp_dir.path(), "bf").len(), 3);
+    assert_eq!(files_with_extension(temp_dir.path(), "props").len(), 3);
+
+    let db = Database::new(temp_dir.path(), Some(500)).unwrap();
+    assert!(db.verify().is_healthy(), "{:?}", db.verify());
+    for i in 0..1500 {
+        assert_eq!(
+            db.get(format!("key_{:04}", i).as_bytes()).unwrap(),
+            Some(format!("value_{}", i).into_bytes())
+        );
+    }
+}
+
+#[test]
+fn damaged_segments_are_salvaged_and_quarantined() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+    let segment = files_with_extension(temp_dir.path(), "sst")
+        .into_iter()
+        .next()
+        .unwrap();
+    // Cut the segment off in the middle of a record
+    let data = std::fs::read(&segment).unwrap();
+    std::fs::write(&segment, &data[..data.len() / 2 + 3]).unwrap();
+
+    let report = Database::repair(temp_dir.path()).unwrap();
+    assert_eq!(report.salvaged_segments, vec![segment.clone()]);
+    assert_eq!(report.lost_files, vec![segment.clone()]);
+    let lost = temp_dir
+        .path()
+        .join(LOST_DIRECTORY_NAME)
+        .join(segment.file_name().unwrap());
+    assert_eq!(std::fs::read(lost).unwrap(), &data[..data.len() / 2 + 3]);
+
+    let db = Database::new(temp_dir.path(), Some(500)).unwrap();
+    assert!(db.verify().is_healthy(), "{:?}", db.verify());
+    let found = (0..1500)
+        .filter(|i| {
+            db.get(format!("key_{:04}", i).as_bytes())
+                .unwrap()
+                .is_some()
+        })
+        .count();
+    // Only the records after the cut are lost
+    assert!(found > 1000 && found < 1500, "{}", found);
+}
+
+#[test]
+fn healthy_directories_are_left_alone() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+    let before = files_with_extension(temp_dir.path(), "sst");
+
+    let report = Database::repair(temp_dir.path()).unwrap();
+    assert!(report.rebuilt_files.is_empty(), "{:?}", report);
+    assert!(report.lost_files.is_empty());
+    assert_eq!(files_with_extension(temp_dir.path(), "sst"), before);
+}
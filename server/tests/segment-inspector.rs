@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use server::database::{Database, DatabaseOptions, RecordValue, SegmentInspector};
+use tempfile::TempDir;
+
+fn first_segment(temp_dir: &TempDir) -> PathBuf {
+    let mut segments: Vec<PathBuf> = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sst"))
+        .collect();
+    segments.sort();
+    segments.remove(0)
+}
+
+#[test]
+fn inspector_reads_segment_and_side_files() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::new(temp_dir.path(), Some(300)).unwrap();
+        for i in 0..300 {
+            db.set(
+                format!("key_{:04}", i).as_bytes(),
+                format!("value_{}", i).as_bytes(),
+            )
+            .unwrap();
+        }
+    }
+
+    let inspector = SegmentInspector::open(first_segment(&temp_dir)).unwrap();
+    let records = inspector.records().unwrap();
+    assert_eq!(records.len(), 300);
+    assert_eq!(records[0].offset, 0);
+    assert_eq!(records[0].key, b"key_0000");
+    assert_eq!(records[0].value, RecordValue::Inline(b"value_0".to_vec()));
+
+    let properties = inspector.properties().unwrap().unwrap();
+    assert_eq!(properties.num_entries, 300);
+    assert_eq!(properties.min_key, b"key_0000");
+    assert_eq!(properties.max_key, b"key_0299");
+
+    let filter = inspector.filter().unwrap().unwrap();
+    assert_eq!(filter.kind, "bloom");
+    let fill_ratio = filter.fill_ratio.unwrap();
+    assert!(fill_ratio > 0.3 && fill_ratio < 0.7, "{}", fill_ratio);
+    assert_eq!(inspector.file_sizes().unwrap().len(), 4);
+
+    // The index only holds every hundredth record, so most lookups scan part of a block
+    let record = inspector.get(b"key_0150").unwrap().unwrap();
+    assert_eq!(record.value, RecordValue::Inline(b"value_150".to_vec()));
+    assert_eq!(
+        record.offset,
+        records
+            .iter()
+            .find(|r| r.key == b"key_0150")
+            .unwrap()
+            .offset
+    );
+    assert!(inspector.get(b"key_9999").unwrap().is_none());
+}
+
+#[test]
+fn inspector_reports_tombstones_and_value_pointers() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::open(
+            temp_dir.path(),
+            DatabaseOptions {
+                max_table_size: Some(3),
+                value_log_threshold: Some(100),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        db.set(b"big", &[b'x'; 200]).unwrap();
+        db.set(b"small", b"value").unwrap();
+        db.delete(b"gone").unwrap();
+    }
+
+    let inspector = SegmentInspector::open(first_segment(&temp_dir)).unwrap();
+    let values: Vec<RecordValue> = inspector
+        .records()
+        .unwrap()
+        .into_iter()
+        .map(|record| record.value)
+        .collect();
+    assert!(matches!(values[0], RecordValue::Pointer { len: 200, .. }));
+    assert_eq!(values[1], RecordValue::Tombstone);
+    assert_eq!(values[2], RecordValue::Inline(b"value".to_vec()));
+}
+
+#[test]
+fn inspector_rejects_files_that_are_not_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    assert!(SegmentInspector::open(temp_dir.path().join("segment_1.idx")).is_err());
+    assert!(SegmentInspector::open(temp_dir.path().join("segment_1.sst")).is_err());
+}
@@ -0,0 +1,57 @@
+use server::database::Database;
+use tempfile::TempDir;
+
+fn file_names(directory: &std::path::Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn flush_leaves_no_temporary_files_behind() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::new(temp_dir.path(), Some(3)).unwrap();
+    for i in 0..3 {
+        db.set(format!("key{}", i).as_bytes(), b"value").unwrap();
+    }
+
+    assert!(
+        file_names(temp_dir.path())
+            .iter()
+            .all(|name| !name.ends_with(".tmp"))
+    );
+}
+
+#[test]
+fn orphaned_temporary_files_are_removed_on_open() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::new(temp_dir.path(), Some(3)).unwrap();
+        for i in 0..3 {
+            db.set(format!("key{}", i).as_bytes(), b"value").unwrap();
+        }
+    }
+
+    // Partially written outputs of a flush that crashed before they were renamed into place
+    std::fs::write(temp_dir.path().join("segment_7.sst.tmp"), b"key9 val").unwrap();
+    std::fs::write(temp_dir.path().join("segment_7.idx.tmp"), b"key").unwrap();
+    std::fs::write(temp_dir.path().join("MANIFEST.tmp"), b"next_").unwrap();
+
+    let db = Database::new(temp_dir.path(), Some(3)).unwrap();
+    assert_eq!(
+        file_names(temp_dir.path()),
+        vec![
+            "MANIFEST",
+            "segment_1.bf",
+            "segment_1.idx",
+            "segment_1.props",
+            "segment_1.sst",
+            "wal_3.log"
+        ]
+    );
+    assert_eq!(db.get(b"key1").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get(b"key9").unwrap(), None);
+}
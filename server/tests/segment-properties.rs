@@ -0,0 +1,53 @@
+use server::database::Database;
+use tempfile::TempDir;
+
+fn remove_files_with_extension(temp_dir: &TempDir, extension: &str) {
+    for entry in std::fs::read_dir(temp_dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == extension) {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
+
+/// Writes the "b" keys to an older segment and the "a" keys to a newer one
+fn populate(temp_dir: &TempDir) {
+    let mut db = Database::new(temp_dir.path(), Some(100)).unwrap();
+    for prefix in ["b", "a"] {
+        for i in 0..100 {
+            db.set(
+                format!("{}_{:03}", prefix, i).as_bytes(),
+                format!("value_{}", i).as_bytes(),
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[test]
+fn reads_skip_segments_whose_key_range_excludes_the_key() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+    // Without filters only the key ranges keep the newer segment from being read
+    remove_files_with_extension(&temp_dir, "bf");
+
+    let db = Database::new(temp_dir.path(), Some(100)).unwrap();
+    assert_eq!(db.get(b"b_050").unwrap(), Some(b"value_50".to_vec()));
+    assert_eq!(db.block_cache().misses(), 1);
+
+    assert_eq!(db.get(b"c").unwrap(), None);
+    assert_eq!(db.block_cache().misses(), 1);
+}
+
+#[test]
+fn segments_without_properties_are_still_read() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+    remove_files_with_extension(&temp_dir, "bf");
+    remove_files_with_extension(&temp_dir, "props");
+
+    let db = Database::new(temp_dir.path(), Some(100)).unwrap();
+    assert_eq!(db.get(b"b_050").unwrap(), Some(b"value_50".to_vec()));
+    assert_eq!(db.get(b"a_099").unwrap(), Some(b"value_99".to_vec()));
+    assert_eq!(db.scan_prefix(b"b_09").unwrap().len(), 10);
+}
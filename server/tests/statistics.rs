@@ -0,0 +1,78 @@
+use server::database::Database;
+use tempfile::TempDir;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:04}", i).into_bytes()
+}
+
+#[test]
+fn stats_describe_the_memtable_segments_and_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::new(temp_dir.path(), Some(100)).unwrap();
+    for i in 0..250 {
+        db.set(&key(i), b"value").unwrap();
+    }
+
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.mem_table_entries, 50);
+    assert_eq!(stats.mem_table_bytes, 50 * 13);
+    assert_eq!(stats.segments_per_level, vec![2, 0]);
+    assert_eq!(stats.flushes, 2);
+    assert_eq!(stats.compactions, 0);
+    assert_eq!(stats.user_bytes_written, 250 * 13);
+    // Each WAL record adds a separator and a newline
+    assert_eq!(stats.wal_bytes_written, 250 * 15);
+    assert!(stats.flush_bytes_written > 0);
+    for file_type in ["sst", "idx", "bf", "props", "log", "MANIFEST"] {
+        assert!(stats.disk_bytes[file_type] > 0, "{}", file_type);
+    }
+    assert!(stats.write_amplification().unwrap() > 1.0);
+}
+
+#[test]
+fn stats_count_compactions_and_filter_checks() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::new(temp_dir.path(), Some(100)).unwrap();
+    for i in 0..400 {
+        db.set(&key(i), b"value").unwrap();
+    }
+
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.compactions, 1);
+    assert_eq!(stats.segments_per_level, vec![0, 1]);
+    assert!(stats.compaction_bytes_read > 0);
+    assert!(stats.compaction_bytes_written > 0);
+
+    // Both keys fall inside the key range of the single segment, so its filter is checked
+    assert_eq!(db.get(b"key_0000x").unwrap(), None);
+    assert_eq!(db.get(&key(10)).unwrap(), Some(b"value".to_vec()));
+    let stats = db.stats().unwrap();
+    assert_eq!(stats.filter_checks, 2);
+    assert!(stats.filter_useful <= 1);
+    assert!(stats.block_cache_hit_rate().is_some());
+}
+
+#[test]
+fn properties_expose_single_statistics_by_name() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::new(temp_dir.path(), Some(100)).unwrap();
+    for i in 0..150 {
+        db.set(&key(i), b"value").unwrap();
+    }
+
+    assert_eq!(
+        db.property("mem-table-entries").unwrap(),
+        Some("50".to_string())
+    );
+    assert_eq!(
+        db.property("segments-at-level0").unwrap(),
+        Some("1".to_string())
+    );
+    assert_eq!(db.property("flushes").unwrap(), Some("1".to_string()));
+    assert!(db.property("write-amplification").unwrap().is_some());
+    assert_eq!(db.property("no-such-property").unwrap(), None);
+
+    let all = db.property("stats").unwrap().unwrap();
+    assert!(all.lines().any(|line| line == "segments-at-level1 0"));
+    assert!(all.lines().any(|line| line.starts_with("disk-bytes-sst ")));
+}
@@ -0,0 +1,60 @@
+use server::database::{Database, DatabaseOptions};
+use tempfile::TempDir;
+
+fn populate(db: &mut Database<&std::path::Path>) {
+    for i in 0..3000 {
+        db.set(
+            format!("key_{:04}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn reads_succeed_with_fewer_open_tables_than_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(
+        temp_dir.path(),
+        DatabaseOptions {
+            max_table_size: Some(500),
+            table_cache_capacity: Some(1),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    populate(&mut db);
+
+    for i in (0..3000).step_by(13) {
+        assert_eq!(
+            db.get(format!("key_{:04}", i).as_bytes()).unwrap(),
+            Some(format!("value_{}", i).into_bytes())
+        );
+    }
+    assert_eq!(db.get(b"key_9999").unwrap(), None);
+    assert_eq!(db.get(b"a").unwrap(), None);
+}
+
+#[test]
+fn missing_index_files_are_rebuilt_from_the_segment() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::new(temp_dir.path(), Some(1000)).unwrap();
+        populate(&mut db);
+    }
+
+    for entry in std::fs::read_dir(temp_dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "idx") {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    let db = Database::new(temp_dir.path(), Some(1000)).unwrap();
+    for i in (0..3000).step_by(11) {
+        assert_eq!(
+            db.get(format!("key_{:04}", i).as_bytes()).unwrap(),
+            Some(format!("value_{}", i).into_bytes())
+        );
+    }
+}
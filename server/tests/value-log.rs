@@ -0,0 +1,151 @@
+use server::database::{Database, DatabaseOptions};
+use tempfile::TempDir;
+
+fn options() -> DatabaseOptions {
+    DatabaseOptions {
+        max_table_size: Some(50),
+        value_log_threshold: Some(256),
+        value_log_file_size: Some(64 * 1024),
+        ..Default::default()
+    }
+}
+
+fn files_with_extension(temp_dir: &TempDir, extension: &str) -> Vec<std::path::PathBuf> {
+    std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect()
+}
+
+fn total_size(paths: &[std::path::PathBuf]) -> u64 {
+    paths
+        .iter()
+        .map(|path| std::fs::metadata(path).unwrap().len())
+        .sum()
+}
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:03}", i).into_bytes()
+}
+
+fn large_value(i: usize, round: usize) -> Vec<u8> {
+    format!("{}_{}_", i, round).repeat(100).into_bytes()
+}
+
+fn write_round(db: &mut Database<&std::path::Path>, round: usize) {
+    for i in 0..200 {
+        db.set(&key(i), &large_value(i, round)).unwrap();
+    }
+}
+
+fn assert_round(db: &Database<&std::path::Path>, round: usize) {
+    for i in (0..200).step_by(9) {
+        assert_eq!(db.get(&key(i)).unwrap(), Some(large_value(i, round)));
+    }
+    assert_eq!(
+        &*db.get_pinned(&key(10)).unwrap().unwrap(),
+        large_value(10, round).as_slice()
+    );
+    assert_eq!(
+        db.multi_get(&[key(3).as_slice(), b"missing", key(150).as_slice()])
+            .unwrap(),
+        vec![
+            Some(large_value(3, round)),
+            None,
+            Some(large_value(150, round))
+        ]
+    );
+    assert_eq!(
+        db.scan_prefix(b"key_01").unwrap(),
+        (10..20)
+            .map(|i| (key(i), large_value(i, round)))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn large_values_are_kept_out_of_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::open(temp_dir.path(), options()).unwrap();
+        write_round(&mut db, 0);
+        db.set(b"small", b"inline").unwrap();
+        assert_round(&db, 0);
+    }
+
+    let segments = files_with_extension(&temp_dir, "sst");
+    let value_logs = files_with_extension(&temp_dir, "vlog");
+    assert!(!value_logs.is_empty());
+    assert!(total_size(&segments) * 10 < total_size(&value_logs));
+
+    let db = Database::open(temp_dir.path(), options()).unwrap();
+    assert_round(&db, 0);
+    assert_eq!(db.get(b"small").unwrap(), Some(b"inline".to_vec()));
+}
+
+#[test]
+fn garbage_collection_reclaims_overwritten_values() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(temp_dir.path(), options()).unwrap();
+    write_round(&mut db, 0);
+    write_round(&mut db, 1);
+    // Only a few keys keep their value from the first round
+    for i in 0..150 {
+        db.set(&key(i), &large_value(i, 2)).unwrap();
+    }
+    let size_before = total_size(&files_with_extension(&temp_dir, "vlog"));
+
+    let mut reclaimed = 0;
+    while reclaimed < 100 && db.collect_value_log_garbage().unwrap() {
+        reclaimed += 1;
+    }
+    assert!(reclaimed > 0);
+    assert!(total_size(&files_with_extension(&temp_dir, "vlog")) < size_before);
+
+    for i in 0..200 {
+        let round = if i < 150 { 2 } else { 1 };
+        assert_eq!(db.get(&key(i)).unwrap(), Some(large_value(i, round)));
+    }
+    drop(db);
+
+    let db = Database::open(temp_dir.path(), options()).unwrap();
+    assert_eq!(db.get(&key(199)).unwrap(), Some(large_value(199, 1)));
+    assert_eq!(db.get(&key(0)).unwrap(), Some(large_value(0, 2)));
+}
+
+#[test]
+fn values_that_look_like_pointers_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::new(temp_dir.path(), Some(2)).unwrap();
+        db.set(b"a", b"\x001 2 3").unwrap();
+        db.set(b"b", b"plain").unwrap();
+    }
+
+    let db = Database::new(temp_dir.path(), Some(2)).unwrap();
+    assert_eq!(db.get(b"a").unwrap(), Some(b"\x001 2 3".to_vec()));
+    assert_eq!(db.get(b"b").unwrap(), Some(b"plain".to_vec()));
+}
+
+#[test]
+fn value_log_files_the_manifest_does_not_cover_are_removed_on_open() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::open(temp_dir.path(), options()).unwrap();
+        write_round(&mut db, 0);
+    }
+    let mut value_logs = files_with_extension(&temp_dir, "vlog");
+    value_logs.sort();
+
+    // Started by a flush that crashed before its segment was recorded in the manifest
+    let orphan = temp_dir.path().join("value_1000.vlog");
+    std::fs::write(&orphan, large_value(0, 1)).unwrap();
+
+    let db = Database::open(temp_dir.path(), options()).unwrap();
+    assert!(!orphan.exists());
+    let mut remaining = files_with_extension(&temp_dir, "vlog");
+    remaining.sort();
+    assert_eq!(remaining, value_logs);
+    assert_round(&db, 0);
+}
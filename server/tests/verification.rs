@@ -0,0 +1,106 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use server::database::{Database, Scrubber};
+use tempfile::TempDir;
+
+fn populate(temp_dir: &TempDir) {
+    let mut db = Database::new(temp_dir.path(), Some(500)).unwrap();
+    for i in 0..1500 {
+        db.set(
+            format!("key_{:04}", i).as_bytes(),
+            format!("value_{}", i).as_bytes(),
+        )
+        .unwrap();
+    }
+}
+
+fn corrupt(temp_dir: &TempDir, extension: &str) -> std::path::PathBuf {
+    let path = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == extension))
+        .unwrap();
+    let mut data = std::fs::read(&path).unwrap();
+    let middle = data.len() / 2;
+    data[middle] ^= 0x01;
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn healthy_directories_pass_verification() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+
+    let db = Database::new(temp_dir.path(), Some(500)).unwrap();
+    let report = db.verify();
+    assert!(report.is_healthy(), "{:?}", report);
+    // A segment, an index and a filter for each of the three flushes
+    assert_eq!(report.files.len(), 9);
+}
+
+#[test]
+fn corrupted_files_are_reported() {
+    for extension in ["sst", "idx", "bf"] {
+        let temp_dir = TempDir::new().unwrap();
+        populate(&temp_dir);
+        let corrupted = corrupt(&temp_dir, extension);
+
+        let db = Database::new(temp_dir.path(), Some(500)).unwrap();
+        let report = db.verify();
+        let corrupted_report = report
+            .files
+            .iter()
+            .find(|file| file.path == corrupted)
+            .unwrap();
+        assert!(
+            corrupted_report
+                .problems
+                .contains(&"checksum mismatch".to_string())
+        );
+        // A corrupted segment can also leave its index or filter disagreeing with it, but
+        // the files of other segments are unaffected
+        assert!(
+            report
+                .unhealthy_files()
+                .all(|file| file.path.file_stem() == corrupted.file_stem()),
+            "{:?}",
+            report
+        );
+    }
+}
+
+#[test]
+fn scrubber_verifies_segments_in_the_background() {
+    let temp_dir = TempDir::new().unwrap();
+    populate(&temp_dir);
+    let corrupted = corrupt(&temp_dir, "sst");
+
+    let db = Arc::new(Mutex::new(
+        Database::new(temp_dir.path().to_path_buf(), Some(500)).unwrap(),
+    ));
+    let scrubber = Scrubber::start(Arc::clone(&db), Duration::from_secs(3600)).unwrap();
+
+    let started = Instant::now();
+    let report = loop {
+        if let Some(report) = scrubber.last_report() {
+            break report;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(report.files.len(), 9);
+    assert!(!report.is_healthy());
+    assert!(
+        report
+            .unhealthy_files()
+            .all(|file| file.path.file_stem() == corrupted.file_stem())
+    );
+
+    // Writes keep going while the scrubber is idle, and dropping it stops it
+    db.lock().unwrap().set(b"key", b"value").unwrap();
+    drop(scrubber);
+}
@@ -0,0 +1,93 @@
+use server::database::{Database, DatabaseOptions};
+use tempfile::TempDir;
+
+#[test]
+fn unflushed_writes_are_replayed_on_reopen() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let mut db = Database::new(temp_dir.path(), Some(5)).unwrap();
+        for i in 0..7 {
+            db.set(format!("key{}", i).as_bytes(), b"value").unwrap();
+        }
+        db.delete(b"key6").unwrap();
+    }
+
+    let db = Database::new(temp_dir.path(), Some(5)).unwrap();
+    assert_eq!(db.get(b"key0").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get(b"key5").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get(b"key6").unwrap(), None);
+}
+
+#[test]
+fn flushed_wal_left_behind_by_a_crash_is_not_replayed() {
+    let temp_dir = TempDir::new().unwrap();
+    let stale_wal = temp_dir.path().join("wal_0.log");
+    {
+        let mut db = Database::new(temp_dir.path(), Some(2)).unwrap();
+        db.set(b"key1", b"old").unwrap();
+        let wal_contents = std::fs::read(&stale_wal).unwrap();
+        db.set(b"key2", b"old").unwrap();
+        db.set(b"key1", b"new").unwrap();
+
+        // Pretend the process died after the manifest was written but before the
+        // flushed WAL could be removed
+        assert!(!stale_wal.exists());
+        std::fs::write(&stale_wal, wal_contents).unwrap();
+    }
+
+    let db = Database::new(temp_dir.path(), Some(2)).unwrap();
+    assert!(!stale_wal.exists());
+    assert_eq!(db.get(b"key1").unwrap(), Some(b"new".to_vec()));
+    assert_eq!(db.get(b"key2").unwrap(), Some(b"old".to_vec()));
+}
+
+#[test]
+fn retired_wals_are_archived_up_to_the_limit() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut db = Database::open(
+        temp_dir.path(),
+        DatabaseOptions {
+            max_table_size: Some(2),
+            wal_archive_limit: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    for i in 0..10 {
+        db.set(format!("key{}", i).as_bytes(), b"value").unwrap();
+    }
+
+    let mut archived = std::fs::read_dir(temp_dir.path().join("archive"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    archived.sort();
+
+    // Five flushes each retire one WAL; WAL and segment numbers are interleaved
+    assert_eq!(archived, vec!["wal_6.log", "wal_8.log"]);
+}
+
+#[test]
+fn writes_after_reopen_do_not_follow_a_torn_record() {
+    let temp_dir = TempDir::new().unwrap();
+    let wal = temp_dir.path().join("wal_0.log");
+    {
+        let mut db = Database::new(temp_dir.path(), Some(100)).unwrap();
+        db.set(b"key1", b"value").unwrap();
+    }
+    // A crash cut the last record short before its newline
+    let mut contents = std::fs::read(&wal).unwrap();
+    contents.extend_from_slice(b"key2 tor");
+    std::fs::write(&wal, &contents).unwrap();
+
+    {
+        let mut db = Database::new(temp_dir.path(), Some(100)).unwrap();
+        db.set(b"key3", b"value").unwrap();
+    }
+    assert_eq!(std::fs::read(&wal).unwrap(), contents);
+
+    let db = Database::new(temp_dir.path(), Some(100)).unwrap();
+    assert_eq!(db.get(b"key1").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get(b"key3").unwrap(), Some(b"value".to_vec()));
+}